@@ -1,25 +1,21 @@
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use criterion::measurement::WallTime;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkGroup, Criterion};
 use rdb_fs::fromstr::FromStr;
 use rdb_fs::{
-    File, FileDB, FileQuery, HashTags2DBFS, HashTagsDBFS, NaiveDBFS, TagSet, TagTreeDBFS,
+    AddFileError, File, FileDB, FileQuery, FileReader, Format, HashTags2DBFS, HashTagsDBFS, NaiveDBFS, Query, QueryMix, TagSet, TagTreeDBFS,
+    TagsPerFile, Workload,
 };
 use std::fs::File as OSFile;
-use serde_yaml::from_reader;
+use std::io::BufReader;
 use std::collections::hash_set::HashSet;
 
-pub fn load_file_names(file_name: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
-    let file = OSFile::open(file_name)?;
-    let conf: Vec<String> = from_reader(file)?;
-    Ok(conf)
-}
-
-
-fn file_list_from_iter_str(items: impl IntoIterator<Item=impl AsRef<str>>) -> HashSet<File>
-{
-    items
-        .into_iter()
-        .map(|s| File::from_str(s.as_ref()).unwrap())
-        .collect()
+pub fn load_files(file_name: &str) -> Result<HashSet<File>, Box<dyn std::error::Error>> {
+    let input = BufReader::new(OSFile::open(file_name)?);
+    let mut files = HashSet::new();
+    for file in FileReader::new(input, Format::Yaml) {
+        files.insert(file.map_err(|e| format!("{:?}", e))?);
+    }
+    Ok(files)
 }
 
 fn query_list_from_iter_str<'a, I>(items: I) -> HashSet<TagSet>
@@ -32,7 +28,7 @@ where
         .collect()
 }
 
-fn add_files_to_db<'a, DB, I>(db: &mut DB, files: I) -> Option<()>
+fn add_files_to_db<DB, I>(db: &mut DB, files: I) -> Result<(), AddFileError>
 where
     DB: FileDB,
     I: IntoIterator<Item = File>,
@@ -40,10 +36,10 @@ where
     for f in files.into_iter() {
         db.add_file(&f)?;
     }
-    Some(())
+    Ok(())
 }
 
-fn query_files_in_db<'a, DB, I, FQ>(db: &DB, queries: I) -> Option<()>
+fn query_files_in_db<DB, I, FQ>(db: &DB, queries: I) -> Option<()>
 where
     DB: FileDB,
     I: IntoIterator<Item = FQ>,
@@ -63,16 +59,15 @@ where
 }
 
 pub fn criterion_benchmark_add(c: &mut Criterion) {
-    let file_names = load_file_names("test_files2.yml").unwrap();
-    let files = file_list_from_iter_str(file_names.into_iter());
+    let files = load_files("data/test_files2.yml").unwrap();
 
     c.bench_function("naive_add_files", |b| {
-        b.iter(|| assert_eq!(Some(()), add_files_to_db(black_box(&mut NaiveDBFS::new()), black_box(files.clone()))))
+        b.iter(|| assert_eq!(Ok(()), add_files_to_db(black_box(&mut NaiveDBFS::new()), black_box(files.clone()))))
     });
 
     c.bench_function("hashtags_add_files", |b| {
         b.iter(|| {
-            assert_eq!(Some(()), add_files_to_db(
+            assert_eq!(Ok(()), add_files_to_db(
                 black_box(&mut HashTagsDBFS::new()),
                 black_box(files.clone()),
             ))
@@ -81,7 +76,7 @@ pub fn criterion_benchmark_add(c: &mut Criterion) {
 
     c.bench_function("hashtags2_add_files", |b| {
         b.iter(|| {
-            assert_eq!(Some(()), add_files_to_db(
+            assert_eq!(Ok(()), add_files_to_db(
                 black_box(&mut HashTags2DBFS::new()),
                 black_box(files.clone()),
             ))
@@ -89,25 +84,24 @@ pub fn criterion_benchmark_add(c: &mut Criterion) {
     });
 
     c.bench_function("tagtree_add_files", |b| {
-        b.iter(|| assert_eq!(Some(()), add_files_to_db(black_box(&mut TagTreeDBFS::new()), black_box(files.clone()))))
+        b.iter(|| assert_eq!(Ok(()), add_files_to_db(black_box(&mut TagTreeDBFS::new()), black_box(files.clone()))))
     });
 }
 
 pub fn criterion_benchmark_search(c: &mut Criterion) {
-    let file_names = load_file_names("test_files2.yml").unwrap();
-    let files = file_list_from_iter_str(file_names.into_iter());
+    let files = load_files("data/test_files2.yml").unwrap();
 
     let mut naive = NaiveDBFS::new();
-    add_files_to_db(&mut naive, files.clone());
+    let _ = add_files_to_db(&mut naive, files.clone());
 
     let mut hashtags = HashTagsDBFS::new();
-    add_files_to_db(&mut hashtags, files.clone());
+    let _ = add_files_to_db(&mut hashtags, files.clone());
 
     let mut hashtags2 = HashTags2DBFS::new();
-    add_files_to_db(&mut hashtags2, files.clone());
+    let _ = add_files_to_db(&mut hashtags2, files.clone());
 
     let mut tagtree = TagTreeDBFS::new();
-    add_files_to_db(&mut tagtree, files.clone());
+    let _ = add_files_to_db(&mut tagtree, files.clone());
 
     let queries = query_list_from_iter_str(["/home/luke/.cache", "/home/luke/Downloads"]);
 
@@ -128,5 +122,55 @@ pub fn criterion_benchmark_search(c: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, criterion_benchmark_add, criterion_benchmark_search);
+// Synthetic datasets of different shapes, to see how each backend copes
+// with them.
+fn workload_shapes() -> Vec<(&'static str, Workload)> {
+    vec![
+        ("flat", Workload::new(5000).with_zipf_exponent(0.0)),
+        ("skewed", Workload::new(5000).with_zipf_exponent(1.2)),
+        ("wide_vocabulary", Workload::new(5000).with_vocabulary(2000)),
+        ("many_tags", Workload::new(5000).with_tags_per_file(TagsPerFile::Uniform(8, 16))),
+        ("colliding_names", Workload::new(5000).with_name_collisions(0.5)),
+    ]
+}
+
+fn bench_workload<DB: FileDB>(
+    group: &mut BenchmarkGroup<WallTime>,
+    backend: &str,
+    new_db: fn() -> DB,
+    files: &[File],
+    queries: &[Query],
+) {
+    group.bench_function(format!("{}_add_files", backend), |b| {
+        b.iter(|| assert_eq!(Ok(()), add_files_to_db(black_box(&mut new_db()), black_box(files.to_vec()))))
+    });
+
+    let mut db = new_db();
+    assert_eq!(Ok(()), add_files_to_db(&mut db, files.to_vec()));
+    group.bench_function(format!("{}_get_files", backend), |b| {
+        b.iter(|| query_files_in_db(black_box(&db), black_box(queries.iter())))
+    });
+}
+
+pub fn criterion_benchmark_workloads(c: &mut Criterion) {
+    for (shape, workload) in workload_shapes() {
+        let files = workload.generate_files();
+        let queries = workload.generate_queries(&files, 100, &QueryMix::default());
+
+        let mut group = c.benchmark_group(format!("workload_{}", shape));
+        group.sample_size(10);
+        bench_workload(&mut group, "naive", NaiveDBFS::new, &files, &queries);
+        bench_workload(&mut group, "hashtags", HashTagsDBFS::new, &files, &queries);
+        bench_workload(&mut group, "hashtags2", HashTags2DBFS::new, &files, &queries);
+        bench_workload(&mut group, "tagtree", TagTreeDBFS::new, &files, &queries);
+        group.finish();
+    }
+}
+
+criterion_group!(
+    benches,
+    criterion_benchmark_add,
+    criterion_benchmark_search,
+    criterion_benchmark_workloads
+);
 criterion_main!(benches);
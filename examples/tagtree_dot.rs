@@ -0,0 +1,68 @@
+// Build a TagTreeDBFS from a list of paths, or any other format import
+// reads, and print it as Graphviz DOT.
+//
+//   cargo run --example tagtree_dot -- data/test_files.yml --depth 3 | dot -Tsvg > tree.svg
+//
+// Options: --depth N draws branches N deep as summaries, --collapse N
+// summarises any subtree of more than N files.
+
+use rdb_fs::{DotOptions, FileDB, FileReader, Format, TagTreeDBFS};
+use std::fs::File as OSFile;
+use std::io::{self, BufReader, Write};
+use std::process::exit;
+
+fn usage() -> ! {
+    eprintln!("usage: tagtree_dot [FILES] [--depth N] [--collapse N]");
+    exit(2)
+}
+
+fn number(arg: Option<String>) -> usize {
+    match arg.and_then(|a| a.parse().ok()) {
+        Some(n) => n,
+        None => usage(),
+    }
+}
+
+fn main() {
+    let mut paths = "data/test_files.yml".to_string();
+    let mut options = DotOptions::new();
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--depth" => options = options.with_max_depth(number(args.next())),
+            "--collapse" => options = options.collapse_above(number(args.next())),
+            _ if arg.starts_with("--") => usage(),
+            _ => paths = arg,
+        }
+    }
+
+    let input = match OSFile::open(&paths) {
+        Ok(f) => BufReader::new(f),
+        Err(e) => {
+            eprintln!("can't read {}: {}", paths, e);
+            exit(1)
+        }
+    };
+
+    let mut db = TagTreeDBFS::new();
+    let format = Format::from_path(&paths).unwrap_or(Format::Yaml);
+    for file in FileReader::new(input, format) {
+        match file {
+            // Repeated paths are skipped, as they would be by a filesystem.
+            Ok(file) => {
+                let _ = db.add_file(&file);
+            }
+            Err(e) => {
+                eprintln!("can't read {}: {:?}", paths, e);
+                exit(1)
+            }
+        }
+    }
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    if db.write_dot(&mut out, &options).and_then(|_| out.flush()).is_err() {
+        exit(1);
+    }
+}
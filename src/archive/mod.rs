@@ -0,0 +1,253 @@
+// Indexing the entries of tar, gzipped tar and zip archives as files, so
+// build artifacts can be searched without extracting them.
+//
+// An entry "dir/sub/name" becomes a file called "name" tagged with "dir",
+// "sub" and the archive's name, plus structured tags locating its content:
+// "size=" its length and "offset=" where its data starts, in the tar stream
+// after decompression for a gzipped tar. Compressed zip entries also have
+// "packed=" their stored length and "compression=" the method. read_entry
+// uses these to read the content back. A directory that looks like one of
+// these tags, e.g. "size=0", has its '=' written as "%3D", so locate can't
+// mistake it for the real one.
+
+mod tar;
+mod zip;
+
+use crate::keyvalue::split_tag;
+use crate::{AddFileError, File, FileDB, FileQuery, TagSet};
+use flate2::read::{DeflateDecoder, MultiGzDecoder};
+use std::fs;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+pub const SIZE_KEY: &str = "size";
+pub const OFFSET_KEY: &str = "offset";
+pub const PACKED_KEY: &str = "packed";
+pub const COMPRESSION_KEY: &str = "compression";
+
+const METADATA_KEYS: [&str; 4] = [SIZE_KEY, OFFSET_KEY, PACKED_KEY, COMPRESSION_KEY];
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum ArchiveKind {
+    Tar,
+    TarGz,
+    Zip,
+}
+
+impl ArchiveKind {
+    // Tell the kind of archive from its first bytes, leaving the reader at
+    // the start.
+    pub fn detect<R: Read + Seek>(input: &mut R) -> Result<ArchiveKind, ArchiveError> {
+        let mut start = vec![];
+        input.seek(SeekFrom::Start(0))?;
+        input.by_ref().take(512).read_to_end(&mut start)?;
+        input.seek(SeekFrom::Start(0))?;
+
+        if start.starts_with(&[0x1f, 0x8b]) {
+            Ok(ArchiveKind::TarGz)
+        } else if start.starts_with(b"PK\x03\x04") || start.starts_with(b"PK\x05\x06") {
+            Ok(ArchiveKind::Zip)
+        } else if start.len() == 512 && &start[257..262] == b"ustar" {
+            Ok(ArchiveKind::Tar)
+        } else {
+            Err(ArchiveError::UnknownFormat)
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Compression {
+    Stored,
+    Deflate,
+    // A zip method we can't decompress, by number.
+    Other(u16),
+}
+
+impl Compression {
+    fn name(&self) -> String {
+        match self {
+            Compression::Stored => "stored".to_string(),
+            Compression::Deflate => "deflate".to_string(),
+            Compression::Other(method) => format!("method{}", method),
+        }
+    }
+
+    fn from_name(name: &str) -> Compression {
+        match name {
+            "deflate" => Compression::Deflate,
+            "stored" => Compression::Stored,
+            _ => Compression::Other(
+                name.trim_start_matches("method").parse().unwrap_or(u16::MAX),
+            ),
+        }
+    }
+}
+
+// A regular file in an archive. Directories and links aren't listed.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct ArchiveEntry {
+    // As stored, e.g. "./src/main.rs".
+    pub path: String,
+    // The length of the content.
+    pub size: u64,
+    // Where the content starts.
+    pub offset: u64,
+    // The length of the content as stored.
+    pub packed_size: u64,
+    pub compression: Compression,
+}
+
+impl ArchiveEntry {
+    // The entry as a file, tagged with the archive's name. None if the path
+    // has no file name.
+    pub fn to_file(&self, archive: &str) -> Option<File> {
+        let mut parts: Vec<&str> = self
+            .path
+            .split('/')
+            .filter(|p| !p.is_empty() && *p != ".")
+            .collect();
+        let name = parts.pop()?;
+
+        let mut tags: TagSet = parts.into_iter().map(directory_tag).collect();
+        tags.insert(archive.to_string());
+        tags.insert(format!("{}={}", SIZE_KEY, self.size));
+        tags.insert(format!("{}={}", OFFSET_KEY, self.offset));
+        if self.compression != Compression::Stored {
+            tags.insert(format!("{}={}", PACKED_KEY, self.packed_size));
+            tags.insert(format!("{}={}", COMPRESSION_KEY, self.compression.name()));
+        }
+        Some(File::new(name.to_string(), tags))
+    }
+
+    // Where the content of a file made by to_file is, or None if it wasn't.
+    // Only the file's name is known, so that is all the path holds.
+    pub fn locate(file: &File) -> Option<ArchiveEntry> {
+        let value = |key: &str| {
+            file.tags()
+                .iter()
+                .filter_map(|t| split_tag(t))
+                .find(|(k, _)| *k == key)
+                .map(|(_, v)| v)
+        };
+        let size = value(SIZE_KEY)?.parse().ok()?;
+        let compression = value(COMPRESSION_KEY).map_or(Compression::Stored, Compression::from_name);
+        Some(ArchiveEntry {
+            path: file.name.clone(),
+            size,
+            offset: value(OFFSET_KEY)?.parse().ok()?,
+            packed_size: match value(PACKED_KEY) {
+                Some(packed) => packed.parse().ok()?,
+                None => size,
+            },
+            compression,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum ArchiveError {
+    Io(io::Error),
+    // Not a tar, gzipped tar or zip archive.
+    UnknownFormat,
+    // The archive is damaged or truncated.
+    Malformed(String),
+    // The file has no location in an archive, or its content is compressed
+    // in a way we can't read.
+    NotAnEntry(File),
+    Add(File, AddFileError),
+}
+
+impl From<io::Error> for ArchiveError {
+    fn from(error: io::Error) -> Self {
+        ArchiveError::Io(error)
+    }
+}
+
+// A directory of an entry's path as a tag, escaped if it would read as
+// one of the tags locating the content.
+fn directory_tag(part: &str) -> String {
+    match split_tag(part) {
+        Some((key, _)) if METADATA_KEYS.contains(&key) => part.replacen('=', "%3D", 1),
+        _ => part.to_string(),
+    }
+}
+
+// Every regular file in the archive, whatever its kind.
+pub fn read_entries<R: Read + Seek>(mut input: R) -> Result<Vec<ArchiveEntry>, ArchiveError> {
+    match ArchiveKind::detect(&mut input)? {
+        ArchiveKind::Tar => tar::entries(BufReader::new(input)),
+        ArchiveKind::TarGz => tar::entries(MultiGzDecoder::new(BufReader::new(input))),
+        ArchiveKind::Zip => zip::entries(input),
+    }
+}
+
+// Add every regular file in the archive to the DB, tagged with the given
+// archive name. Returns how many were added.
+pub fn index_archive<DB: FileDB, R: Read + Seek>(
+    db: &mut DB,
+    input: R,
+    archive: &str,
+) -> Result<usize, ArchiveError> {
+    let mut added = 0;
+    for entry in read_entries(input)? {
+        if let Some(file) = entry.to_file(archive) {
+            if let Err(error) = db.add_file(&file) {
+                return Err(ArchiveError::Add(file, error));
+            }
+            added += 1;
+        }
+    }
+    Ok(added)
+}
+
+// Index the archive at the path, tagging its files with its file name.
+pub fn index_archive_file<DB: FileDB, P: AsRef<Path>>(
+    db: &mut DB,
+    path: P,
+) -> Result<usize, ArchiveError> {
+    let path = path.as_ref();
+    let archive = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    index_archive(db, fs::File::open(path)?, &archive)
+}
+
+// Read the content of a file indexed from the archive.
+pub fn read_entry<R: Read + Seek>(mut input: R, file: &File) -> Result<Vec<u8>, ArchiveError> {
+    let entry = match ArchiveEntry::locate(file) {
+        Some(entry) => entry,
+        None => return Err(ArchiveError::NotAnEntry(file.clone())),
+    };
+
+    let packed: Box<dyn Read + '_> = match ArchiveKind::detect(&mut input)? {
+        ArchiveKind::TarGz => {
+            let mut tar = MultiGzDecoder::new(BufReader::new(input));
+            io::copy(&mut tar.by_ref().take(entry.offset), &mut io::sink())?;
+            Box::new(tar.take(entry.packed_size))
+        }
+        ArchiveKind::Tar | ArchiveKind::Zip => {
+            input.seek(SeekFrom::Start(entry.offset))?;
+            Box::new(input.take(entry.packed_size))
+        }
+    };
+
+    // The size comes from the archive, so don't trust it enough to allocate
+    // it up front; read one byte more to tell if the content is too long.
+    let limit = entry.size.saturating_add(1);
+    let mut content = vec![];
+    match entry.compression {
+        Compression::Stored => packed.take(limit).read_to_end(&mut content)?,
+        Compression::Deflate => DeflateDecoder::new(packed).take(limit).read_to_end(&mut content)?,
+        Compression::Other(_) => return Err(ArchiveError::NotAnEntry(file.clone())),
+    };
+    if content.len() as u64 != entry.size {
+        return Err(ArchiveError::Malformed(format!(
+            "expected {} bytes of {}, read {}",
+            entry.size,
+            file.name,
+            content.len()
+        )));
+    }
+    Ok(content)
+}
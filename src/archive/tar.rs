@@ -0,0 +1,226 @@
+// Tar headers, in the ustar layout, with the GNU long name and pax
+// extensions that tools use for long paths and large files.
+
+use super::{ArchiveEntry, ArchiveError, Compression};
+use std::io::{self, Read};
+
+const BLOCK: u64 = 512;
+
+// Counts the bytes read, so entries know where their data starts.
+struct Counting<R> {
+    inner: R,
+    position: u64,
+}
+
+impl<R: Read> Read for Counting<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R: Read> Counting<R> {
+    fn skip(&mut self, n: u64) -> Result<(), ArchiveError> {
+        let skipped = io::copy(&mut self.by_ref().take(n), &mut io::sink())?;
+        if skipped < n {
+            Err(ArchiveError::Malformed("truncated entry".to_string()))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn read_data(&mut self, n: u64) -> Result<Vec<u8>, ArchiveError> {
+        let mut data = vec![];
+        self.by_ref().take(n).read_to_end(&mut data)?;
+        if (data.len() as u64) < n {
+            return Err(ArchiveError::Malformed("truncated entry".to_string()));
+        }
+        self.skip(padding(n))?;
+        Ok(data)
+    }
+}
+
+fn padding(size: u64) -> u64 {
+    (BLOCK - size % BLOCK) % BLOCK
+}
+
+// A NUL terminated string field.
+fn text(field: &[u8]) -> String {
+    let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+// A number field, in octal or, for large values, base 256.
+fn number(field: &[u8]) -> Result<u64, ArchiveError> {
+    if field[0] & 0x80 != 0 {
+        let mut n: u64 = (field[0] & 0x7f) as u64;
+        for &b in &field[1..] {
+            n = n
+                .checked_mul(256)
+                .ok_or_else(|| ArchiveError::Malformed("number too large".to_string()))?
+                + b as u64;
+        }
+        return Ok(n);
+    }
+    let digits = text(field);
+    let digits = digits.trim_matches(|c: char| c == ' ' || c == '\0');
+    if digits.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(digits, 8)
+        .map_err(|_| ArchiveError::Malformed(format!("bad number {:?}", digits)))
+}
+
+fn checksum_ok(header: &[u8; BLOCK as usize]) -> Result<bool, ArchiveError> {
+    let expected = number(&header[148..156])?;
+    let sum: u64 = header
+        .iter()
+        .enumerate()
+        .map(|(i, &b)| if (148..156).contains(&i) { b' ' as u64 } else { b as u64 })
+        .sum();
+    Ok(sum == expected)
+}
+
+// Pax extended headers are records of the form "LENGTH KEY=VALUE\n".
+fn pax_records(data: &[u8]) -> Vec<(String, String)> {
+    let mut records = vec![];
+    let mut rest = data;
+    while let Some(space) = rest.iter().position(|&b| b == b' ') {
+        let length: usize = match std::str::from_utf8(&rest[..space]).ok().and_then(|l| l.parse().ok()) {
+            Some(length) if length > space && length <= rest.len() => length,
+            _ => break,
+        };
+        let record = String::from_utf8_lossy(&rest[space + 1..length]);
+        if let Some((key, value)) = record.trim_end_matches('\n').split_once('=') {
+            records.push((key.to_string(), value.to_string()));
+        }
+        rest = &rest[length..];
+    }
+    records
+}
+
+// Every regular file in the archive, with its offset in the (uncompressed)
+// tar stream.
+pub(crate) fn entries<R: Read>(input: R) -> Result<Vec<ArchiveEntry>, ArchiveError> {
+    let mut input = Counting {
+        inner: input,
+        position: 0,
+    };
+    let mut entries = vec![];
+    // Set by a GNU long name or pax header for the entry after it.
+    let mut next_path: Option<String> = None;
+    let mut next_size: Option<u64> = None;
+
+    loop {
+        let mut header = [0u8; BLOCK as usize];
+        let mut read = 0;
+        while read < header.len() {
+            match input.read(&mut header[read..])? {
+                0 => break,
+                n => read += n,
+            }
+        }
+        if read == 0 {
+            // Some writers leave out the end of archive blocks.
+            return Ok(entries);
+        }
+        if read < header.len() {
+            return Err(ArchiveError::Malformed("truncated header".to_string()));
+        }
+        if header.iter().all(|&b| b == 0) {
+            return Ok(entries);
+        }
+        if !checksum_ok(&header)? {
+            return Err(ArchiveError::Malformed(format!(
+                "bad header checksum at byte {}",
+                input.position - BLOCK
+            )));
+        }
+
+        let mut size = number(&header[124..136])?;
+        let kind = header[156];
+        match kind {
+            b'L' => {
+                next_path = Some(text(&input.read_data(size)?));
+                continue;
+            }
+            b'x' => {
+                for (key, value) in pax_records(&input.read_data(size)?) {
+                    match key.as_str() {
+                        "path" => next_path = Some(value),
+                        "size" => next_size = value.parse().ok(),
+                        _ => (),
+                    }
+                }
+                continue;
+            }
+            _ => (),
+        }
+
+        let path = match next_path.take() {
+            Some(path) => path,
+            None if &header[257..262] == b"ustar" && header[345] != 0 => {
+                format!("{}/{}", text(&header[345..500]), text(&header[0..100]))
+            }
+            None => text(&header[0..100]),
+        };
+        if let Some(pax_size) = next_size.take() {
+            size = pax_size;
+        }
+
+        let offset = input.position;
+        // Regular files, old style and contiguous. Links, directories and
+        // devices have no content of their own.
+        if matches!(kind, b'0' | b'\0' | b'7') && !path.ends_with('/') {
+            entries.push(ArchiveEntry {
+                path,
+                size,
+                offset,
+                packed_size: size,
+                compression: Compression::Stored,
+            });
+        }
+        match size.checked_add(padding(size)) {
+            Some(skip) => input.skip(skip)?,
+            None => {
+                return Err(ArchiveError::Malformed(format!(
+                    "bad size {} at byte {}",
+                    size,
+                    offset - BLOCK
+                )))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_should_be_octal_or_base_256() {
+        assert_eq!(0o644, number(b"0000644\0").unwrap());
+        assert_eq!(11, number(b"     13 ").unwrap());
+        assert_eq!(0, number(b"\0\0\0\0").unwrap());
+        assert_eq!(
+            10_000_000_000,
+            number(&[0x80, 0, 0, 0, 0x02, 0x54, 0x0b, 0xe4, 0x00]).unwrap()
+        );
+        assert!(number(b"0009\0").is_err());
+    }
+
+    #[test]
+    fn pax_records_should_be_split() {
+        let data = b"21 path=a/b/long.txt\n14 size=12345\n";
+        assert_eq!(
+            vec![
+                ("path".to_string(), "a/b/long.txt".to_string()),
+                ("size".to_string(), "12345".to_string())
+            ],
+            pax_records(data)
+        );
+        // A record longer than the data ends them.
+        assert_eq!(Vec::<(String, String)>::new(), pax_records(b"99 path=x\n"));
+    }
+}
@@ -0,0 +1,165 @@
+// Zip archives are read from their central directory at the end, so only
+// the directory and each entry's local header are read, never the data.
+
+use super::{ArchiveEntry, ArchiveError, Compression};
+use std::io::{Read, Seek, SeekFrom};
+
+const END_OF_DIRECTORY: u32 = 0x0605_4b50;
+const ZIP64_END_OF_DIRECTORY: u32 = 0x0606_4b50;
+const ZIP64_LOCATOR: u32 = 0x0706_4b50;
+const DIRECTORY_ENTRY: u32 = 0x0201_4b50;
+const LOCAL_HEADER: u32 = 0x0403_4b50;
+const ZIP64_EXTRA: u16 = 0x0001;
+
+// The end of directory record is 22 bytes, and may be followed by a
+// comment of up to 64K.
+const MAX_END_SEARCH: u64 = 22 + 0xffff;
+
+fn u16_at(b: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([b[at], b[at + 1]])
+}
+
+fn u32_at(b: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(b[at..at + 4].try_into().unwrap())
+}
+
+fn u64_at(b: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(b[at..at + 8].try_into().unwrap())
+}
+
+fn malformed(what: &str) -> ArchiveError {
+    ArchiveError::Malformed(what.to_string())
+}
+
+fn read_at<R: Read + Seek>(input: &mut R, at: u64, len: usize) -> Result<Vec<u8>, ArchiveError> {
+    input.seek(SeekFrom::Start(at))?;
+    let mut buf = vec![0; len];
+    input.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+// Where the central directory starts, and how many entries it has.
+fn find_directory<R: Read + Seek>(input: &mut R) -> Result<(u64, u64), ArchiveError> {
+    let len = input.seek(SeekFrom::End(0))?;
+    let search = len.min(MAX_END_SEARCH);
+    let tail = read_at(input, len - search, search as usize)?;
+
+    let end = (0..tail.len().saturating_sub(21))
+        .rev()
+        .find(|&i| u32_at(&tail, i) == END_OF_DIRECTORY)
+        .ok_or_else(|| malformed("no end of central directory"))?;
+    let end_offset = len - search + end as u64;
+    let mut entries = u16_at(&tail, end + 10) as u64;
+    let mut start = u32_at(&tail, end + 16) as u64;
+
+    // Archives too big for the 32 bit fields say so with all ones, and
+    // keep the real values in a ZIP64 record, found through a locator
+    // just before the end record.
+    if (entries == 0xffff || start == 0xffff_ffff) && end_offset >= 20 {
+        let locator = read_at(input, end_offset - 20, 20)?;
+        if u32_at(&locator, 0) == ZIP64_LOCATOR {
+            let record = read_at(input, u64_at(&locator, 8), 56)?;
+            if u32_at(&record, 0) != ZIP64_END_OF_DIRECTORY {
+                return Err(malformed("bad ZIP64 end of central directory"));
+            }
+            entries = u64_at(&record, 32);
+            start = u64_at(&record, 48);
+        }
+    }
+    Ok((start, entries))
+}
+
+// Replace the fields a directory entry marked as too big with their values
+// from its ZIP64 extra field, which holds only those, in this order.
+fn apply_zip64(extra: &[u8], size: &mut u64, packed: &mut u64, offset: &mut u64) {
+    let mut rest = extra;
+    while rest.len() >= 4 {
+        let id = u16_at(rest, 0);
+        let len = (u16_at(rest, 2) as usize).min(rest.len() - 4);
+        let mut data = &rest[4..4 + len];
+        if id == ZIP64_EXTRA {
+            for field in [size, packed, offset] {
+                if *field == 0xffff_ffff && data.len() >= 8 {
+                    *field = u64_at(data, 0);
+                    data = &data[8..];
+                }
+            }
+            return;
+        }
+        rest = &rest[4 + len..];
+    }
+}
+
+pub(crate) fn entries<R: Read + Seek>(mut input: R) -> Result<Vec<ArchiveEntry>, ArchiveError> {
+    let (start, count) = find_directory(&mut input)?;
+    input.seek(SeekFrom::Start(start))?;
+
+    // Read the whole directory first, then each local header.
+    let mut found = vec![];
+    for _ in 0..count {
+        let mut header = [0u8; 46];
+        input.read_exact(&mut header)?;
+        if u32_at(&header, 0) != DIRECTORY_ENTRY {
+            return Err(malformed("bad central directory entry"));
+        }
+        let method = u16_at(&header, 10);
+        let mut packed = u32_at(&header, 20) as u64;
+        let mut size = u32_at(&header, 24) as u64;
+        let name_len = u16_at(&header, 28) as usize;
+        let extra_len = u16_at(&header, 30) as usize;
+        let comment_len = u16_at(&header, 32) as usize;
+        let mut header_offset = u32_at(&header, 42) as u64;
+
+        let mut rest = vec![0; name_len + extra_len + comment_len];
+        input.read_exact(&mut rest)?;
+        let path = String::from_utf8_lossy(&rest[..name_len]).into_owned();
+        apply_zip64(
+            &rest[name_len..name_len + extra_len],
+            &mut size,
+            &mut packed,
+            &mut header_offset,
+        );
+
+        if !path.ends_with('/') {
+            found.push((path, method, size, packed, header_offset));
+        }
+    }
+
+    let mut entries = vec![];
+    for (path, method, size, packed_size, header_offset) in found {
+        let local = read_at(&mut input, header_offset, 30)?;
+        if u32_at(&local, 0) != LOCAL_HEADER {
+            return Err(malformed("bad local header"));
+        }
+        let offset = header_offset + 30 + u16_at(&local, 26) as u64 + u16_at(&local, 28) as u64;
+        entries.push(ArchiveEntry {
+            path,
+            size,
+            offset,
+            packed_size,
+            compression: match method {
+                0 => Compression::Stored,
+                8 => Compression::Deflate,
+                other => Compression::Other(other),
+            },
+        });
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zip64_fields_should_replace_only_marked_ones() {
+        let mut extra = vec![0x0a, 0x00, 0x02, 0x00, 0xaa, 0xbb];
+        extra.extend([0x01, 0x00, 0x10, 0x00]);
+        extra.extend(5_000_000_000u64.to_le_bytes());
+        extra.extend(6_000_000_000u64.to_le_bytes());
+
+        let (mut size, mut packed, mut offset) = (0xffff_ffff, 100, 0xffff_ffff);
+        apply_zip64(&extra, &mut size, &mut packed, &mut offset);
+        assert_eq!((5_000_000_000, 100, 6_000_000_000), (size, packed, offset));
+    }
+}
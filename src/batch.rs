@@ -0,0 +1,83 @@
+use crate::fdb_trait::{AddFileError, GetFileError};
+use crate::query::Query;
+use crate::File;
+
+// One staged change to a DB.
+#[derive(PartialEq, Debug, Clone)]
+pub enum BatchOp {
+    Add(File),
+    // Remove the single file the query matches once the ops before this
+    // one have been applied, as remove_file would.
+    Remove(Query),
+}
+
+// A set of changes that are applied in order, all together, or not at all.
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct Batch {
+    ops: Vec<BatchOp>,
+}
+
+impl Batch {
+    pub fn new() -> Self {
+        Batch { ops: vec![] }
+    }
+
+    pub fn add_file(&mut self, file: File) -> &mut Self {
+        self.ops.push(BatchOp::Add(file));
+        self
+    }
+
+    pub fn remove_file(&mut self, query: Query) -> &mut Self {
+        self.ops.push(BatchOp::Remove(query));
+        self
+    }
+
+    pub fn ops(&self) -> &[BatchOp] {
+        &self.ops
+    }
+
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+}
+
+impl FromIterator<File> for Batch {
+    fn from_iter<I: IntoIterator<Item = File>>(iter: I) -> Self {
+        Batch {
+            ops: iter.into_iter().map(BatchOp::Add).collect(),
+        }
+    }
+}
+
+// Why a batch was not applied; the first op that failed, and how.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct BatchError {
+    pub index: usize,
+    pub error: BatchOpError,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum BatchOpError {
+    Add(AddFileError),
+    Remove(GetFileError),
+}
+
+impl BatchError {
+    pub(crate) fn add(index: usize, error: AddFileError) -> Self {
+        BatchError {
+            index,
+            error: BatchOpError::Add(error),
+        }
+    }
+
+    pub(crate) fn remove(index: usize, error: GetFileError) -> Self {
+        BatchError {
+            index,
+            error: BatchOpError::Remove(error),
+        }
+    }
+}
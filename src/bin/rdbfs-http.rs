@@ -0,0 +1,51 @@
+// Serve a tag database over HTTP.
+//
+//   rdbfs-http [ADDRESS] [PATHS] [BACKEND]
+//
+// Listens on ADDRESS (127.0.0.1:7878 by default), after loading the files in
+// PATHS, a text file with one path such as "/photos/2021/beach.jpg" per line.
+// BACKEND is one of naive, hashtags, hashtags2 or tagtree, the default.
+
+use rdb_fs::fromstr::FromStr;
+use rdb_fs::{BackendConfig, File, FileDB, HttpService, BACKENDS};
+use std::net::TcpListener;
+use std::process::exit;
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let address = args.next().unwrap_or_else(|| "127.0.0.1:7878".to_string());
+
+    let paths = args.next();
+    let backend = args.next().unwrap_or_else(|| "tagtree".to_string());
+
+    let mut db = BackendConfig::new(&backend).build().unwrap_or_else(|_| {
+        eprintln!("unknown backend {}, expected one of {:?}", backend, BACKENDS);
+        exit(1)
+    });
+    if let Some(paths) = paths {
+        let contents = std::fs::read_to_string(&paths).unwrap_or_else(|e| {
+            eprintln!("can't read {}: {}", paths, e);
+            exit(1)
+        });
+        for line in contents.lines().filter(|l| !l.trim().is_empty()) {
+            match File::from_str(line.trim()) {
+                Ok(file) => {
+                    if let Err(e) = db.add_file(&file) {
+                        eprintln!("skipping {}: {:?}", line, e);
+                    }
+                }
+                Err(e) => eprintln!("skipping {}: {}", line, e),
+            }
+        }
+    }
+
+    let listener = TcpListener::bind(&address).unwrap_or_else(|e| {
+        eprintln!("can't listen on {}: {}", address, e);
+        exit(1)
+    });
+    eprintln!("serving on http://{}", address);
+    if let Err(e) = HttpService::new(db).serve(listener) {
+        eprintln!("{}", e);
+        exit(1);
+    }
+}
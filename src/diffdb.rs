@@ -0,0 +1,336 @@
+use crate::batch::{Batch, BatchError, BatchOp};
+use crate::fdb_trait::{AddFileError, GetFileError, RetagFileError};
+use crate::query::Query;
+use crate::{File, FileDB, FileQuery, QueryStats, TagSet};
+use std::cell::RefCell;
+use std::collections::btree_set::BTreeSet;
+use std::collections::vec_deque::VecDeque;
+use std::fmt;
+use std::mem::discriminant;
+
+// The changes DiffDBFS remembers by default.
+pub const DEFAULT_HISTORY_LIMIT: usize = 10_000;
+
+// An operation made on a DiffDBFS. Queries are kept as a Query with the
+// same tags, name and predicates.
+#[derive(PartialEq, Debug, Clone)]
+pub enum Operation {
+    Add(File),
+    GetFiles(Query),
+    GetFile(Query),
+    Remove(Query),
+    Retag(Query, TagSet),
+    FindByName(String),
+}
+
+impl Operation {
+    // Whether the operation may change what is stored.
+    fn is_change(&self) -> bool {
+        matches!(
+            self,
+            Operation::Add(_) | Operation::Remove(_) | Operation::Retag(..)
+        )
+    }
+}
+
+// Two backends giving different answers to the same operation.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Divergence {
+    pub operation: Operation,
+    // What each backend answered. For sets of files, only the files the
+    // other backend didn't return are listed.
+    pub primary: String,
+    pub secondary: String,
+    // The changes made before the operation, oldest first. Made on two
+    // empty backends, these should reproduce the divergence.
+    pub history: Vec<Operation>,
+    // Set if older changes were dropped from the history to keep it within
+    // its limit, in which case it may not be enough to reproduce it.
+    pub truncated: bool,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "backends diverged on {:?}", self.operation)?;
+        writeln!(f, "primary: {}", self.primary)?;
+        writeln!(f, "secondary: {}", self.secondary)?;
+        write!(f, "after {} changes", self.history.len())?;
+        if self.truncated {
+            write!(f, " (older changes dropped)")?;
+        }
+        for op in &self.history {
+            write!(f, "\n    {:?}", op)?;
+        }
+        Ok(())
+    }
+}
+
+// What DiffDBFS does when the backends disagree.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum OnDivergence {
+    // Panic, showing the divergence. For tests.
+    Panic,
+    // Keep the first divergence for DiffDBFS::divergence, and carry on
+    // answering from the primary backend.
+    Record,
+}
+
+#[derive(Debug, Clone, Default)]
+struct DiffState {
+    history: VecDeque<Operation>,
+    truncated: bool,
+    divergence: Option<Divergence>,
+}
+
+// Wraps two databases, making every operation on both and checking they
+// give the same answer. Sets of files are compared as sets, and errors by
+// their kind only, as backends may describe the same error differently.
+// Answers always come from the primary. Queries that only describe the
+// work done, like explain, go to the primary alone.
+#[derive(Clone)]
+pub struct DiffDBFS<A: FileDB, B: FileDB> {
+    primary: A,
+    secondary: B,
+    on_divergence: OnDivergence,
+    history_limit: usize,
+    state: RefCell<DiffState>,
+}
+
+impl<A: FileDB, B: FileDB> DiffDBFS<A, B> {
+    // Compare the two backends, panicking if they ever disagree.
+    pub fn new(primary: A, secondary: B) -> Self {
+        DiffDBFS {
+            primary,
+            secondary,
+            on_divergence: OnDivergence::Panic,
+            history_limit: DEFAULT_HISTORY_LIMIT,
+            state: RefCell::new(DiffState::default()),
+        }
+    }
+
+    pub fn with_on_divergence(mut self, on_divergence: OnDivergence) -> Self {
+        self.on_divergence = on_divergence;
+        self
+    }
+
+    // Remember at most this many changes to report with a divergence.
+    pub fn with_history_limit(mut self, limit: usize) -> Self {
+        self.history_limit = limit;
+        self
+    }
+
+    pub fn primary(&self) -> &A {
+        &self.primary
+    }
+
+    pub fn secondary(&self) -> &B {
+        &self.secondary
+    }
+
+    pub fn into_inner(self) -> (A, B) {
+        (self.primary, self.secondary)
+    }
+
+    // The first divergence found, when recording them.
+    pub fn divergence(&self) -> Option<Divergence> {
+        self.state.borrow().divergence.clone()
+    }
+
+    // Compare the answers to an operation, then remember it if it was a
+    // change.
+    fn check(&self, operation: Operation, same: bool, primary: String, secondary: String) {
+        let mut state = self.state.borrow_mut();
+        if !same && state.divergence.is_none() {
+            let divergence = Divergence {
+                operation: operation.clone(),
+                primary,
+                secondary,
+                history: state.history.iter().cloned().collect(),
+                truncated: state.truncated,
+            };
+            match self.on_divergence {
+                OnDivergence::Panic => panic!("{}", divergence),
+                OnDivergence::Record => state.divergence = Some(divergence),
+            }
+        }
+
+        if operation.is_change() {
+            state.history.push_back(operation);
+            while state.history.len() > self.history_limit {
+                state.history.pop_front();
+                state.truncated = true;
+            }
+        }
+    }
+
+    fn check_results<T: PartialEq + fmt::Debug, E: fmt::Debug>(
+        &self,
+        operation: Operation,
+        primary: &Result<T, E>,
+        secondary: &Result<T, E>,
+        same_error: fn(&E, &E) -> bool,
+    ) {
+        let same = match (primary, secondary) {
+            (Ok(a), Ok(b)) => a == b,
+            (Err(a), Err(b)) => same_error(a, b),
+            _ => false,
+        };
+        self.check(
+            operation,
+            same,
+            format!("{:?}", primary),
+            format!("{:?}", secondary),
+        );
+    }
+
+    fn check_sets(
+        &self,
+        operation: Operation,
+        primary: &BTreeSet<File>,
+        secondary: &BTreeSet<File>,
+    ) {
+        let only_in = |a: &BTreeSet<File>, b: &BTreeSet<File>| {
+            let missing: Vec<&File> = a.difference(b).collect();
+            format!(
+                "{} files, {} not in the other: {:?}",
+                a.len(),
+                missing.len(),
+                missing
+            )
+        };
+        self.check(
+            operation,
+            primary == secondary,
+            only_in(primary, secondary),
+            only_in(secondary, primary),
+        );
+    }
+}
+
+fn query_of<F: FileQuery>(query: &F) -> Query {
+    Query::with_tags_of(query, query.tags().clone())
+}
+
+fn same_kind<E>(a: &E, b: &E) -> bool {
+    discriminant(a) == discriminant(b)
+}
+
+fn same_retag_error(a: &RetagFileError, b: &RetagFileError) -> bool {
+    match (a, b) {
+        (RetagFileError::Get(a), RetagFileError::Get(b)) => same_kind(a, b),
+        (RetagFileError::Add(a), RetagFileError::Add(b)) => same_kind(a, b),
+        (RetagFileError::Lost { retag: a, .. }, RetagFileError::Lost { retag: b, .. }) => {
+            same_kind(a, b)
+        }
+        _ => false,
+    }
+}
+
+impl<A: FileDB, B: FileDB> FileDB for DiffDBFS<A, B> {
+    type FileIterator = std::collections::btree_set::IntoIter<File>;
+
+    fn add_file(&mut self, new_file: &File) -> Result<(), AddFileError> {
+        let primary = self.primary.add_file(new_file);
+        let secondary = self.secondary.add_file(new_file);
+        self.check_results(
+            Operation::Add(new_file.clone()),
+            &primary,
+            &secondary,
+            same_kind,
+        );
+        primary
+    }
+
+    fn get_files<F: FileQuery>(&self, query: &F) -> Self::FileIterator {
+        let primary: BTreeSet<File> = self.primary.get_files(query).collect();
+        let secondary: BTreeSet<File> = self.secondary.get_files(query).collect();
+        self.check_sets(Operation::GetFiles(query_of(query)), &primary, &secondary);
+        primary.into_iter()
+    }
+
+    fn explain<F: FileQuery>(&self, query: &F) -> QueryStats {
+        self.primary.explain(query)
+    }
+
+    fn get_file<F: FileQuery>(&self, query: &F) -> Result<File, GetFileError> {
+        let primary = self.primary.get_file(query);
+        let secondary = self.secondary.get_file(query);
+        self.check_results(
+            Operation::GetFile(query_of(query)),
+            &primary,
+            &secondary,
+            same_kind,
+        );
+        primary
+    }
+
+    fn remove_file<F: FileQuery>(&mut self, query: &F) -> Result<File, GetFileError> {
+        let primary = self.primary.remove_file(query);
+        let secondary = self.secondary.remove_file(query);
+        self.check_results(
+            Operation::Remove(query_of(query)),
+            &primary,
+            &secondary,
+            same_kind,
+        );
+        primary
+    }
+
+    fn retag_file<F: FileQuery>(
+        &mut self,
+        query: &F,
+        tags: TagSet,
+    ) -> Result<File, RetagFileError> {
+        let primary = self.primary.retag_file(query, tags.clone());
+        let secondary = self.secondary.retag_file(query, tags.clone());
+        self.check_results(
+            Operation::Retag(query_of(query), tags),
+            &primary,
+            &secondary,
+            same_retag_error,
+        );
+        primary
+    }
+
+    // Applies the batch to a copy, as the default does, so each file added
+    // or removed is compared. If the batch fails, the copy is dropped but a
+    // divergence found on the way is kept.
+    fn apply_batch(&mut self, batch: &Batch) -> Result<Vec<File>, BatchError>
+    where
+        Self: Clone,
+    {
+        let mut staged = self.clone();
+        let mut removed = vec![];
+        for (index, op) in batch.ops().iter().enumerate() {
+            let result = match op {
+                BatchOp::Add(file) => staged
+                    .add_file(file)
+                    .map_err(|error| BatchError::add(index, error)),
+                BatchOp::Remove(query) => staged
+                    .remove_file(query)
+                    .map(|file| removed.push(file))
+                    .map_err(|error| BatchError::remove(index, error)),
+            };
+            if let Err(error) = result {
+                let state = self.state.get_mut();
+                if state.divergence.is_none() {
+                    state.divergence = staged.state.into_inner().divergence;
+                }
+                return Err(error);
+            }
+        }
+        *self = staged;
+        Ok(removed)
+    }
+
+    fn find_by_name(&self, name: &str) -> Vec<File> {
+        let primary: BTreeSet<File> = self.primary.find_by_name(name).into_iter().collect();
+        let secondary: BTreeSet<File> = self.secondary.find_by_name(name).into_iter().collect();
+        self.check_sets(
+            Operation::FindByName(name.to_string()),
+            &primary,
+            &secondary,
+        );
+        primary.into_iter().collect()
+    }
+}
@@ -0,0 +1,214 @@
+use crate::batch::{Batch, BatchError};
+use crate::explain::QueryStats;
+use crate::AddFileError;
+use crate::File;
+use crate::FileDB;
+use crate::FileQuery;
+use crate::GetFileError;
+use crate::HashTags2DBFS;
+use crate::HashTagsDBFS;
+use crate::NaiveDBFS;
+use crate::QueryOptions;
+use crate::ResultPage;
+use crate::RetagFileError;
+use crate::RuleSet;
+use crate::RulesDBFS;
+use crate::TagHierarchy;
+use crate::TagSet;
+use crate::TagTreeDBFS;
+
+// FileDB, in a form that can be used as a trait object, so the backend can
+// be chosen at runtime. Every FileDB that can be cloned and sent between
+// threads is a DynFileDB; each dyn_ method does the same as the FileDB
+// method it is named after, but takes &dyn FileQuery and boxes the
+// iterator. The names differ so that having both traits in scope doesn't
+// make calls on a plain backend ambiguous.
+pub trait DynFileDB {
+    fn dyn_add_file(&mut self, file: &File) -> Result<(), AddFileError>;
+
+    fn dyn_get_files<'a>(&'a self, query: &dyn FileQuery) -> Box<dyn Iterator<Item = File> + 'a>;
+
+    fn dyn_get_page(&self, query: &dyn FileQuery, options: &QueryOptions) -> ResultPage;
+
+    fn dyn_explain(&self, query: &dyn FileQuery) -> QueryStats;
+
+    fn dyn_get_file(&self, query: &dyn FileQuery) -> Result<File, GetFileError>;
+
+    fn dyn_remove_file(&mut self, query: &dyn FileQuery) -> Result<File, GetFileError>;
+
+    fn dyn_retag_file(&mut self, query: &dyn FileQuery, tags: TagSet)
+        -> Result<File, RetagFileError>;
+
+    fn dyn_apply_batch(&mut self, batch: &Batch) -> Result<Vec<File>, BatchError>;
+
+    fn dyn_find_by_name(&self, name: &str) -> Vec<File>;
+
+    // A copy of the DB, so a boxed DynFileDB can be cloned.
+    fn clone_box(&self) -> Box<dyn DynFileDB + Send>;
+}
+
+impl<DB: FileDB + Clone + Send + 'static> DynFileDB for DB {
+    fn dyn_add_file(&mut self, file: &File) -> Result<(), AddFileError> {
+        FileDB::add_file(self, file)
+    }
+
+    fn dyn_get_files<'a>(&'a self, query: &dyn FileQuery) -> Box<dyn Iterator<Item = File> + 'a> {
+        Box::new(FileDB::get_files(self, &query))
+    }
+
+    fn dyn_get_page(&self, query: &dyn FileQuery, options: &QueryOptions) -> ResultPage {
+        FileDB::get_page(self, &query, options)
+    }
+
+    fn dyn_explain(&self, query: &dyn FileQuery) -> QueryStats {
+        FileDB::explain(self, &query)
+    }
+
+    fn dyn_get_file(&self, query: &dyn FileQuery) -> Result<File, GetFileError> {
+        FileDB::get_file(self, &query)
+    }
+
+    fn dyn_remove_file(&mut self, query: &dyn FileQuery) -> Result<File, GetFileError> {
+        FileDB::remove_file(self, &query)
+    }
+
+    fn dyn_retag_file(
+        &mut self,
+        query: &dyn FileQuery,
+        tags: TagSet,
+    ) -> Result<File, RetagFileError> {
+        FileDB::retag_file(self, &query, tags)
+    }
+
+    fn dyn_apply_batch(&mut self, batch: &Batch) -> Result<Vec<File>, BatchError> {
+        FileDB::apply_batch(self, batch)
+    }
+
+    fn dyn_find_by_name(&self, name: &str) -> Vec<File> {
+        FileDB::find_by_name(self, name)
+    }
+
+    fn clone_box(&self) -> Box<dyn DynFileDB + Send> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for Box<dyn DynFileDB> {
+    fn clone(&self) -> Self {
+        (**self).clone_box()
+    }
+}
+
+impl Clone for Box<dyn DynFileDB + Send> {
+    fn clone(&self) -> Self {
+        (**self).clone_box()
+    }
+}
+
+// A boxed DynFileDB is a FileDB again, so it can be wrapped in RulesDBFS,
+// served, and so on. The iterator can't borrow from the box, so get_files
+// collects the matches first.
+impl<D: DynFileDB + ?Sized> FileDB for Box<D> {
+    type FileIterator = std::vec::IntoIter<File>;
+
+    fn add_file(&mut self, file: &File) -> Result<(), AddFileError> {
+        (**self).dyn_add_file(file)
+    }
+
+    fn get_files<F: FileQuery>(&self, query: &F) -> Self::FileIterator {
+        (**self).dyn_get_files(query).collect::<Vec<_>>().into_iter()
+    }
+
+    fn get_page<F: FileQuery>(&self, query: &F, options: &QueryOptions) -> ResultPage {
+        (**self).dyn_get_page(query, options)
+    }
+
+    fn explain<F: FileQuery>(&self, query: &F) -> QueryStats {
+        (**self).dyn_explain(query)
+    }
+
+    fn get_file<F: FileQuery>(&self, query: &F) -> Result<File, GetFileError> {
+        (**self).dyn_get_file(query)
+    }
+
+    fn remove_file<F: FileQuery>(&mut self, query: &F) -> Result<File, GetFileError> {
+        (**self).dyn_remove_file(query)
+    }
+
+    fn retag_file<F: FileQuery>(
+        &mut self,
+        query: &F,
+        tags: TagSet,
+    ) -> Result<File, RetagFileError> {
+        (**self).dyn_retag_file(query, tags)
+    }
+
+    // Leave the staging to the backend, rather than cloning the whole box.
+    fn apply_batch(&mut self, batch: &Batch) -> Result<Vec<File>, BatchError>
+    where
+        Self: Clone,
+    {
+        (**self).dyn_apply_batch(batch)
+    }
+
+    fn find_by_name(&self, name: &str) -> Vec<File> {
+        (**self).dyn_find_by_name(name)
+    }
+}
+
+// The names BackendConfig accepts.
+pub const BACKENDS: [&str; 4] = ["naive", "hashtags", "hashtags2", "tagtree"];
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct UnknownBackend(pub String);
+
+// Which backend to build, and how to set it up.
+#[derive(Debug, Clone)]
+pub struct BackendConfig {
+    name: String,
+    hierarchy: TagHierarchy,
+    rules: Option<RuleSet>,
+}
+
+impl BackendConfig {
+    // The backend with one of the names in BACKENDS, with a flat tag
+    // hierarchy and no rules.
+    pub fn new(name: &str) -> Self {
+        BackendConfig {
+            name: name.to_string(),
+            hierarchy: TagHierarchy::new(),
+            rules: None,
+        }
+    }
+
+    pub fn with_hierarchy(mut self, hierarchy: TagHierarchy) -> Self {
+        self.hierarchy = hierarchy;
+        self
+    }
+
+    // Wrap the backend in a RulesDBFS applying these rules.
+    pub fn with_rules(mut self, rules: RuleSet) -> Self {
+        self.rules = Some(rules);
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    // Make an empty DB as configured.
+    pub fn build(&self) -> Result<Box<dyn DynFileDB + Send>, UnknownBackend> {
+        let hierarchy = self.hierarchy.clone();
+        let db: Box<dyn DynFileDB + Send> = match self.name.as_str() {
+            "naive" => Box::new(NaiveDBFS::with_hierarchy(hierarchy)),
+            "hashtags" => Box::new(HashTagsDBFS::with_hierarchy(hierarchy)),
+            "hashtags2" => Box::new(HashTags2DBFS::with_hierarchy(hierarchy)),
+            "tagtree" => Box::new(TagTreeDBFS::with_hierarchy(hierarchy)),
+            _ => return Err(UnknownBackend(self.name.clone())),
+        };
+        Ok(match &self.rules {
+            Some(rules) => Box::new(RulesDBFS::new(db, rules.clone())),
+            None => db,
+        })
+    }
+}
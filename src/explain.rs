@@ -0,0 +1,93 @@
+use std::collections::btree_map::BTreeMap;
+use std::fmt;
+
+// Counts of the work a backend did to answer a query, as returned by
+// FileDB::explain. Each backend fills in the counters that apply to how it
+// searches and leaves the rest at zero, so the same query can be compared
+// across backends.
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct QueryStats {
+    // Tag tree: branches whose masks were checked.
+    pub branch_nodes_visited: usize,
+    // Tag tree: non-empty children of those branches that the query's
+    // masks ruled out, so were never searched.
+    pub children_pruned: usize,
+    // Tag tree: how many non-empty children each tag of the query, or the
+    // predicates on each key, would have ruled out alone. A child is
+    // counted once for every mask that excludes it.
+    pub pruned_by: BTreeMap<String, usize>,
+    // Tag tree: leaves whose tags allowed the query, so whose files were
+    // looked at.
+    pub end_nodes_scanned: usize,
+    // Hash backends: tags looked up in the index.
+    pub index_lookups: usize,
+    // Hash backends: entries read from the sets those lookups found.
+    pub index_entries_read: usize,
+    // Files checked against the rest of the query once the backend's
+    // index or tree had picked them out as possible results.
+    pub files_scanned: usize,
+    // Files looked at but not returned.
+    pub files_rejected: usize,
+    // Files returned.
+    pub files_matched: usize,
+}
+
+impl QueryStats {
+    pub(crate) fn scanned(&mut self, scanned: usize, matched: usize) {
+        self.files_scanned += scanned;
+        self.files_matched += matched;
+        self.files_rejected += scanned - matched;
+    }
+}
+
+impl fmt::Display for QueryStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "branch nodes visited: {}", self.branch_nodes_visited)?;
+        write!(f, "children pruned: {}", self.children_pruned)?;
+        if !self.pruned_by.is_empty() {
+            let by: Vec<String> = self
+                .pruned_by
+                .iter()
+                .map(|(tag, count)| format!("{}: {}", tag, count))
+                .collect();
+            write!(f, " ({})", by.join(", "))?;
+        }
+        writeln!(f)?;
+        writeln!(f, "end nodes scanned: {}", self.end_nodes_scanned)?;
+        writeln!(f, "index lookups: {}", self.index_lookups)?;
+        writeln!(f, "index entries read: {}", self.index_entries_read)?;
+        writeln!(f, "files scanned: {}", self.files_scanned)?;
+        writeln!(f, "files rejected: {}", self.files_rejected)?;
+        write!(f, "files matched: {}", self.files_matched)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::QueryStats;
+
+    #[test]
+    fn stats_should_display_one_counter_per_line() {
+        let mut stats = QueryStats {
+            branch_nodes_visited: 2,
+            children_pruned: 3,
+            end_nodes_scanned: 1,
+            ..QueryStats::default()
+        };
+        stats.pruned_by.insert("photos".to_string(), 3);
+        stats.pruned_by.insert("year".to_string(), 1);
+        stats.scanned(4, 3);
+
+        assert_eq!(
+            "branch nodes visited: 2\n\
+             children pruned: 3 (photos: 3, year: 1)\n\
+             end nodes scanned: 1\n\
+             index lookups: 0\n\
+             index entries read: 0\n\
+             files scanned: 4\n\
+             files rejected: 1\n\
+             files matched: 3",
+            stats.to_string()
+        );
+    }
+}
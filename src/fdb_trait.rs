@@ -1,10 +1,171 @@
+use crate::batch::{Batch, BatchError, BatchOp};
 use crate::File;
 use crate::FileQuery;
+use crate::explain::QueryStats;
+use crate::paging::page;
+use crate::Query;
+use crate::QueryOptions;
+use crate::ResultPage;
+use crate::TagSet;
+use std::collections::btree_set::BTreeSet;
 
-#[derive(PartialEq, Eq, Debug)]
+// The most candidates an ambiguity error will list.
+pub const MAX_CANDIDATES: usize = 16;
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Ambiguity {
+    // The files that matched, in order, up to MAX_CANDIDATES of them.
+    pub candidates: Vec<File>,
+    // Set if more files matched than are listed.
+    pub truncated: bool,
+    // Tags some, but not all, of the candidates have; adding one of these to
+    // the query will narrow the match.
+    pub disambiguating_tags: TagSet,
+}
+
+impl Ambiguity {
+    fn new(candidates: BTreeSet<File>, truncated: bool) -> Self {
+        let mut all_tags = TagSet::new();
+        let mut common_tags: Option<TagSet> = None;
+        for f in &candidates {
+            all_tags.extend(f.tags.iter().cloned());
+            common_tags = Some(match common_tags {
+                Some(common) => common.intersection(&f.tags).cloned().collect(),
+                None => f.tags.clone(),
+            });
+        }
+
+        Ambiguity {
+            disambiguating_tags: all_tags
+                .difference(&common_tags.unwrap_or_default())
+                .cloned()
+                .collect(),
+            candidates: candidates.into_iter().collect(),
+            truncated,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum AddFileError {
+    // Exactly this file is already stored.
+    Duplicate,
+    // Storing the file would make existing files of the same name
+    // impossible to tell apart; holds the files it conflicts with.
+    Conflict(Vec<File>),
+    // The file has a tag that can't be stored, e.g. an empty one.
+    InvalidTag(String),
+    // The backend couldn't find room for the file.
+    Capacity,
+}
+
+impl AddFileError {
+    // Checks every backend makes before storing a file.
+    pub(crate) fn check_tags(file: &File) -> Result<(), AddFileError> {
+        match file.tags.iter().find(|t| t.is_empty()) {
+            Some(t) => Err(AddFileError::InvalidTag(t.clone())),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
 pub enum GetFileError {
     NoSuchFile,
-    TooManyFiles,
+    TooManyFiles(Ambiguity),
+}
+
+#[derive(PartialEq, Eq, Debug)]
+pub enum RetagFileError {
+    // The query didn't pick out a single file to retag.
+    Get(GetFileError),
+    // The file couldn't be stored with its new tags; it keeps its old ones.
+    Add(AddFileError),
+    // The file couldn't be stored with its new tags, and then couldn't be
+    // put back with its old ones either, so it is no longer in the DB.
+    // Holds the file as it was, and why each attempt failed.
+    Lost {
+        file: File,
+        retag: AddFileError,
+        restore: AddFileError,
+    },
+}
+
+// Gathers the matches for get_file, so that if there is more than one, the
+// error can say what they were.
+pub(crate) struct Candidates {
+    found: BTreeSet<File>,
+    truncated: bool,
+}
+
+impl Candidates {
+    pub(crate) fn new() -> Self {
+        Candidates {
+            found: BTreeSet::new(),
+            truncated: false,
+        }
+    }
+
+    // Returns false once there are more candidates than we will list, so the
+    // caller can stop searching.
+    pub(crate) fn push(&mut self, file: File) -> bool {
+        if self.found.len() < MAX_CANDIDATES || self.found.contains(&file) {
+            self.found.insert(file);
+            true
+        } else {
+            self.truncated = true;
+            false
+        }
+    }
+
+    // Add the result of searching part of the DB.
+    pub(crate) fn push_result(&mut self, result: Result<File, GetFileError>) -> bool {
+        match result {
+            Ok(file) => self.push(file),
+            Err(GetFileError::NoSuchFile) => true,
+            Err(GetFileError::TooManyFiles(ambiguity)) => {
+                self.truncated |= ambiguity.truncated;
+                ambiguity.candidates.into_iter().all(|f| self.push(f)) && !self.truncated
+            }
+        }
+    }
+
+    pub(crate) fn into_result(mut self) -> Result<File, GetFileError> {
+        match self.found.len() {
+            0 => Err(GetFileError::NoSuchFile),
+            1 => Ok(self.found.pop_first().unwrap()),
+            _ => Err(GetFileError::TooManyFiles(Ambiguity::new(
+                self.found,
+                self.truncated,
+            ))),
+        }
+    }
+}
+
+// Replace the single file matching the query with the one made from it,
+// as retag_file does. Also used to rename files, which FileDB can't do.
+pub(crate) fn replace_file<DB, F, M>(
+    db: &mut DB,
+    query: &F,
+    make: M,
+) -> Result<File, RetagFileError>
+where
+    DB: FileDB + ?Sized,
+    F: FileQuery,
+    M: FnOnce(&File) -> File,
+{
+    let old = db.remove_file(query).map_err(RetagFileError::Get)?;
+    match db.add_file(&make(&old)) {
+        Ok(()) => Ok(old),
+        Err(retag) => match db.add_file(&old) {
+            Ok(()) => Err(RetagFileError::Add(retag)),
+            Err(restore) => Err(RetagFileError::Lost {
+                file: old,
+                retag,
+                restore,
+            }),
+        },
+    }
 }
 
 pub trait FileDB {
@@ -12,13 +173,175 @@ pub trait FileDB {
 
     // Add a file to the DB. Fail if it collides exatly
     // with an existing file.
-    fn add_file(&mut self, new_files: &File) -> Option<()>;
+    fn add_file(&mut self, new_files: &File) -> Result<(), AddFileError>;
 
     // Get the set of all files that match the given
     // query.
     fn get_files<F: FileQuery>(&self, query: &F) -> Self::FileIterator;
 
+    // Get one page of the files that match the given query,
+    // in the order the options ask for.
+    fn get_page<F: FileQuery>(&self, query: &F, options: &QueryOptions) -> ResultPage {
+        page(self.get_files(query), options)
+    }
+
+    // Count the work done to answer the query, to see
+    // why it is slow or compare backends. By default only
+    // the results are counted.
+    fn explain<F: FileQuery>(&self, query: &F) -> QueryStats {
+        let mut stats = QueryStats::default();
+        let matched = self.get_files(query).count();
+        stats.scanned(matched, matched);
+        stats
+    }
+
     // Get a single file, fail if there are multiple
     // or no matches.
     fn get_file<F: FileQuery>(&self, query: &F) -> Result<File, GetFileError>;
+
+    // Remove the single file matching the query, and return it. Fails in
+    // the same way as get_file, in which case nothing is removed.
+    fn remove_file<F: FileQuery>(&mut self, query: &F) -> Result<File, GetFileError>;
+
+    // Replace the tags of the single file matching the query, and return
+    // the file as it was. If the file can't be stored with the new tags,
+    // it is put back as it was; if that fails too, the error holds it.
+    fn retag_file<F: FileQuery>(
+        &mut self,
+        query: &F,
+        tags: TagSet,
+    ) -> Result<File, RetagFileError> {
+        replace_file(self, query, |old| File::new(old.name.clone(), tags))
+    }
+
+    // Apply every change in the batch, in order, or none of them, and
+    // return the files removed, one for each Remove op. By default the
+    // changes are made to a clone of the whole DB, which replaces it only
+    // if they all succeed; so even a small batch costs time and memory in
+    // proportion to the size of the DB. Backends that can stage changes
+    // more cheaply override this.
+    fn apply_batch(&mut self, batch: &Batch) -> Result<Vec<File>, BatchError>
+    where
+        Self: Clone,
+    {
+        let mut staged = self.clone();
+        let mut removed = vec![];
+        for (index, op) in batch.ops().iter().enumerate() {
+            match op {
+                BatchOp::Add(file) => staged
+                    .add_file(file)
+                    .map_err(|error| BatchError::add(index, error))?,
+                BatchOp::Remove(query) => removed.push(
+                    staged
+                        .remove_file(query)
+                        .map_err(|error| BatchError::remove(index, error))?,
+                ),
+            }
+        }
+        *self = staged;
+        Ok(removed)
+    }
+
+    // Get every file with the given name, whatever its tags.
+    // Backends with a name index should override this, as
+    // the default searches the whole DB.
+    fn find_by_name(&self, name: &str) -> Vec<File> {
+        self.get_files(&Query::new(TagSet::new()).with_name(name))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AddFileError, Candidates, GetFileError, RetagFileError, MAX_CANDIDATES};
+    use crate::{fromstr::FromStr, File, FileDB, FileQuery, NaiveDBFS, TagSet};
+
+    // A DB that has no room for anything once a file has been removed.
+    struct Shrinking {
+        db: NaiveDBFS,
+        full: bool,
+    }
+
+    impl FileDB for Shrinking {
+        type FileIterator = <NaiveDBFS as FileDB>::FileIterator;
+
+        fn add_file(&mut self, new_file: &File) -> Result<(), AddFileError> {
+            if self.full {
+                return Err(AddFileError::Capacity);
+            }
+            self.db.add_file(new_file)
+        }
+
+        fn get_files<F: FileQuery>(&self, query: &F) -> Self::FileIterator {
+            self.db.get_files(query)
+        }
+
+        fn get_file<F: FileQuery>(&self, query: &F) -> Result<File, GetFileError> {
+            self.db.get_file(query)
+        }
+
+        fn remove_file<F: FileQuery>(&mut self, query: &F) -> Result<File, GetFileError> {
+            self.full = true;
+            self.db.remove_file(query)
+        }
+    }
+
+    #[test]
+    fn candidates_should_be_capped() {
+        let mut candidates = Candidates::new();
+        for i in 0..MAX_CANDIDATES {
+            assert!(candidates.push(File::new_cloned(&i.to_string(), ["a"])));
+        }
+        assert!(!candidates.push(File::new_cloned("one more", ["a"])));
+
+        match candidates.into_result() {
+            Err(GetFileError::TooManyFiles(ambiguity)) => {
+                assert_eq!(MAX_CANDIDATES, ambiguity.candidates.len());
+                assert!(ambiguity.truncated);
+                assert!(ambiguity.disambiguating_tags.is_empty());
+            }
+            other => panic!("Expected an ambiguity, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn candidates_should_list_disambiguating_tags() {
+        let mut candidates = Candidates::new();
+        candidates.push(File::from_str("/a/b/x").unwrap());
+        candidates.push(File::from_str("/a/c/d/x").unwrap());
+        // Repeats are ignored.
+        candidates.push(File::from_str("/a/b/x").unwrap());
+
+        match candidates.into_result() {
+            Err(GetFileError::TooManyFiles(ambiguity)) => {
+                assert_eq!(2, ambiguity.candidates.len());
+                assert!(!ambiguity.truncated);
+                assert_eq!(
+                    TagSet::from_str("/b/c/d").unwrap(),
+                    ambiguity.disambiguating_tags
+                );
+            }
+            other => panic!("Expected an ambiguity, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn retag_should_return_a_file_it_could_not_put_back() {
+        let mut db = Shrinking {
+            db: NaiveDBFS::new(),
+            full: false,
+        };
+        let file = File::from_str("/a/x").unwrap();
+        db.add_file(&file).unwrap();
+
+        assert_eq!(
+            Err(RetagFileError::Lost {
+                file,
+                retag: AddFileError::Capacity,
+                restore: AddFileError::Capacity,
+            }),
+            db.retag_file(&File::from_str("/a/x").unwrap(), TagSet::from_str("/b").unwrap())
+        );
+        assert!(db.find_by_name("x").is_empty());
+    }
 }
@@ -1,6 +1,7 @@
-use crate::fromstr::FromStr;
+use crate::fromstr::{components, write_component, FromStr, ParseError, ParseErrorKind};
 use crate::FileQuery;
 use crate::TagSet;
+use std::fmt;
 
 #[derive(PartialOrd, Ord, PartialEq, Eq, Hash, Debug, Clone)]
 pub struct File {
@@ -25,18 +26,33 @@ impl File {
     }
 
     pub(crate) fn has_tags(&self, tags: &TagSet) -> bool {
-        self.tags.is_superset(&tags)
+        self.tags.is_superset(tags)
     }
 }
 
 impl FromStr for File {
-    fn from_str(path: &str) -> Option<File> {
-        let mut split = path.rsplit("/");
-        // Filter out empty strings to remove "" before the first '/'
-        Some(File::new(
-            split.next()?.to_string(),
-            split.filter(|x| x != &"").map(|x| x.to_string()).collect(),
-        ))
+    // The last component is the name, the rest are tags.
+    fn from_str(path: &str) -> Result<File, ParseError> {
+        let (mut found, trailing) = components(path)?;
+        match found.pop() {
+            Some(name) if !trailing => Ok(File::new(
+                name.text,
+                found.into_iter().map(|c| c.text).collect(),
+            )),
+            _ => Err(ParseError::new(path.len(), ParseErrorKind::MissingName)),
+        }
+    }
+}
+
+// The file as "/tag/tag/name", which File::from_str reads back.
+impl fmt::Display for File {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for tag in &self.tags {
+            f.write_str("/")?;
+            write_component(f, tag)?;
+        }
+        f.write_str("/")?;
+        write_component(f, &self.name)
     }
 }
 
@@ -59,7 +75,7 @@ mod tests {
 
     use crate::file::File;
     use crate::file::TagSet;
-    use crate::fromstr::FromStr;
+    use crate::fromstr::{FromStr, ParseError, ParseErrorKind};
     use std::collections::btree_set::BTreeSet;
 
     #[test]
@@ -86,6 +102,36 @@ mod tests {
 
         let actual = File::from_str(input);
 
-        assert_eq!(Some(expected), actual);
+        assert_eq!(Ok(expected), actual);
+    }
+
+    #[test]
+    fn file_without_a_name_should_not_parse() {
+        for path in ["", "/", "/etc/", "etc/fine/"] {
+            assert_eq!(
+                Err(ParseError::new(path.len(), ParseErrorKind::MissingName)),
+                File::from_str(path)
+            );
+        }
+    }
+
+    #[test]
+    fn display_should_round_trip_awkward_files() {
+        let files = [
+            File::new_cloned("make.txt", ["etc", "fine"]),
+            File::new_cloned("AC/DC.mp3", ["rock/metal", ""]),
+            File::new_cloned("\"quoted\"", ["back\\slash\\", "\\x2f"]),
+            File::new_cloned("", Vec::<String>::new()),
+        ];
+        let paths = [
+            "/etc/fine/make.txt",
+            "/\"\"/rock\\/metal/AC\\/DC.mp3",
+            "/\\x2f/back\\slash\\\\/\\\"quoted\"",
+            "/\"\"",
+        ];
+        for (file, path) in files.into_iter().zip(paths) {
+            assert_eq!(path, file.to_string());
+            assert_eq!(Ok(file), File::from_str(path));
+        }
     }
 }
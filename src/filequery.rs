@@ -1,8 +1,47 @@
 use crate::File;
+use crate::KeyPredicate;
+use crate::NamePattern;
 use crate::TagSet;
 
 pub trait FileQuery {
     fn could_match(&self, to_match: &File) -> bool;
     fn tags(&self) -> &TagSet;
     fn name(&self) -> Option<&str>;
+
+    // Comparisons on structured "key=value" tags that a match must also
+    // satisfy. Backends may use these to prune, but must still check
+    // could_match on anything they return.
+    fn predicates(&self) -> &[KeyPredicate] {
+        &[]
+    }
+
+    // A pattern the file name must match, for queries that don't give an
+    // exact name.
+    fn name_pattern(&self) -> Option<&NamePattern> {
+        None
+    }
+}
+
+// Lets a borrowed query, in particular a &dyn FileQuery, be passed where a
+// FileQuery is expected.
+impl<F: FileQuery + ?Sized> FileQuery for &F {
+    fn could_match(&self, to_match: &File) -> bool {
+        (**self).could_match(to_match)
+    }
+
+    fn tags(&self) -> &TagSet {
+        (**self).tags()
+    }
+
+    fn name(&self) -> Option<&str> {
+        (**self).name()
+    }
+
+    fn predicates(&self) -> &[KeyPredicate] {
+        (**self).predicates()
+    }
+
+    fn name_pattern(&self) -> Option<&NamePattern> {
+        (**self).name_pattern()
+    }
 }
@@ -1,3 +1,245 @@
+// Files, tag sets and queries are written as paths, e.g.
+// "/photos/2021/beach.jpg", with this grammar:
+//
+//   path      = ["/"] [component *("/" component)] ["/"]
+//   component = quoted / plain
+//   quoted    = '"' *(char / "\\" / '\"') '"'
+//   plain     = 1*(char / "\\" / "\/" / '\"')
+//
+// A backslash escapes a following '\', '/' or '"', and otherwise stands for
+// itself, so names like "input\x2fby-path" read as written. A component
+// starting with '"' is quoted, and may hold '/' or be empty; a '"' anywhere
+// else in a plain component is just a '"'. Plain components can't be empty,
+// so "a//b" is an error. For a file the last component is its name, so a
+// file's path can't end with '/'.
+//
+// Display on File and TagPath writes the canonical form, which reads back
+// to the same value: a leading '/', tags in order, and only the escapes and
+// quotes needed.
+
+use std::fmt;
+
 pub trait FromStr {
-    fn from_str(path: &str) -> Option<Self> where Self: Sized;
+    fn from_str(path: &str) -> Result<Self, ParseError> where Self: Sized;
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct ParseError {
+    // The byte offset in the path where the problem is.
+    pub position: usize,
+    pub kind: ParseErrorKind,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum ParseErrorKind {
+    // Two '/' in a row, or a '/' before the first component after the
+    // leading one.
+    EmptyComponent,
+    // A file path with no name, e.g. "" or "/photos/".
+    MissingName,
+    // A quoted component with no closing '"'.
+    UnterminatedQuote,
+    // Something other than '/' after a closing '"'.
+    TextAfterQuote,
+    // A '\' at the very end of the path.
+    DanglingEscape,
+    // A component that looks like a comparison but isn't a valid one.
+    BadComparison(String),
+    // A paging cursor that wasn't made by Cursor's Display.
+    BadCursor,
+}
+
+impl ParseError {
+    pub(crate) fn new(position: usize, kind: ParseErrorKind) -> Self {
+        ParseError { position, kind }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at byte {}", self.kind, self.position)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseErrorKind::EmptyComponent => write!(f, "empty component"),
+            ParseErrorKind::MissingName => write!(f, "no file name"),
+            ParseErrorKind::UnterminatedQuote => write!(f, "quote with no closing '\"'"),
+            ParseErrorKind::TextAfterQuote => write!(f, "text after a closing '\"'"),
+            ParseErrorKind::DanglingEscape => write!(f, "'\\' at the end"),
+            ParseErrorKind::BadComparison(text) => write!(f, "{:?} isn't a valid comparison", text),
+            ParseErrorKind::BadCursor => write!(f, "not a paging cursor"),
+        }
+    }
+}
+
+// One component of a path, unescaped.
+#[derive(PartialEq, Eq, Debug)]
+pub(crate) struct Component {
+    pub(crate) text: String,
+    // Where it starts in the path.
+    pub(crate) position: usize,
+    // Quoted components are always plain tags, never comparisons.
+    pub(crate) quoted: bool,
+}
+
+// Split a path into its components. Also says whether it ends with a '/'
+// after the last component.
+pub(crate) fn components(path: &str) -> Result<(Vec<Component>, bool), ParseError> {
+    let mut chars = path.char_indices().peekable();
+    if let Some((_, '/')) = chars.peek() {
+        chars.next();
+    }
+
+    let mut found = vec![];
+    loop {
+        let position = match chars.peek() {
+            Some(&(i, _)) => i,
+            None => return Ok((found, false)),
+        };
+        let mut text = String::new();
+        let quoted = chars.peek().map(|&(_, c)| c) == Some('"');
+
+        if quoted {
+            chars.next();
+            loop {
+                match chars.next() {
+                    Some((_, '"')) => break,
+                    Some((_, '\\')) => match chars.peek() {
+                        Some(&(_, c)) if c == '\\' || c == '"' => {
+                            text.push(c);
+                            chars.next();
+                        }
+                        _ => text.push('\\'),
+                    },
+                    Some((_, c)) => text.push(c),
+                    None => {
+                        return Err(ParseError::new(position, ParseErrorKind::UnterminatedQuote))
+                    }
+                }
+            }
+            match chars.peek() {
+                Some((_, '/')) | None => (),
+                Some(&(i, _)) => return Err(ParseError::new(i, ParseErrorKind::TextAfterQuote)),
+            }
+        } else {
+            while let Some(&(i, c)) = chars.peek() {
+                match c {
+                    '/' => break,
+                    '\\' => {
+                        chars.next();
+                        match chars.peek() {
+                            Some(&(_, c)) if c == '\\' || c == '/' || c == '"' => {
+                                text.push(c);
+                                chars.next();
+                            }
+                            Some(_) => text.push('\\'),
+                            None => {
+                                return Err(ParseError::new(i, ParseErrorKind::DanglingEscape))
+                            }
+                        }
+                    }
+                    c => {
+                        text.push(c);
+                        chars.next();
+                    }
+                }
+            }
+            if text.is_empty() {
+                return Err(ParseError::new(position, ParseErrorKind::EmptyComponent));
+            }
+        }
+
+        found.push(Component {
+            text,
+            position,
+            quoted,
+        });
+        // Past the '/', if any. A '/' at the very end is allowed.
+        match chars.next() {
+            Some(_) if chars.peek().is_none() => return Ok((found, true)),
+            Some(_) => (),
+            None => return Ok((found, false)),
+        }
+    }
+}
+
+// Write a component so components() reads it back as it was.
+pub(crate) fn write_component(f: &mut fmt::Formatter, component: &str) -> fmt::Result {
+    if component.is_empty() {
+        return f.write_str("\"\"");
+    }
+    let mut chars = component.chars().peekable();
+    if component.starts_with('"') {
+        f.write_str("\\\"")?;
+        chars.next();
+    }
+    while let Some(c) = chars.next() {
+        match c {
+            '/' => f.write_str("\\/")?,
+            // Only a '\' that would be read as an escape needs one itself.
+            '\\' => match chars.peek() {
+                Some('\\' | '/' | '"') | None => f.write_str("\\\\")?,
+                Some(_) => f.write_str("\\")?,
+            },
+            c => write!(f, "{}", c)?,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{components, ParseError, ParseErrorKind};
+
+    fn texts(path: &str) -> Result<Vec<String>, ParseError> {
+        components(path).map(|(found, _)| found.into_iter().map(|c| c.text).collect())
+    }
+
+    #[test]
+    fn escapes_and_quotes_should_be_read() {
+        assert_eq!(Ok(vec!["a/b".to_string()]), texts("/a\\/b"));
+        assert_eq!(Ok(vec!["a\\b".to_string()]), texts("a\\\\b"));
+        assert_eq!(Ok(vec!["input\\x2fby".to_string()]), texts("/input\\x2fby"));
+        assert_eq!(
+            Ok(vec!["".to_string(), "x/\"y\\".to_string()]),
+            texts("/\"\"/\"x/\\\"y\\\\\"")
+        );
+        assert_eq!(Ok(vec!["say \"hi\"".to_string()]), texts("say \"hi\""));
+        assert_eq!(Ok(vec!["\"q".to_string()]), texts("\\\"q"));
+    }
+
+    #[test]
+    fn leading_and_trailing_slashes_should_be_optional() {
+        for path in ["a/b", "/a/b", "a/b/", "/a/b/"] {
+            assert_eq!(Ok(vec!["a".to_string(), "b".to_string()]), texts(path));
+        }
+        assert_eq!(Ok((vec![], false)), components(""));
+        assert_eq!(Ok((vec![], false)), components("/"));
+        assert!(components("/a/").unwrap().1);
+        assert!(!components("/a\\/").unwrap().1);
+    }
+
+    #[test]
+    fn malformed_paths_should_say_where() {
+        let error = |position, kind| Err(ParseError { position, kind });
+        assert_eq!(error(3, ParseErrorKind::EmptyComponent), texts("/a//b"));
+        assert_eq!(error(1, ParseErrorKind::EmptyComponent), texts("//"));
+        assert_eq!(error(3, ParseErrorKind::UnterminatedQuote), texts("/a/\"b/c"));
+        assert_eq!(error(6, ParseErrorKind::TextAfterQuote), texts("/a/\"b\"c"));
+        assert_eq!(error(2, ParseErrorKind::DanglingEscape), texts("/a\\"));
+    }
+
+    #[test]
+    fn parse_errors_should_display_readably() {
+        let error = ParseError::new(3, ParseErrorKind::EmptyComponent);
+        assert_eq!("empty component at byte 3", error.to_string());
+        let error = ParseError::new(0, ParseErrorKind::BadComparison("year>".to_string()));
+        assert_eq!("\"year>\" isn't a valid comparison at byte 0", error.to_string());
+        assert_eq!("'\\' at the end", ParseErrorKind::DanglingEscape.to_string());
+    }
 }
@@ -1,27 +1,114 @@
+use crate::nameindex::NameIndex;
 use crate::FileDB;
-use crate::{fdb_trait::GetFileError, File, FileQuery};
+use crate::TagHierarchy;
+use crate::explain::QueryStats;
+use crate::fdb_trait::{AddFileError, Candidates, GetFileError};
+use crate::memory::HeapSize;
+use crate::{File, FileQuery};
 use std::collections::btree_set::{BTreeSet, IntoIter};
 use std::collections::hash_map::HashMap;
 
+#[derive(Clone)]
 pub struct HashTagsDBFS {
     files: HashMap<String, BTreeSet<File>>,
+    hierarchy: TagHierarchy,
+    names: NameIndex,
+}
+
+impl Default for HashTagsDBFS {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl HashTagsDBFS {
     pub fn new() -> HashTagsDBFS {
+        HashTagsDBFS::with_hierarchy(TagHierarchy::new())
+    }
+
+    pub fn with_hierarchy(hierarchy: TagHierarchy) -> HashTagsDBFS {
         HashTagsDBFS {
             files: HashMap::new(),
+            hierarchy,
+            names: NameIndex::new(),
+        }
+    }
+
+    // An estimate of the bytes allocated by the DB and its indexes.
+    pub fn memory_usage(&self) -> usize {
+        self.files.heap_bytes() + self.names.heap_bytes() + self.hierarchy.heap_bytes()
+    }
+}
+
+impl HashTagsDBFS {
+    // The files get_files returns: those the index finds that match the
+    // whole query, counting the work done.
+    fn lookup<F: FileQuery>(&self, query: &F, stats: &mut QueryStats) -> BTreeSet<File> {
+        let candidates = self.candidates(query, stats);
+        let found: BTreeSet<File> = candidates
+            .iter()
+            .filter(|f| query.could_match(f))
+            .cloned()
+            .collect();
+        stats.scanned(candidates.len(), found.len());
+        found
+    }
+
+    // The files the index says could match, counting the lookups made.
+    fn candidates<F: FileQuery>(&self, query: &F, stats: &mut QueryStats) -> BTreeSet<File> {
+        let mut iter = query.tags().iter();
+
+        // Initialise our union with the first value; initialising with
+        // an empty set won't work, as union with empty is empty.
+        let mut result = match iter.next() {
+            Some(t) => {
+                stats.index_lookups += 1;
+                match self.files.get(t) {
+                    Some(first) => {
+                        stats.index_entries_read += first.len();
+                        first.clone()
+                    }
+                    None => return BTreeSet::new(),
+                }
+            }
+            // No tags to look up; every file matches.
+            None => return self.names.all_files(),
+        };
+
+        // Iterate through the rest of the elements
+        for t in iter {
+            stats.index_lookups += 1;
+            if !self.files.contains_key(t) {
+                // If we found a single key that doesn't exist
+                return BTreeSet::new();
+            }
+
+            // Union is lazy, and doesn't support chaining, so we need the
+            // whole thing, so we have to clone and collect.
+            stats.index_entries_read += self.files[t].len();
+            result = result.union(&self.files[t]).cloned().collect();
         }
+
+        result
     }
 }
 
 impl FileDB for HashTagsDBFS {
     type FileIterator = IntoIter<File>;
 
-    fn add_file(&mut self, new_file: &File) -> Option<()> {
+    fn add_file(&mut self, new_file: &File) -> Result<(), AddFileError> {
+        AddFileError::check_tags(new_file)?;
+        // Index every ancestor of the file's tags, so queries on them find it.
+        let expanded = self.hierarchy.expand_file(new_file);
+        let new_file = expanded.as_ref();
         match self.get_file(new_file) {
-            Ok(_) => None,
-            Err(GetFileError::TooManyFiles) => None,
+            Ok(existing) if &existing == new_file => Err(AddFileError::Duplicate),
+            // Another file of this name has every tag of the new one, so a
+            // lookup by the new file would be ambiguous.
+            Ok(existing) => Err(AddFileError::Conflict(vec![existing])),
+            Err(GetFileError::TooManyFiles(ambiguity)) => {
+                Err(AddFileError::Conflict(ambiguity.candidates))
+            }
             Err(GetFileError::NoSuchFile) => {
                 for t in new_file.tags() {
                     match self.files.get_mut(t) {
@@ -35,54 +122,37 @@ impl FileDB for HashTagsDBFS {
                         }
                     }
                 }
-                Some(())
+                self.names.insert(new_file);
+                Ok(())
             }
         }
     }
 
     fn get_files<F: FileQuery>(&self, query: &F) -> Self::FileIterator {
-        let mut iter = query.tags().into_iter();
-
-        // Initialise our union with the first value; initialising with
-        // an empty set won't work, as union with empty is empty.
-        let mut result = self.files[iter.next().unwrap()].clone();
-
-        // Iterate through the rest of the elements
-        for t in iter {
-            if !self.files.contains_key(t) {
-                // If we found a single key that doesn't exist
-                return BTreeSet::new().into_iter();
-            }
-
-            // Union is lazy, and doesn't support chaining, so we need the
-            // whole thing, so we have to clone and collect.
-            result = result.union(&self.files[t]).cloned().collect();
-        }
+        self.lookup(query, &mut QueryStats::default()).into_iter()
+    }
 
-        result.into_iter()
+    fn explain<F: FileQuery>(&self, query: &F) -> QueryStats {
+        let mut stats = QueryStats::default();
+        self.lookup(query, &mut stats);
+        stats
     }
 
     fn get_file<F: FileQuery>(&self, query: &F) -> Result<File, GetFileError> {
-        // Just fail if the query isn't a well formed file.
-        let mut result: Result<File, GetFileError> = Err(GetFileError::NoSuchFile);
+        if let Some(result) = self.names.get_file(query) {
+            return result;
+        }
+
+        let mut candidates = Candidates::new();
 
         for k in query.tags() {
             match self.files.get(k) {
                 Some(file_set) => {
                     for f in file_set {
-                        if query.could_match(&f) {
-                            match &result {
-                                Ok(current) => {
-                                    if current != f {
-                                        // At least one tag has multiple matches, so we fail.
-                                        return Err(GetFileError::TooManyFiles);
-                                    }
-                                }
-                                Err(GetFileError::NoSuchFile) => {
-                                    result = Ok(f.clone());
-                                }
-                                Err(GetFileError::TooManyFiles) => {}
-                            }
+                        // The same file is seen once per tag; candidates
+                        // ignores the repeats.
+                        if query.could_match(f) && !candidates.push(f.clone()) {
+                            return candidates.into_result();
                         }
                     }
                 }
@@ -93,8 +163,26 @@ impl FileDB for HashTagsDBFS {
                 }
             }
         }
-        // Either we found a single match, or no match at all, so return what
-        // we have.
-        result
+        // Either we found a single match, no match at all, or too many.
+        candidates.into_result()
+    }
+
+    fn remove_file<F: FileQuery>(&mut self, query: &F) -> Result<File, GetFileError> {
+        let file = self.get_file(query)?;
+        for t in file.tags() {
+            if let Some(file_set) = self.files.get_mut(t) {
+                file_set.remove(&file);
+                // Drop tags no file has any more, so queries on them fail fast.
+                if file_set.is_empty() {
+                    self.files.remove(t);
+                }
+            }
+        }
+        self.names.remove(&file);
+        Ok(file)
+    }
+
+    fn find_by_name(&self, name: &str) -> Vec<File> {
+        self.names.find(name)
     }
 }
@@ -1,44 +1,43 @@
+use crate::nameindex::NameIndex;
 use crate::FileDB;
+use crate::TagHierarchy;
 use crate::TagSet;
-use crate::{fdb_trait::GetFileError, File, FileQuery};
+use crate::explain::QueryStats;
+use crate::fdb_trait::{AddFileError, Candidates, GetFileError};
+use crate::memory::HeapSize;
+use crate::{File, FileQuery};
 use std::collections::btree_set::{BTreeSet, IntoIter};
 use std::collections::hash_map::HashMap;
 
+#[derive(Clone)]
 pub struct HashTags2DBFS {
     files: HashMap<String, BTreeSet<String>>,
+    hierarchy: TagHierarchy,
+    names: NameIndex,
+}
+
+impl Default for HashTags2DBFS {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl HashTags2DBFS {
     pub fn new() -> HashTags2DBFS {
+        HashTags2DBFS::with_hierarchy(TagHierarchy::new())
+    }
+
+    pub fn with_hierarchy(hierarchy: TagHierarchy) -> HashTags2DBFS {
         HashTags2DBFS {
             files: HashMap::new(),
+            hierarchy,
+            names: NameIndex::new(),
         }
     }
 
-    // Assumes we've already checked all keys in query exist.
-    fn get_tags_not_in_query<F: FileQuery>(&self, query: &F) -> BTreeSet<String> {
-        self.files
-            .keys()
-            .filter(|&x| !query.tags().contains(x))
-            .cloned()
-            .collect()
-    }
-
-    // Assumes we've already checked all keys in query exist.
-    fn resolve_actual_file(&self, file: &File) -> File {
-        let all_extra_tags = self.get_tags_not_in_query(file);
-        let file_name = &file.name;
-        let mut extra_tags = BTreeSet::<String>::new();
-
-        for t in &all_extra_tags {
-            if self.files[t].contains(file_name) {
-                extra_tags.insert(t.to_string());
-            }
-        }
-
-        let all_file_tags: BTreeSet<String> = file.tags.union(&extra_tags).cloned().collect();
-
-        File::new(file.name.clone(), all_file_tags)
+    // An estimate of the bytes allocated by the DB and its indexes.
+    pub fn memory_usage(&self) -> usize {
+        self.files.heap_bytes() + self.names.heap_bytes() + self.hierarchy.heap_bytes()
     }
 
     fn do_all_keys_exist(&self, to_check: &TagSet) -> bool {
@@ -51,28 +50,86 @@ impl HashTags2DBFS {
         self.get_key_set().is_superset(to_check)
     }
 
-    fn file_name_to_file(&self, file_name: &str) -> File {
-        let keys = self
-            .files
-            .iter()
-            .filter(|(_, v)| v.contains(file_name))
-            .map(|(k, _)| k);
+    fn get_key_set(&self) -> BTreeSet<String> {
+        self.files.keys().cloned().collect()
+    }
 
-        File::new(file_name.to_string(), BTreeSet::from_iter(keys.cloned()))
+    // The files get_files returns: those the index finds that match the
+    // whole query, counting the work done.
+    fn lookup<F: FileQuery>(&self, query: &F, stats: &mut QueryStats) -> BTreeSet<File> {
+        let candidates = self.candidates(query, stats);
+        let found: BTreeSet<File> = candidates
+            .iter()
+            .filter(|f| query.could_match(f))
+            .cloned()
+            .collect();
+        stats.scanned(candidates.len(), found.len());
+        found
     }
 
-    fn get_key_set(&self) -> BTreeSet<String> {
-        self.files.keys().cloned().collect()
+    // The files the index says could match, counting the lookups made.
+    // Each name found is looked up once more in the name index.
+    fn candidates<F: FileQuery>(&self, query: &F, stats: &mut QueryStats) -> BTreeSet<File> {
+        let mut iter = query.tags().iter();
+
+        // Initialise our union with the first value; initialising with
+        // an empty set won't work, as union with empty is empty.
+        let mut file_names = match iter.next() {
+            Some(t) => {
+                stats.index_lookups += 1;
+                match self.files.get(t) {
+                    Some(first) => {
+                        stats.index_entries_read += first.len();
+                        first.clone()
+                    }
+                    None => return BTreeSet::new(),
+                }
+            }
+            // No tags to look up; every file matches.
+            None => return self.names.all_files(),
+        };
+
+        // Iterate through the rest of the elements
+        for t in iter {
+            stats.index_lookups += 1;
+            if !self.files.contains_key(t) {
+                // If we found a single key that doesn't exist, fail
+                return BTreeSet::new();
+            }
+
+            // Union is lazy, and doesn't support chaining, so we need the
+            // whole thing, so we have to clone and collect.
+            stats.index_entries_read += self.files[t].len();
+            file_names = file_names.union(&self.files[t]).cloned().collect();
+        }
+
+        // Files of the same name share entries in the tag sets, so take
+        // each one's own tags from the name index rather than merging them.
+        let mut result = BTreeSet::<File>::new();
+        for f in file_names {
+            stats.index_lookups += 1;
+            result.extend(self.names.find(&f));
+        }
+        result
     }
 }
 
 impl FileDB for HashTags2DBFS {
     type FileIterator = IntoIter<File>;
 
-    fn add_file(&mut self, new_file: &File) -> Option<()> {
+    fn add_file(&mut self, new_file: &File) -> Result<(), AddFileError> {
+        AddFileError::check_tags(new_file)?;
+        // Index every ancestor of the file's tags, so queries on them find it.
+        let expanded = self.hierarchy.expand_file(new_file);
+        let new_file = expanded.as_ref();
         match self.get_file(new_file) {
-            Ok(_) => None,
-            Err(GetFileError::TooManyFiles) => None,
+            Ok(existing) if &existing == new_file => Err(AddFileError::Duplicate),
+            // Another file of this name has every tag of the new one, so a
+            // lookup by the new file would be ambiguous.
+            Ok(existing) => Err(AddFileError::Conflict(vec![existing])),
+            Err(GetFileError::TooManyFiles(ambiguity)) => {
+                Err(AddFileError::Conflict(ambiguity.candidates))
+            }
             Err(GetFileError::NoSuchFile) => {
                 for t in new_file.tags() {
                     match self.files.get_mut(t) {
@@ -86,78 +143,66 @@ impl FileDB for HashTags2DBFS {
                         }
                     }
                 }
+                self.names.insert(new_file);
 
-                Some(())
+                Ok(())
             }
         }
     }
 
     fn get_files<F: FileQuery>(&self, query: &F) -> Self::FileIterator {
-        let mut iter = query.tags().into_iter();
-
-        // Initialise our union with the first value; initialising with
-        // an empty set won't work, as union with empty is empty.
-        let mut file_names = self.files[iter.next().unwrap()].clone();
+        self.lookup(query, &mut QueryStats::default()).into_iter()
+    }
 
-        // Iterate through the rest of the elements
-        for t in iter {
-            if !self.files.contains_key(t) {
-                // If we found a single key that doesn't exist, fail
-                return BTreeSet::new().into_iter();
-            }
+    fn explain<F: FileQuery>(&self, query: &F) -> QueryStats {
+        let mut stats = QueryStats::default();
+        self.lookup(query, &mut stats);
+        stats
+    }
 
-            // Union is lazy, and doesn't support chaining, so we need the
-            // whole thing, so we have to clone and collect.
-            file_names = file_names.union(&self.files[t]).cloned().collect();
+    fn get_file<F: FileQuery>(&self, query: &F) -> Result<File, GetFileError> {
+        if let Some(result) = self.names.get_file(query) {
+            return result;
         }
 
-        let mut result = BTreeSet::<File>::new();
+        if !self.do_all_keys_exist(query.tags()) {
+            return Err(GetFileError::NoSuchFile);
+        }
 
-        for f in file_names {
-            let tmp_file = File::new(f.clone(), query.tags().clone());
-            result.insert(self.resolve_actual_file(&tmp_file));
+        // Named queries are answered by the name index above, which, unlike
+        // the tag sets, keeps files of the same name with different tags
+        // apart.
+        let mut candidates = Candidates::new();
+        for f in self.get_files(query) {
+            if !candidates.push(f) {
+                break;
+            }
         }
-        result.into_iter()
+        candidates.into_result()
     }
 
-    fn get_file<F: FileQuery>(&self, query: &F) -> Result<File, GetFileError> {
-        if !self.do_all_keys_exist(query.tags()) {
-            return Err(GetFileError::NoSuchFile);
-        }
+    fn remove_file<F: FileQuery>(&mut self, query: &F) -> Result<File, GetFileError> {
+        let file = self.get_file(query)?;
+        self.names.remove(&file);
 
-        if let Some(name) = query.name() {
-            // We only need to match the first tag, as if we can't
-            // find the named file for ANY tag, then it's a failure.
-            // We check for all tags in the query later.
-            if let Some(k) = query.tags().iter().next() {
-                if let Some(file_set) = self.files.get(k) {
-                    if file_set.contains(name) {
-                        let candidate = self.file_name_to_file(name);
-                        // Because we only store the file name for each tag,
-                        // we can only really have one file of each name on the
-                        // whole system. We therefore know that if this one is
-                        // a match, then it's the right one, and if it's not,
-                        // then there's no match. This is a bug; we should
-                        // support multiple files of the same name.
-                        if query.could_match(&candidate) {
-                            return Ok(candidate);
-                        }
-                    }
-                }
+        // Tags only hold names, so a tag keeps the name while any other file
+        // of that name still has it.
+        let remaining = self.names.find(&file.name);
+        for t in file.tags() {
+            if remaining.iter().any(|f| f.tags.contains(t)) {
+                continue;
             }
-            // This is a fallthrough, if we don't get a match for any tag
-            // (or if the given tags are empty, which we ignore by default...)
-            return Err(GetFileError::NoSuchFile);
-        } else {
-            let mut possible_files = self.get_files(query);
-            if possible_files.len() == 1 {
-                // We know there's exactly 1, so next is safe to unwrap.
-                return Ok(possible_files.next().unwrap());
-            } else if possible_files.len() > 1 {
-                return Err(GetFileError::TooManyFiles);
-            } else {
-                return Err(GetFileError::NoSuchFile);
+            if let Some(file_names) = self.files.get_mut(t) {
+                file_names.remove(&file.name);
+                if file_names.is_empty() {
+                    self.files.remove(t);
+                }
             }
         }
+        Ok(file)
+    }
+
+    fn find_by_name(&self, name: &str) -> Vec<File> {
+        self.names.find(name)
     }
 }
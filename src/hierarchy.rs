@@ -0,0 +1,146 @@
+use crate::memory::HeapSize;
+use crate::File;
+use crate::TagSet;
+use std::borrow::Cow;
+use std::collections::btree_set::BTreeSet;
+use std::collections::hash_map::HashMap;
+
+// Describes which tags imply other tags. A tag's parent is taken from the
+// explicit parent map if it has an entry, otherwise from the text before the
+// last separator, e.g. "media/photo/raw" has the parent "media/photo".
+//
+// Backends expand the tags of every file they store to include all
+// ancestors, so a query for "media" will find a file tagged only with
+// "media/photo/raw".
+#[derive(Debug, Clone, Default)]
+pub struct TagHierarchy {
+    separator: Option<char>,
+    parents: HashMap<String, String>,
+}
+
+impl TagHierarchy {
+    // A flat hierarchy; no tag implies any other.
+    pub fn new() -> Self {
+        TagHierarchy {
+            separator: None,
+            parents: HashMap::new(),
+        }
+    }
+
+    pub fn with_separator(separator: char) -> Self {
+        TagHierarchy {
+            separator: Some(separator),
+            parents: HashMap::new(),
+        }
+    }
+
+    pub fn with_parent(mut self, child: &str, parent: &str) -> Self {
+        self.parents.insert(child.to_string(), parent.to_string());
+        self
+    }
+
+    pub fn is_flat(&self) -> bool {
+        self.separator.is_none() && self.parents.is_empty()
+    }
+
+    pub fn parent(&self, tag: &str) -> Option<String> {
+        if let Some(parent) = self.parents.get(tag) {
+            return Some(parent.clone());
+        }
+        let (parent, _) = tag.rsplit_once(self.separator?)?;
+        if parent.is_empty() {
+            None
+        } else {
+            Some(parent.to_string())
+        }
+    }
+
+    // All ancestors of a tag, nearest first. The explicit parent map may
+    // contain cycles, so stop as soon as we see a tag twice.
+    pub fn ancestors(&self, tag: &str) -> Vec<String> {
+        let mut seen = BTreeSet::from([tag.to_string()]);
+        let mut result = vec![];
+        let mut current = tag.to_string();
+        while let Some(parent) = self.parent(&current) {
+            if !seen.insert(parent.clone()) {
+                break;
+            }
+            result.push(parent.clone());
+            current = parent;
+        }
+        result
+    }
+
+    pub fn expand(&self, tags: &TagSet) -> TagSet {
+        let mut result = tags.clone();
+        for t in tags {
+            result.extend(self.ancestors(t));
+        }
+        result
+    }
+
+    // Borrows the file unchanged when there is nothing to expand, so flat
+    // databases don't pay for a clone on every add.
+    pub fn expand_file<'a>(&self, file: &'a File) -> Cow<'a, File> {
+        if self.is_flat() {
+            Cow::Borrowed(file)
+        } else {
+            Cow::Owned(File::new(file.name.clone(), self.expand(&file.tags)))
+        }
+    }
+
+    pub(crate) fn heap_bytes(&self) -> usize {
+        self.parents.heap_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TagHierarchy;
+    use crate::TagSet;
+
+    #[test]
+    fn should_find_ancestors_by_separator() {
+        let h = TagHierarchy::with_separator('/');
+
+        assert_eq!(
+            vec!["media/photo".to_string(), "media".to_string()],
+            h.ancestors("media/photo/raw")
+        );
+        assert_eq!(Vec::<String>::new(), h.ancestors("media"));
+    }
+
+    #[test]
+    fn should_prefer_explicit_parent() {
+        let h = TagHierarchy::with_separator('/').with_parent("media/photo", "images");
+
+        assert_eq!(
+            vec!["media/photo".to_string(), "images".to_string()],
+            h.ancestors("media/photo/raw")
+        );
+    }
+
+    #[test]
+    fn should_stop_at_parent_cycles() {
+        let h = TagHierarchy::new()
+            .with_parent("a", "b")
+            .with_parent("b", "c")
+            .with_parent("c", "a");
+
+        assert_eq!(vec!["b".to_string(), "c".to_string()], h.ancestors("a"));
+    }
+
+    #[test]
+    fn should_expand_tag_set() {
+        let h = TagHierarchy::with_separator(':');
+        let tags = TagSet::from(["media:photo".to_string(), "holiday".to_string()]);
+
+        let expected = TagSet::from([
+            "media:photo".to_string(),
+            "media".to_string(),
+            "holiday".to_string(),
+        ]);
+
+        assert_eq!(expected, h.expand(&tags));
+    }
+}
@@ -0,0 +1,152 @@
+use super::{percent_encode, Count, ErrorBody, Facet, FileRecord, Page};
+use crate::File;
+use serde::de::DeserializeOwned;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+
+#[derive(Debug)]
+pub enum HttpError {
+    Io(io::Error),
+    // The server answered with an error status.
+    Status(u16, ErrorBody),
+    // The server's answer couldn't be understood.
+    BadResponse(String),
+}
+
+impl From<io::Error> for HttpError {
+    fn from(error: io::Error) -> Self {
+        HttpError::Io(error)
+    }
+}
+
+// A client for HttpService. Each call makes one request on a new
+// connection.
+pub struct HttpClient {
+    address: SocketAddr,
+}
+
+impl HttpClient {
+    pub fn new(address: SocketAddr) -> Self {
+        HttpClient { address }
+    }
+
+    pub fn add_file(&self, file: &File) -> Result<(), HttpError> {
+        let body = serde_json::to_string(&FileRecord::from(file)).unwrap();
+        self.request::<FileRecord>("POST", "/files", &body)
+            .map(|_| ())
+    }
+
+    // Remove the single file matching the path, and return it.
+    pub fn remove_file(&self, path: &str) -> Result<File, HttpError> {
+        self.request::<FileRecord>("DELETE", &url("/file", path, &[]), "")
+            .map(File::from)
+    }
+
+    pub fn get_file(&self, path: &str) -> Result<File, HttpError> {
+        self.request::<FileRecord>("GET", &url("/file", path, &[]), "")
+            .map(File::from)
+    }
+
+    // One page of the files matching the query, starting after the
+    // cursor from the page before, or at the start.
+    pub fn get_files(
+        &self,
+        query: &str,
+        after: Option<&str>,
+        limit: usize,
+    ) -> Result<Page, HttpError> {
+        let mut params = vec![("limit", limit.to_string())];
+        if let Some(after) = after {
+            params.push(("after", after.to_string()));
+        }
+        self.request("GET", &url("/files", query, &params), "")
+    }
+
+    // Every file matching the query, fetched a page at a time.
+    pub fn get_all_files(&self, query: &str) -> Result<Vec<File>, HttpError> {
+        let mut files = vec![];
+        let mut after = None;
+        loop {
+            let page = self.get_files(query, after.as_deref(), super::MAX_PAGE_SIZE)?;
+            files.extend(page.files.into_iter().map(File::from));
+            match page.next {
+                Some(next) => after = Some(next),
+                None => return Ok(files),
+            }
+        }
+    }
+
+    pub fn count(&self, query: &str) -> Result<usize, HttpError> {
+        self.request::<Count>("GET", &url("/count", query, &[]), "")
+            .map(|c| c.count)
+    }
+
+    pub fn facets(&self, query: &str, limit: usize) -> Result<Vec<Facet>, HttpError> {
+        self.request(
+            "GET",
+            &url("/facets", query, &[("limit", limit.to_string())]),
+            "",
+        )
+    }
+
+    fn request<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        target: &str,
+        body: &str,
+    ) -> Result<T, HttpError> {
+        let mut stream = TcpStream::connect(self.address)?;
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\
+             Content-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            method,
+            target,
+            self.address,
+            body.len(),
+            body
+        )?;
+        stream.flush()?;
+
+        let mut reader = BufReader::new(stream);
+        let mut status_line = String::new();
+        reader.read_line(&mut status_line)?;
+        let status: u16 = status_line
+            .split_whitespace()
+            .nth(1)
+            .and_then(|s| s.parse().ok())
+            .ok_or_else(|| HttpError::BadResponse(status_line.clone()))?;
+
+        // The server closes the connection after the body, so read to the
+        // end rather than trusting the headers.
+        let mut line = String::new();
+        loop {
+            line.clear();
+            reader.read_line(&mut line)?;
+            if line.trim_end().is_empty() {
+                break;
+            }
+        }
+        let mut body = String::new();
+        reader.read_to_string(&mut body)?;
+
+        let bad = |e: serde_json::Error| HttpError::BadResponse(e.to_string());
+        if (200..300).contains(&status) {
+            serde_json::from_str(&body).map_err(bad)
+        } else {
+            Err(HttpError::Status(
+                status,
+                serde_json::from_str(&body).map_err(bad)?,
+            ))
+        }
+    }
+}
+
+fn url(route: &str, path: &str, params: &[(&str, String)]) -> String {
+    let mut url = format!("{}/{}", route, percent_encode(path.trim_start_matches('/')));
+    for (i, (name, value)) in params.iter().enumerate() {
+        url.push(if i == 0 { '?' } else { '&' });
+        url.push_str(&format!("{}={}", name, value));
+    }
+    url
+}
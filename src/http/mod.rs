@@ -0,0 +1,415 @@
+pub(crate) mod client;
+
+use crate::fdb_trait::{AddFileError, GetFileError};
+use crate::fromstr::{FromStr, ParseError};
+use crate::{Cursor, File, FileDB, FileQuery, Query, QueryOptions};
+use serde::{Deserialize, Serialize};
+use std::collections::btree_map::BTreeMap;
+use std::io::{self, Read};
+use std::net::TcpListener;
+use std::sync::Mutex;
+
+// Pages are this long unless the client asks otherwise.
+pub const DEFAULT_PAGE_SIZE: usize = 100;
+// And never longer than this.
+pub const MAX_PAGE_SIZE: usize = 1000;
+// Request bodies longer than this are refused, so one request can't use up
+// the server's memory.
+pub const MAX_BODY: u64 = 1 << 20;
+
+// A file as it is sent over the wire. The name and tags are kept apart, so
+// a name may contain '/'.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct FileRecord {
+    pub name: String,
+    pub tags: Vec<String>,
+}
+
+impl From<&File> for FileRecord {
+    fn from(file: &File) -> Self {
+        FileRecord {
+            name: file.name.clone(),
+            tags: file.tags.iter().cloned().collect(),
+        }
+    }
+}
+
+impl From<FileRecord> for File {
+    fn from(record: FileRecord) -> Self {
+        File::new(record.name, record.tags.into_iter().collect())
+    }
+}
+
+// One page of the files matching a query, sorted by name then tags.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct Page {
+    pub files: Vec<FileRecord>,
+    // The cursor to fetch the next page after, if there is one.
+    pub next: Option<String>,
+}
+
+// How many of the files matching a query have a tag they didn't ask for.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct Facet {
+    pub tag: String,
+    pub count: usize,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub(crate) struct Count {
+    pub(crate) count: usize,
+}
+
+// The body of every error response.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct ErrorBody {
+    pub error: String,
+    // The files that made a request ambiguous or conflicting.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub candidates: Vec<FileRecord>,
+}
+
+pub(crate) struct Response {
+    pub(crate) status: u16,
+    pub(crate) body: String,
+}
+
+impl Response {
+    fn json<T: Serialize>(status: u16, value: &T) -> Self {
+        Response {
+            status,
+            body: serde_json::to_string(value).unwrap(),
+        }
+    }
+
+    fn error(status: u16, error: &str) -> Self {
+        Response::error_with(status, error, &[])
+    }
+
+    fn error_with(status: u16, error: &str, candidates: &[File]) -> Self {
+        Response::json(
+            status,
+            &ErrorBody {
+                error: error.to_string(),
+                candidates: candidates.iter().map(FileRecord::from).collect(),
+            },
+        )
+    }
+}
+
+// Serves a database as JSON over HTTP. Queries are given in the URL as
+// paths, as accepted by Query::from_str, e.g. "/files/photos/year>=2020".
+//
+//   GET    /files/<query>?after=&limit=    a Page of matching files
+//   GET    /file/<path>                    the single file matching the path
+//   POST   /files                          add the FileRecord in the body
+//   DELETE /file/<path>                    remove the file matching the path
+//   GET    /count/<query>                  {"count": n}
+//   GET    /facets/<query>?limit=          Facets, most common first
+//
+// Errors are an ErrorBody, with status 400 for a bad request, 404 if no
+// file matches, 409 for an ambiguous path or a file that can't be added,
+// and 413 for a body longer than MAX_BODY.
+pub struct HttpService<DB: FileDB> {
+    db: Mutex<DB>,
+}
+
+impl<DB: FileDB> HttpService<DB> {
+    pub fn new(db: DB) -> Self {
+        HttpService { db: Mutex::new(db) }
+    }
+
+    pub fn into_inner(self) -> DB {
+        self.db.into_inner().unwrap_or_else(|p| p.into_inner())
+    }
+
+    // Answer requests on the listener until it fails.
+    pub fn serve(&self, listener: TcpListener) -> io::Result<()> {
+        let server = tiny_http::Server::from_listener(listener, None)
+            .map_err(|e| io::Error::other(e.to_string()))?;
+        let content_type =
+            tiny_http::Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap();
+
+        for mut request in server.incoming_requests() {
+            let response = match read_body(request.as_reader()) {
+                Ok(body) => self.handle(request.method().as_str(), request.url(), &body),
+                Err(response) => response,
+            };
+            let response = tiny_http::Response::from_string(response.body)
+                .with_status_code(response.status)
+                .with_header(content_type.clone());
+            // The client going away doesn't stop us serving others.
+            let _ = request.respond(response);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn handle(&self, method: &str, url: &str, body: &str) -> Response {
+        let (path, params) = match url.split_once('?') {
+            Some((path, params)) => (path, params),
+            None => (url, ""),
+        };
+        let (route, rest) = match path.trim_start_matches('/').split_once('/') {
+            Some((route, rest)) => (route, rest),
+            None => (path.trim_start_matches('/'), ""),
+        };
+        let rest = match percent_decode(rest) {
+            Some(rest) => rest,
+            None => return Response::error(400, "bad percent encoding"),
+        };
+        let params = match parse_params(params) {
+            Some(params) => params,
+            None => return Response::error(400, "bad query parameters"),
+        };
+
+        let mut db = self.db.lock().unwrap_or_else(|p| p.into_inner());
+        match (method, route) {
+            ("GET", "files") => with_query(&rest, |q| list_files(&*db, q, &params)),
+            ("GET", "file") => with_file(&rest, |f| match db.get_file(f) {
+                Ok(file) => Response::json(200, &FileRecord::from(&file)),
+                Err(error) => get_error(error),
+            }),
+            ("POST", "files") if rest.is_empty() => add_file(&mut *db, body),
+            ("DELETE", "file") => with_file(&rest, |f| match db.remove_file(f) {
+                Ok(file) => Response::json(200, &FileRecord::from(&file)),
+                Err(error) => get_error(error),
+            }),
+            ("GET", "count") => with_query(&rest, |q| {
+                Response::json(
+                    200,
+                    &Count {
+                        count: db.get_files(q).count(),
+                    },
+                )
+            }),
+            ("GET", "facets") => with_query(&rest, |q| facets(&*db, q, &params)),
+            (_, "files" | "file" | "count" | "facets") => {
+                Response::error(405, "method not allowed")
+            }
+            _ => Response::error(404, "no such endpoint"),
+        }
+    }
+}
+
+// Read up to MAX_BODY bytes of text, and one more to tell if there was
+// too much.
+fn read_body<R: Read>(reader: R) -> Result<String, Response> {
+    let mut body = String::new();
+    match reader.take(MAX_BODY + 1).read_to_string(&mut body) {
+        Ok(_) if body.len() as u64 > MAX_BODY => Err(Response::error(413, "body too large")),
+        Ok(_) => Ok(body),
+        Err(_) => Err(Response::error(400, "body isn't UTF-8")),
+    }
+}
+
+fn with_query<F: FnOnce(&Query) -> Response>(path: &str, f: F) -> Response {
+    match Query::from_str(path) {
+        Ok(query) => f(&query),
+        Err(error) => parse_error("query", &error),
+    }
+}
+
+fn with_file<F: FnOnce(&File) -> Response>(path: &str, f: F) -> Response {
+    match File::from_str(path) {
+        Ok(file) => f(&file),
+        Err(error) => parse_error("file path", &error),
+    }
+}
+
+// Say what was wrong with the text, and where.
+fn parse_error(what: &str, error: &ParseError) -> Response {
+    Response::error(400, &format!("bad {}: {}", what, error))
+}
+
+fn get_error(error: GetFileError) -> Response {
+    match error {
+        GetFileError::NoSuchFile => Response::error(404, "no such file"),
+        GetFileError::TooManyFiles(ambiguity) => {
+            Response::error_with(409, "too many files", &ambiguity.candidates)
+        }
+    }
+}
+
+fn add_file<DB: FileDB>(db: &mut DB, body: &str) -> Response {
+    let file: File = match serde_json::from_str::<FileRecord>(body) {
+        Ok(record) => record.into(),
+        Err(e) => return Response::error(400, &e.to_string()),
+    };
+    match db.add_file(&file) {
+        Ok(()) => Response::json(201, &FileRecord::from(&file)),
+        Err(AddFileError::Duplicate) => Response::error(409, "duplicate file"),
+        Err(AddFileError::Conflict(files)) => {
+            Response::error_with(409, "conflicts with existing files", &files)
+        }
+        Err(AddFileError::InvalidTag(tag)) => {
+            Response::error(400, &format!("invalid tag {:?}", tag))
+        }
+        Err(AddFileError::Capacity) => Response::error(507, "no room for file"),
+    }
+}
+
+fn list_files<DB: FileDB>(db: &DB, query: &Query, params: &BTreeMap<String, String>) -> Response {
+    let limit = match param(params, "limit", DEFAULT_PAGE_SIZE) {
+        Some(limit) => limit.min(MAX_PAGE_SIZE),
+        None => return Response::error(400, "bad limit"),
+    };
+    let mut options = QueryOptions::new().with_limit(limit);
+    if let Some(after) = params.get("after") {
+        match Cursor::from_str(after) {
+            Ok(cursor) => options = options.after(cursor),
+            Err(error) => return parse_error("cursor", &error),
+        }
+    }
+
+    // Pages carry on from the last file of the one before, so they only
+    // ever hold limit files, and stay in step as files come and go.
+    let page = db.get_page(query, &options);
+    Response::json(
+        200,
+        &Page {
+            files: page.files.iter().map(FileRecord::from).collect(),
+            next: page.next.map(|cursor| cursor.to_string()),
+        },
+    )
+}
+
+fn facets<DB: FileDB>(db: &DB, query: &Query, params: &BTreeMap<String, String>) -> Response {
+    let limit = match param(params, "limit", usize::MAX) {
+        Some(limit) => limit,
+        None => return Response::error(400, "bad limit"),
+    };
+
+    let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+    for f in db.get_files(query) {
+        for t in f.tags {
+            if !query.tags().contains(&t) {
+                *counts.entry(t).or_default() += 1;
+            }
+        }
+    }
+
+    let mut facets: Vec<Facet> = counts
+        .into_iter()
+        .map(|(tag, count)| Facet { tag, count })
+        .collect();
+    // Most common first; the sort is stable, so ties stay in tag order.
+    facets.sort_by_key(|f| std::cmp::Reverse(f.count));
+    facets.truncate(limit);
+    Response::json(200, &facets)
+}
+
+fn param(params: &BTreeMap<String, String>, name: &str, default: usize) -> Option<usize> {
+    match params.get(name) {
+        Some(value) => value.parse().ok(),
+        None => Some(default),
+    }
+}
+
+fn parse_params(params: &str) -> Option<BTreeMap<String, String>> {
+    params
+        .split('&')
+        .filter(|x| !x.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            Some((percent_decode(key)?, percent_decode(value)?))
+        })
+        .collect()
+}
+
+// Escape everything but unreserved characters and '/', so a path can be
+// put in a URL as it is.
+pub(crate) fn percent_encode(path: &str) -> String {
+    let mut encoded = String::new();
+    for b in path.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                encoded.push(b as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    encoded
+}
+
+pub(crate) fn percent_decode(encoded: &str) -> Option<String> {
+    let mut bytes = vec![];
+    let mut iter = encoded.bytes();
+    while let Some(b) = iter.next() {
+        if b == b'%' {
+            let hex = [iter.next()?, iter.next()?];
+            bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else {
+            bytes.push(b);
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{percent_decode, percent_encode, read_body, HttpService, MAX_BODY};
+    use crate::{fromstr::FromStr, File, FileDB, NaiveDBFS};
+
+    #[test]
+    fn percent_encoding_should_round_trip() {
+        let path = "/year>=2020/100% done/naïve?#.txt";
+        let encoded = percent_encode(path);
+
+        assert_eq!(
+            "/year%3E%3D2020/100%25%20done/na%C3%AFve%3F%23.txt",
+            encoded
+        );
+        assert_eq!(Some(path.to_string()), percent_decode(&encoded));
+        assert_eq!(None, percent_decode("%4"));
+        assert_eq!(None, percent_decode("%zz"));
+    }
+
+    #[test]
+    fn bodies_should_be_capped() {
+        let limit = MAX_BODY as usize;
+        assert!(read_body("x".repeat(limit).as_bytes()).is_ok_and(|b| b.len() == limit));
+        match read_body("x".repeat(limit + 1).as_bytes()) {
+            Err(response) => assert_eq!(413, response.status),
+            Ok(_) => panic!("Expected the body to be refused"),
+        }
+    }
+
+    #[test]
+    fn service_should_reject_bad_requests() {
+        let mut db = NaiveDBFS::new();
+        db.add_file(&File::from_str("/a/one.txt").unwrap()).unwrap();
+        let service = HttpService::new(db);
+
+        assert_eq!(200, service.handle("GET", "/files/a", "").status);
+        assert_eq!(404, service.handle("GET", "/nothing", "").status);
+        assert_eq!(405, service.handle("PUT", "/files/a", "").status);
+        assert_eq!(400, service.handle("GET", "/files/year>", "").status);
+        assert_eq!(400, service.handle("GET", "/files/a?limit=ten", "").status);
+        assert_eq!(400, service.handle("GET", "/files/a?after=zz", "").status);
+        assert_eq!(400, service.handle("POST", "/files", "{}").status);
+        assert_eq!(400, service.handle("GET", "/file/", "").status);
+    }
+
+    #[test]
+    fn service_should_say_where_a_path_is_bad() {
+        let service = HttpService::new(NaiveDBFS::new());
+        let error = |path: &str| service.handle("GET", path, "").body;
+
+        assert_eq!(
+            r#"{"error":"bad query: \"year>\" isn't a valid comparison at byte 0"}"#,
+            error("/files/year>")
+        );
+        assert_eq!(
+            r#"{"error":"bad file path: empty component at byte 2"}"#,
+            error("/file/a//b")
+        );
+        assert_eq!(
+            r#"{"error":"bad file path: no file name at byte 0"}"#,
+            error("/file/")
+        );
+        assert_eq!(
+            r#"{"error":"bad cursor: not a paging cursor at byte 0"}"#,
+            error("/files/a?after=zz")
+        );
+    }
+}
@@ -0,0 +1,101 @@
+// CSV as in RFC 4180: fields holding a comma, quote or line break are
+// quoted, with quotes doubled, and a quoted field may span lines.
+
+fn needs_quotes(field: &str) -> bool {
+    field.is_empty()
+        || field.contains([',', '"', '\n', '\r'])
+        || field.trim() != field
+}
+
+pub(crate) fn write_record<'a, I: IntoIterator<Item = &'a str>>(out: &mut String, fields: I) {
+    for (i, field) in fields.into_iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        if needs_quotes(field) {
+            out.push('"');
+            out.push_str(&field.replace('"', "\"\""));
+            out.push('"');
+        } else {
+            out.push_str(field);
+        }
+    }
+    out.push_str("\r\n");
+}
+
+// Whether the text read so far ends outside a quoted field, so the record
+// is complete.
+pub(crate) fn is_complete(record: &str) -> bool {
+    record.bytes().filter(|&b| b == b'"').count() % 2 == 0
+}
+
+pub(crate) fn parse_record(record: &str) -> Result<Vec<String>, String> {
+    let record = record
+        .strip_suffix('\n')
+        .map(|r| r.strip_suffix('\r').unwrap_or(r))
+        .unwrap_or(record);
+    let mut fields = vec![];
+    let mut chars = record.chars().peekable();
+
+    loop {
+        let mut field = String::new();
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            loop {
+                match chars.next() {
+                    Some('"') if chars.peek() == Some(&'"') => {
+                        chars.next();
+                        field.push('"');
+                    }
+                    Some('"') => break,
+                    Some(c) => field.push(c),
+                    None => return Err("unterminated quote".to_string()),
+                }
+            }
+            match chars.peek() {
+                None | Some(',') => (),
+                Some(_) => return Err("text after a closing quote".to_string()),
+            }
+        } else {
+            while let Some(&c) = chars.peek() {
+                if c == ',' {
+                    break;
+                }
+                if c == '"' {
+                    return Err("quote inside an unquoted field".to_string());
+                }
+                field.push(c);
+                chars.next();
+            }
+        }
+        fields.push(field);
+
+        match chars.next() {
+            Some(',') => (),
+            _ => return Ok(fields),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_complete, parse_record, write_record};
+
+    #[test]
+    fn csv_should_round_trip_awkward_fields() {
+        let fields = ["plain", "", "a,b", "say \"hi\"", "two\r\nlines", " padded "];
+        let mut out = String::new();
+        write_record(&mut out, fields);
+        assert!(is_complete(&out));
+        assert!(!is_complete(&out[..out.find('\n').unwrap() + 1]));
+        assert_eq!(Ok(fields.map(String::from).to_vec()), parse_record(&out));
+    }
+
+    #[test]
+    fn csv_should_reject_stray_quotes() {
+        assert!(parse_record("a\"b,c").is_err());
+        assert!(parse_record("\"a\"b,c").is_err());
+        assert!(parse_record("\"a").is_err());
+        assert_eq!(Ok(vec!["a".to_string()]), parse_record("a\n"));
+    }
+}
@@ -0,0 +1,294 @@
+// Just enough JSON for JSON Lines records of files: writing them, and
+// reading them back while skipping any fields we don't know.
+
+use crate::File;
+use crate::TagSet;
+
+pub(crate) fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+// {"name":"beach.jpg","tags":["2021","photos"]}
+pub(crate) fn write_file(out: &mut String, file: &File) {
+    out.push_str("{\"name\":");
+    write_string(out, &file.name);
+    out.push_str(",\"tags\":[");
+    for (i, tag) in file.tags.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        write_string(out, tag);
+    }
+    out.push_str("]}");
+}
+
+// Read a file from a record with a "name" string and an optional "tags"
+// array of strings. Other fields are ignored.
+pub(crate) fn parse_file(line: &str) -> Result<File, String> {
+    let mut parser = Parser::new(line);
+    let mut name = None;
+    let mut tags = TagSet::new();
+
+    parser.expect(b'{')?;
+    if !parser.next_is(b'}') {
+        loop {
+            let key = parser.string()?;
+            parser.expect(b':')?;
+            match key.as_str() {
+                "name" => name = Some(parser.string()?),
+                "tags" => tags = parser.strings()?.into_iter().collect(),
+                _ => parser.skip_value(0)?,
+            }
+            if parser.next_is(b'}') {
+                break;
+            }
+            parser.expect(b',')?;
+        }
+    }
+    parser.end()?;
+
+    match name {
+        Some(name) => Ok(File::new(name, tags)),
+        None => Err("no \"name\" field".to_string()),
+    }
+}
+
+// How deeply unknown fields may nest arrays and objects, so a hostile
+// record can't overflow the stack.
+const MAX_DEPTH: usize = 64;
+
+pub(crate) struct Parser<'a> {
+    s: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    pub(crate) fn new(s: &'a str) -> Self {
+        Parser { s, pos: 0 }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        while let Some(b' ' | b'\t' | b'\r' | b'\n') = self.s.as_bytes().get(self.pos) {
+            self.pos += 1;
+        }
+        self.s.as_bytes().get(self.pos).copied()
+    }
+
+    // Consume the byte if it comes next.
+    fn next_is(&mut self, c: u8) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: u8) -> Result<(), String> {
+        if self.next_is(c) {
+            Ok(())
+        } else {
+            Err(format!("expected '{}' at column {}", c as char, self.pos + 1))
+        }
+    }
+
+    // Fail if anything but whitespace is left.
+    pub(crate) fn end(&mut self) -> Result<(), String> {
+        match self.peek() {
+            None => Ok(()),
+            Some(_) => Err(format!("unexpected text at column {}", self.pos + 1)),
+        }
+    }
+
+    pub(crate) fn string(&mut self) -> Result<String, String> {
+        self.expect(b'"')?;
+        let bytes = self.s.as_bytes();
+        let mut out = String::new();
+        loop {
+            let start = self.pos;
+            while let Some(&b) = bytes.get(self.pos) {
+                if b == b'"' || b == b'\\' || b < 0x20 {
+                    break;
+                }
+                self.pos += 1;
+            }
+            out.push_str(&self.s[start..self.pos]);
+
+            match bytes.get(self.pos) {
+                Some(b'"') => {
+                    self.pos += 1;
+                    return Ok(out);
+                }
+                Some(b'\\') => {
+                    self.pos += 1;
+                    out.push(self.escape()?);
+                }
+                Some(_) => return Err(format!("control character at column {}", self.pos + 1)),
+                None => return Err("unterminated string".to_string()),
+            }
+        }
+    }
+
+    fn escape(&mut self) -> Result<char, String> {
+        let c = self.s.as_bytes().get(self.pos).copied();
+        self.pos += 1;
+        Ok(match c {
+            Some(b'"') => '"',
+            Some(b'\\') => '\\',
+            Some(b'/') => '/',
+            Some(b'b') => '\u{8}',
+            Some(b'f') => '\u{c}',
+            Some(b'n') => '\n',
+            Some(b'r') => '\r',
+            Some(b't') => '\t',
+            Some(b'u') => {
+                let high = self.hex4()?;
+                if (0xd800..0xdc00).contains(&high) {
+                    // The first half of a surrogate pair.
+                    if !self.s[self.pos..].starts_with("\\u") {
+                        return Err("unpaired surrogate".to_string());
+                    }
+                    self.pos += 2;
+                    let low = self.hex4()?;
+                    if !(0xdc00..0xe000).contains(&low) {
+                        return Err(format!("bad surrogate pair at column {}", self.pos - 5));
+                    }
+                    let code = 0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00);
+                    char::from_u32(code).ok_or("bad surrogate pair")?
+                } else {
+                    char::from_u32(high).ok_or("unpaired surrogate")?
+                }
+            }
+            _ => return Err(format!("bad escape at column {}", self.pos)),
+        })
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let digits = self.s.get(self.pos..self.pos + 4).ok_or("short \\u escape")?;
+        self.pos += 4;
+        // from_str_radix would also take a sign.
+        if !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(format!("bad \\u escape {}", digits));
+        }
+        u32::from_str_radix(digits, 16).map_err(|_| format!("bad \\u escape {}", digits))
+    }
+
+    fn strings(&mut self) -> Result<Vec<String>, String> {
+        let mut strings = vec![];
+        self.expect(b'[')?;
+        if self.next_is(b']') {
+            return Ok(strings);
+        }
+        loop {
+            strings.push(self.string()?);
+            if self.next_is(b']') {
+                return Ok(strings);
+            }
+            self.expect(b',')?;
+        }
+    }
+
+    // Skip a value nested inside depth arrays or objects.
+    fn skip_value(&mut self, depth: usize) -> Result<(), String> {
+        match self.peek() {
+            Some(b'"') => self.string().map(|_| ()),
+            Some(b'[' | b'{') if depth == MAX_DEPTH => {
+                Err(format!("nested too deeply at column {}", self.pos + 1))
+            }
+            Some(open @ (b'[' | b'{')) => {
+                let close = if open == b'[' { b']' } else { b'}' };
+                self.pos += 1;
+                if self.next_is(close) {
+                    return Ok(());
+                }
+                loop {
+                    if open == b'{' {
+                        self.string()?;
+                        self.expect(b':')?;
+                    }
+                    self.skip_value(depth + 1)?;
+                    if self.next_is(close) {
+                        return Ok(());
+                    }
+                    self.expect(b',')?;
+                }
+            }
+            _ => {
+                // A number, true, false or null.
+                let start = self.pos;
+                while let Some(b) = self.s.as_bytes().get(self.pos) {
+                    if !(b.is_ascii_alphanumeric() || b"+-.".contains(b)) {
+                        break;
+                    }
+                    self.pos += 1;
+                }
+                if self.pos == start {
+                    Err(format!("expected a value at column {}", self.pos + 1))
+                } else {
+                    Ok(())
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_file, write_file, MAX_DEPTH};
+    use crate::File;
+
+    #[test]
+    fn json_should_round_trip_awkward_strings() {
+        let file = File::new_cloned("a/b \"c\"\n\u{1}", ["tab\there", "back\\slash", "ünï"]);
+        let mut line = String::new();
+        write_file(&mut line, &file);
+        assert!(!line.contains('\n'));
+        assert_eq!(Ok(file), parse_file(&line));
+    }
+
+    #[test]
+    fn json_should_skip_unknown_fields() {
+        let line = r#" { "size": 12, "x": {"y": [1, null, "z"]}, "tags": ["a"], "name": "fé😀" } "#;
+        assert_eq!(Ok(File::new_cloned("fé😀", ["a"])), parse_file(line));
+        assert_eq!(Ok(File::new_cloned("f", [] as [&str; 0])), parse_file(r#"{"name":"f"}"#));
+    }
+
+    #[test]
+    fn json_should_reject_bad_records() {
+        assert!(parse_file(r#"{"tags": []}"#).is_err());
+        assert!(parse_file(r#"{"name": "f""#).is_err());
+        assert!(parse_file(r#"{"name": "f"} x"#).is_err());
+        assert!(parse_file(r#"{"name": "\q"}"#).is_err());
+        assert!(parse_file(r#"{"name": 3}"#).is_err());
+    }
+
+    #[test]
+    fn json_should_reject_bad_surrogates() {
+        assert_eq!(Ok(File::new_cloned("😀", [] as [&str; 0])), parse_file(r#"{"name":"\ud83d\ude00"}"#));
+        assert!(parse_file(r#"{"name":"\ud800\u0041"}"#).is_err());
+        assert!(parse_file(r#"{"name":"\ud800\uffff"}"#).is_err());
+        assert!(parse_file(r#"{"name":"\ud800x"}"#).is_err());
+        assert!(parse_file(r#"{"name":"\udc00"}"#).is_err());
+        assert!(parse_file(r#"{"name":"\u+041"}"#).is_err());
+    }
+
+    #[test]
+    fn json_should_limit_nesting_in_unknown_fields() {
+        let nested = |depth: usize| format!(r#"{{"x":{}{},"name":"f"}}"#, "[".repeat(depth), "]".repeat(depth));
+        assert!(parse_file(&nested(MAX_DEPTH)).is_ok());
+        assert!(parse_file(&nested(MAX_DEPTH + 1)).is_err());
+        assert!(parse_file(&nested(1_000_000)).is_err());
+    }
+}
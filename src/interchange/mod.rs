@@ -0,0 +1,269 @@
+// Reading and writing whole databases as files, one record per file, a
+// record at a time so large datasets are never held twice in memory.
+//
+// Yaml is a list of paths, "/tag/tag/name", like data/test_files.yml, as
+// read by File::from_str and written by File's Display.
+//
+// JsonLines is a JSON object per line with explicit "name" and "tags"
+// fields.
+//
+// Csv starts with a "name,tags" header, then has a record per file: its
+// name, followed by each of its tags as a field of its own.
+
+mod csv;
+mod json;
+mod yaml;
+
+use crate::fromstr::FromStr;
+use crate::{AddFileError, File, FileDB, TagSet};
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Format {
+    Yaml,
+    JsonLines,
+    Csv,
+}
+
+impl Format {
+    // The format usually kept in files with this path's extension.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Format> {
+        match path.as_ref().extension()?.to_str()? {
+            "yml" | "yaml" => Some(Format::Yaml),
+            "jsonl" | "ndjson" => Some(Format::JsonLines),
+            "csv" => Some(Format::Csv),
+            _ => None,
+        }
+    }
+}
+
+const CSV_HEADER: [&str; 2] = ["name", "tags"];
+
+#[derive(Debug)]
+pub enum ImportError {
+    Io(io::Error),
+    // The record starting on this line, counting from 1, couldn't be read.
+    Parse { line: usize, message: String },
+    // The file read from this line couldn't be added.
+    Add {
+        line: usize,
+        file: File,
+        error: AddFileError,
+    },
+}
+
+impl From<io::Error> for ImportError {
+    fn from(error: io::Error) -> Self {
+        ImportError::Io(error)
+    }
+}
+
+#[derive(Debug)]
+pub enum ExportError {
+    Io(io::Error),
+}
+
+impl From<io::Error> for ExportError {
+    fn from(error: io::Error) -> Self {
+        ExportError::Io(error)
+    }
+}
+
+// Reads files one at a time from a stream in any of the formats.
+pub struct FileReader<R: BufRead> {
+    input: R,
+    format: Format,
+    // Lines read so far.
+    line: usize,
+    seen_header: bool,
+    buffer: String,
+}
+
+impl<R: BufRead> FileReader<R> {
+    pub fn new(input: R, format: Format) -> Self {
+        FileReader {
+            input,
+            format,
+            line: 0,
+            seen_header: false,
+            buffer: String::new(),
+        }
+    }
+
+    // The lines read so far.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    fn parse_error(&self, line: usize, message: String) -> ImportError {
+        ImportError::Parse { line, message }
+    }
+
+    // Read the next record that isn't blank, returning the line it starts
+    // on, or None at the end of the input.
+    fn next_record(&mut self) -> Result<Option<usize>, ImportError> {
+        loop {
+            self.buffer.clear();
+            if self.input.read_line(&mut self.buffer)? == 0 {
+                return Ok(None);
+            }
+            self.line += 1;
+            let start = self.line;
+
+            if self.format == Format::Csv {
+                while !csv::is_complete(&self.buffer) {
+                    if self.input.read_line(&mut self.buffer)? == 0 {
+                        return Err(self.parse_error(start, "unterminated quote".to_string()));
+                    }
+                    self.line += 1;
+                }
+            }
+            if !self.buffer.trim().is_empty() {
+                return Ok(Some(start));
+            }
+        }
+    }
+
+    fn read_file(&mut self) -> Result<Option<(usize, File)>, ImportError> {
+        loop {
+            let line = match self.next_record()? {
+                Some(line) => line,
+                None => return Ok(None),
+            };
+
+            let parsed = match self.format {
+                Format::Yaml => match yaml::parse_line(&self.buffer) {
+                    Ok(Some(path)) => File::from_str(&path)
+                        .map_err(|error| format!("{:?} isn't a path to a file: {}", path, error)),
+                    Ok(None) => continue,
+                    Err(message) => Err(message),
+                },
+                Format::JsonLines => json::parse_file(&self.buffer),
+                Format::Csv => match csv::parse_record(&self.buffer) {
+                    Ok(fields) if !self.seen_header => {
+                        if fields != CSV_HEADER {
+                            return Err(self.parse_error(
+                                line,
+                                "expected a \"name,tags\" header".to_string(),
+                            ));
+                        }
+                        self.seen_header = true;
+                        continue;
+                    }
+                    Ok(fields) => {
+                        let mut fields = fields.into_iter();
+                        let name = fields.next().unwrap_or_default();
+                        Ok(File::new(name, fields.collect()))
+                    }
+                    Err(message) => Err(message),
+                },
+            };
+            return match parsed {
+                Ok(file) => Ok(Some((line, file))),
+                Err(message) => Err(self.parse_error(line, message)),
+            };
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for FileReader<R> {
+    type Item = Result<File, ImportError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_file().transpose().map(|r| r.map(|(_, file)| file))
+    }
+}
+
+// Writes files one at a time to a stream in any of the formats.
+pub struct FileWriter<W: Write> {
+    out: W,
+    format: Format,
+    written: usize,
+    buffer: String,
+}
+
+impl<W: Write> FileWriter<W> {
+    pub fn new(out: W, format: Format) -> Self {
+        FileWriter {
+            out,
+            format,
+            written: 0,
+            buffer: String::new(),
+        }
+    }
+
+    fn start(&mut self) {
+        if self.written == 0 && self.format == Format::Csv {
+            csv::write_record(&mut self.buffer, CSV_HEADER);
+        }
+    }
+
+    pub fn write(&mut self, file: &File) -> Result<(), ExportError> {
+        self.buffer.clear();
+        self.start();
+        match self.format {
+            Format::Yaml => yaml::write_path(&mut self.buffer, &file.to_string()),
+            Format::JsonLines => {
+                json::write_file(&mut self.buffer, file);
+                self.buffer.push('\n');
+            }
+            Format::Csv => csv::write_record(
+                &mut self.buffer,
+                std::iter::once(file.name.as_str()).chain(file.tags.iter().map(|t| t.as_str())),
+            ),
+        }
+        self.out.write_all(self.buffer.as_bytes())?;
+        self.written += 1;
+        Ok(())
+    }
+
+    // Finish the output, even if no files were written, and return the
+    // stream.
+    pub fn finish(mut self) -> Result<W, ExportError> {
+        self.buffer.clear();
+        if self.written == 0 {
+            self.start();
+            if self.format == Format::Yaml {
+                self.buffer.push_str("[]\n");
+            }
+        }
+        self.out.write_all(self.buffer.as_bytes())?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+
+    pub fn written(&self) -> usize {
+        self.written
+    }
+}
+
+// Add every file in the input to the DB, stopping at the first that can't
+// be read or added. Returns how many were added.
+pub fn import<DB: FileDB, R: BufRead>(
+    db: &mut DB,
+    input: R,
+    format: Format,
+) -> Result<usize, ImportError> {
+    let mut reader = FileReader::new(input, format);
+    let mut added = 0;
+    while let Some((line, file)) = reader.read_file()? {
+        if let Err(error) = db.add_file(&file) {
+            return Err(ImportError::Add { line, file, error });
+        }
+        added += 1;
+    }
+    Ok(added)
+}
+
+// Write every file in the DB, in the order the DB returns them. Returns
+// how many were written.
+pub fn export<DB: FileDB, W: Write>(db: &DB, out: W, format: Format) -> Result<usize, ExportError> {
+    let mut writer = FileWriter::new(out, format);
+    for file in db.get_files(&TagSet::new()) {
+        writer.write(&file)?;
+    }
+    let written = writer.written();
+    writer.finish()?;
+    Ok(written)
+}
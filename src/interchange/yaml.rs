@@ -0,0 +1,102 @@
+// YAML lists of paths, as in data/test_files.yml:
+//
+//   - /boot/vmlinuz-5.16.9-200.fc35.x86_64
+//   - "/docs/notes: draft.txt"
+//
+// Only block sequences of scalars are understood, a line each, which is
+// all we write.
+
+use super::json::{self, Parser};
+
+// Whether the path can be written without quotes.
+fn is_plain(path: &str) -> bool {
+    let first = match path.chars().next() {
+        Some(c) => c,
+        None => return false,
+    };
+    !"-?:,[]{}#&*!|>'\"%@`".contains(first)
+        && !path.contains(": ")
+        && !path.contains(" #")
+        && !path.ends_with(':')
+        && path.trim() == path
+        && !path.chars().any(|c| c.is_control())
+}
+
+pub(crate) fn write_path(out: &mut String, path: &str) {
+    out.push_str("- ");
+    if is_plain(path) {
+        out.push_str(path);
+    } else {
+        // YAML's double quoted strings accept JSON's escapes.
+        json::write_string(out, path);
+    }
+    out.push('\n');
+}
+
+// Read the path from one line of the list, or None if the line holds no
+// entry.
+pub(crate) fn parse_line(line: &str) -> Result<Option<String>, String> {
+    let line = line.trim_end_matches(['\n', '\r']);
+    let trimmed = line.trim();
+    if trimmed.is_empty() || trimmed.starts_with('#') || trimmed == "---" || trimmed == "[]" {
+        return Ok(None);
+    }
+
+    let item = match trimmed.strip_prefix("- ") {
+        Some(item) => item.trim(),
+        None if trimmed == "-" => return Err("empty list entry".to_string()),
+        None => return Err("expected a list entry starting with \"- \"".to_string()),
+    };
+
+    if item.starts_with('"') {
+        let mut parser = Parser::new(item);
+        let path = parser.string()?;
+        parser.end()?;
+        Ok(Some(path))
+    } else if let Some(quoted) = item.strip_prefix('\'') {
+        match quoted.strip_suffix('\'') {
+            Some(inner) => Ok(Some(inner.replace("''", "'"))),
+            None => Err("unterminated quote".to_string()),
+        }
+    } else {
+        // A comment may follow a plain scalar.
+        let path = match item.find(" #") {
+            Some(i) => item[..i].trim_end(),
+            None => item,
+        };
+        Ok(Some(path.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_line, write_path};
+
+    #[test]
+    fn yaml_should_quote_only_when_needed() {
+        let mut out = String::new();
+        write_path(&mut out, "/boot/vmlinuz");
+        write_path(&mut out, "/docs/notes: draft");
+        write_path(&mut out, "#hash");
+        assert_eq!(
+            "- /boot/vmlinuz\n- \"/docs/notes: draft\"\n- \"#hash\"\n",
+            out
+        );
+
+        for line in out.lines() {
+            assert!(parse_line(line).unwrap().is_some());
+        }
+    }
+
+    #[test]
+    fn yaml_should_read_each_kind_of_scalar() {
+        assert_eq!(Ok(Some("/a/b".to_string())), parse_line("- /a/b\n"));
+        assert_eq!(Ok(Some("/a/b".to_string())), parse_line("  - /a/b  # c"));
+        assert_eq!(Ok(Some("/a/\"b".to_string())), parse_line(r#"- "/a/\"b""#));
+        assert_eq!(Ok(Some("/it's".to_string())), parse_line("- '/it''s'"));
+        assert_eq!(Ok(None), parse_line("---"));
+        assert_eq!(Ok(None), parse_line("# comment"));
+        assert!(parse_line("/a/b").is_err());
+        assert!(parse_line("- '/a").is_err());
+    }
+}
@@ -0,0 +1,291 @@
+use crate::TagSet;
+use std::cmp::Ordering;
+use std::ops::Bound;
+
+// Structured tags are ordinary tags of the form "key=value". They are stored
+// as plain strings like any other tag, and only interpreted when a query asks
+// for a comparison on the key.
+pub const KEY_VALUE_SEPARATOR: char = '=';
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy, PartialOrd, Ord)]
+pub struct Date {
+    pub year: i32,
+    pub month: u8,
+    pub day: u8,
+}
+
+impl Date {
+    // Parses ISO 8601 calendar dates, e.g. "2021-03-14".
+    pub fn parse(value: &str) -> Option<Date> {
+        let mut parts = value.splitn(3, '-');
+        let year = parts.next()?;
+        let month = parts.next()?;
+        let day = parts.next()?;
+
+        if year.len() != 4 || month.len() != 2 || day.len() != 2 {
+            return None;
+        }
+
+        let date = Date {
+            year: year.parse().ok()?,
+            month: month.parse().ok()?,
+            day: day.parse().ok()?,
+        };
+
+        if (1..=12).contains(&date.month) && (1..=date.days_in_month()).contains(&date.day) {
+            Some(date)
+        } else {
+            None
+        }
+    }
+
+    // Gregorian leap years: every fourth year, except centuries not
+    // divisible by 400.
+    fn days_in_month(&self) -> u8 {
+        match self.month {
+            2 if self.year % 4 == 0 && (self.year % 100 != 0 || self.year % 400 == 0) => 29,
+            2 => 28,
+            4 | 6 | 9 | 11 => 30,
+            _ => 31,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum TagValue {
+    Integer(i64),
+    Date(Date),
+    String(String),
+}
+
+impl TagValue {
+    // Infer the type of a value from its text. Integers may have a binary
+    // size suffix (K, M, G or T), so "size>10M" works as expected.
+    pub fn parse(value: &str) -> TagValue {
+        if let Some(i) = Self::parse_integer(value) {
+            TagValue::Integer(i)
+        } else if let Some(d) = Date::parse(value) {
+            TagValue::Date(d)
+        } else {
+            TagValue::String(value.to_string())
+        }
+    }
+
+    fn parse_integer(value: &str) -> Option<i64> {
+        let (digits, multiplier) = match value.chars().last()? {
+            'K' => (&value[..value.len() - 1], 1i64 << 10),
+            'M' => (&value[..value.len() - 1], 1i64 << 20),
+            'G' => (&value[..value.len() - 1], 1i64 << 30),
+            'T' => (&value[..value.len() - 1], 1i64 << 40),
+            _ => (value, 1),
+        };
+        digits.parse::<i64>().ok()?.checked_mul(multiplier)
+    }
+}
+
+// Values of different types are never ordered relative to each other, so a
+// range query on integers will not match a string value of the same key.
+impl PartialOrd for TagValue {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match (self, other) {
+            (TagValue::Integer(a), TagValue::Integer(b)) => Some(a.cmp(b)),
+            (TagValue::Date(a), TagValue::Date(b)) => Some(a.cmp(b)),
+            (TagValue::String(a), TagValue::String(b)) => Some(a.cmp(b)),
+            _ => None,
+        }
+    }
+}
+
+// Split a tag into its key and raw value, if it is a structured tag.
+pub fn split_tag(tag: &str) -> Option<(&str, &str)> {
+    tag.split_once(KEY_VALUE_SEPARATOR)
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum Predicate {
+    Equal(TagValue),
+    Range(Bound<TagValue>, Bound<TagValue>),
+    Prefix(String),
+}
+
+impl Predicate {
+    pub fn matches(&self, raw_value: &str) -> bool {
+        match self {
+            Predicate::Equal(expected) => &TagValue::parse(raw_value) == expected,
+            Predicate::Range(lower, upper) => {
+                let value = TagValue::parse(raw_value);
+                // Comparison operators are false for values of different
+                // types, as they have no ordering.
+                let above = match lower {
+                    Bound::Included(l) => value >= *l,
+                    Bound::Excluded(l) => value > *l,
+                    Bound::Unbounded => true,
+                };
+                let below = match upper {
+                    Bound::Included(u) => value <= *u,
+                    Bound::Excluded(u) => value < *u,
+                    Bound::Unbounded => true,
+                };
+                above && below
+            }
+            Predicate::Prefix(prefix) => raw_value.starts_with(prefix.as_str()),
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct KeyPredicate {
+    key: String,
+    predicate: Predicate,
+}
+
+impl KeyPredicate {
+    pub fn new(key: &str, predicate: Predicate) -> Self {
+        KeyPredicate {
+            key: key.to_string(),
+            predicate,
+        }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    // The text every structured tag with this key starts with. As tags are
+    // kept in sorted containers, this is used to range over only the tags
+    // for our key.
+    pub(crate) fn tag_prefix(&self) -> String {
+        format!("{}{}", self.key, KEY_VALUE_SEPARATOR)
+    }
+
+    // Parse a single query component, e.g. "year>=2020", "size<10M",
+    // "year=2021" or "camera^=canon". Returns None for plain tags.
+    pub fn parse(component: &str) -> Option<KeyPredicate> {
+        let op_start = component.find(['<', '>', '=', '^'])?;
+        let (key, rest) = component.split_at(op_start);
+
+        let (op, value) = ["<=", ">=", "^=", "<", ">", "="]
+            .iter()
+            .find_map(|op| rest.strip_prefix(op).map(|v| (*op, v)))?;
+
+        if key.is_empty() || value.is_empty() {
+            return None;
+        }
+
+        let typed = TagValue::parse(value);
+        let predicate = match op {
+            "=" => Predicate::Equal(typed),
+            "<" => Predicate::Range(Bound::Unbounded, Bound::Excluded(typed)),
+            "<=" => Predicate::Range(Bound::Unbounded, Bound::Included(typed)),
+            ">" => Predicate::Range(Bound::Excluded(typed), Bound::Unbounded),
+            ">=" => Predicate::Range(Bound::Included(typed), Bound::Unbounded),
+            "^=" => Predicate::Prefix(value.to_string()),
+            _ => unreachable!(),
+        };
+
+        Some(KeyPredicate::new(key, predicate))
+    }
+
+    // Does this structured tag satisfy the predicate?
+    pub fn matches_tag(&self, tag: &str) -> bool {
+        match split_tag(tag) {
+            Some((key, value)) => key == self.key && self.predicate.matches(value),
+            None => false,
+        }
+    }
+
+    // Does any tag in the set satisfy the predicate?
+    pub fn matches_any(&self, tags: &TagSet) -> bool {
+        let prefix = self.tag_prefix();
+        tags.range::<str, _>((Bound::Included(prefix.as_str()), Bound::Unbounded))
+            .take_while(|t| t.starts_with(prefix.as_str()))
+            .any(|t| self.matches_tag(t))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Date, KeyPredicate, Predicate, TagValue};
+    use crate::{fromstr::FromStr, TagSet};
+    use std::ops::Bound;
+
+    #[test]
+    fn should_parse_typed_values() {
+        assert_eq!(TagValue::Integer(2021), TagValue::parse("2021"));
+        assert_eq!(TagValue::Integer(10 << 20), TagValue::parse("10M"));
+        assert_eq!(
+            TagValue::Date(Date {
+                year: 2021,
+                month: 3,
+                day: 14
+            }),
+            TagValue::parse("2021-03-14")
+        );
+        assert_eq!(TagValue::String("raw".to_string()), TagValue::parse("raw"));
+    }
+
+    #[test]
+    fn dates_should_fit_in_their_month() {
+        assert!(Date::parse("2021-01-31").is_some());
+        assert!(Date::parse("2021-02-31").is_none());
+        assert!(Date::parse("2021-04-30").is_some());
+        assert!(Date::parse("2021-04-31").is_none());
+        assert!(Date::parse("2021-02-28").is_some());
+        assert!(Date::parse("2021-02-29").is_none());
+        assert!(Date::parse("2020-02-29").is_some());
+        assert!(Date::parse("1900-02-29").is_none());
+        assert!(Date::parse("2000-02-29").is_some());
+        assert_eq!(
+            TagValue::String("2021-02-31".to_string()),
+            TagValue::parse("2021-02-31")
+        );
+    }
+
+    #[test]
+    fn should_parse_predicates() {
+        assert_eq!(
+            Some(KeyPredicate::new(
+                "size",
+                Predicate::Range(
+                    Bound::Excluded(TagValue::Integer(10 << 20)),
+                    Bound::Unbounded
+                )
+            )),
+            KeyPredicate::parse("size>10M")
+        );
+        assert_eq!(
+            Some(KeyPredicate::new(
+                "year",
+                Predicate::Range(Bound::Unbounded, Bound::Included(TagValue::Integer(2020)))
+            )),
+            KeyPredicate::parse("year<=2020")
+        );
+        assert_eq!(
+            Some(KeyPredicate::new(
+                "camera",
+                Predicate::Prefix("canon".to_string())
+            )),
+            KeyPredicate::parse("camera^=canon")
+        );
+        assert_eq!(None, KeyPredicate::parse("plain"));
+        assert_eq!(None, KeyPredicate::parse("year>"));
+    }
+
+    #[test]
+    fn should_not_compare_different_types() {
+        let pred = KeyPredicate::parse("year>2020").unwrap();
+
+        assert!(pred.matches_tag("year=2021"));
+        assert!(!pred.matches_tag("year=recent"));
+        assert!(!pred.matches_tag("month=2021"));
+    }
+
+    #[test]
+    fn should_match_any_tag_with_key() {
+        let tags = TagSet::from_str("/photos/year=2019/year=2021").unwrap();
+
+        assert!(KeyPredicate::parse("year>2020").unwrap().matches_any(&tags));
+        assert!(!KeyPredicate::parse("year>2021").unwrap().matches_any(&tags));
+        assert!(KeyPredicate::parse("year=2019").unwrap().matches_any(&tags));
+    }
+}
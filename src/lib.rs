@@ -1,22 +1,78 @@
 #[cfg(test)]
 extern crate proptest;
 
+#[cfg(feature = "archive")]
+mod archive;
+mod batch;
+mod diffdb;
+mod dynfdb;
+mod explain;
 mod fdb_trait;
 mod file;
 mod filequery;
 pub mod fromstr;
 mod hashtags;
 mod hashtags2;
+mod hierarchy;
+#[cfg(feature = "http")]
+mod http;
+mod interchange;
+mod keyvalue;
+mod memory;
 mod naive;
+mod nameindex;
+mod namepattern;
+mod ninep;
+mod paging;
+mod query;
+mod rules;
+mod rulesdb;
+mod subscriptions;
 mod tagset;
 mod tagtree;
+mod vfs;
+mod workload;
 
 pub use crate::fdb_trait::FileDB;
+pub use crate::explain::QueryStats;
+#[cfg(feature = "archive")]
+pub use crate::archive::{
+    index_archive, index_archive_file, read_entries, read_entry, ArchiveEntry, ArchiveError,
+    ArchiveKind, Compression,
+};
+pub use crate::batch::{Batch, BatchError, BatchOp, BatchOpError};
+pub use crate::diffdb::{DiffDBFS, Divergence, OnDivergence, Operation, DEFAULT_HISTORY_LIMIT};
+pub use crate::dynfdb::{BackendConfig, DynFileDB, UnknownBackend, BACKENDS};
+pub use crate::fdb_trait::AddFileError;
 pub use crate::fdb_trait::GetFileError;
+pub use crate::fdb_trait::RetagFileError;
+pub use crate::fdb_trait::{Ambiguity, MAX_CANDIDATES};
 pub use crate::file::File;
 pub use crate::filequery::FileQuery;
 pub use crate::hashtags::HashTagsDBFS;
 pub use crate::hashtags2::HashTags2DBFS;
+pub use crate::hierarchy::TagHierarchy;
+#[cfg(feature = "http")]
+pub use crate::http::client::{HttpClient, HttpError};
+#[cfg(feature = "http")]
+pub use crate::http::{ErrorBody, Facet, FileRecord, HttpService, Page};
+pub use crate::interchange::{export, import, ExportError, FileReader, FileWriter, Format, ImportError};
+pub use crate::keyvalue::{Date, KeyPredicate, Predicate, TagValue};
 pub use crate::naive::NaiveDBFS;
-pub use crate::tagset::TagSet;
+pub use crate::namepattern::NamePattern;
+pub use crate::ninep::client::{NinePClient, NinePError};
+pub use crate::ninep::message::{Qid, Stat};
+pub use crate::ninep::NinePServer;
+pub use crate::paging::{Cursor, QueryOptions, ResultPage, SortOrder};
+pub use crate::query::Query;
+pub use crate::rules::{RuleError, RuleSet};
+pub use crate::rulesdb::{RulesDBFS, SetRulesError};
+pub use crate::subscriptions::{FileEvent, SubscribedDBFS};
+pub use crate::tagset::{TagPath, TagSet};
+pub use crate::tagtree::dot::DotOptions;
+pub use crate::tagtree::invariants::{InvariantReport, Problem, Violation};
+pub use crate::tagtree::snapshot::TagTreeSnapshot;
+pub use crate::tagtree::stats::{BranchStats, TreeStats};
 pub use crate::tagtree::TagTreeDBFS;
+pub use crate::vfs::{Attr, DirEntry, EntryKind, Vfs, VfsError, ROOT_INODE};
+pub use crate::workload::{QueryMix, TagsPerFile, Workload};
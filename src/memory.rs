@@ -0,0 +1,95 @@
+use crate::File;
+use std::collections::btree_map::BTreeMap;
+use std::collections::btree_set::BTreeSet;
+use std::collections::hash_map::HashMap;
+use std::collections::hash_set::HashSet;
+use std::mem::size_of;
+
+// The bookkeeping an Arc allocates alongside its value: the strong and
+// weak counts.
+pub(crate) const ARC_HEADER: usize = 2 * size_of::<usize>();
+
+// An estimate of the bytes a value has allocated on the heap, not counting
+// the value itself. The collections' layouts aren't public, so these are
+// approximations meant for comparing backends and sizing machines, not
+// exact accounting.
+pub(crate) trait HeapSize {
+    fn heap_bytes(&self) -> usize;
+}
+
+// B-tree nodes hold up to eleven entries, and are on average about two
+// thirds full.
+fn btree_bytes(len: usize, entry: usize) -> usize {
+    len * entry * 3 / 2
+}
+
+// Hash tables allocate every bucket up front, plus a control byte each.
+fn hash_bytes(capacity: usize, entry: usize) -> usize {
+    capacity * (entry + 1)
+}
+
+impl HeapSize for String {
+    fn heap_bytes(&self) -> usize {
+        self.capacity()
+    }
+}
+
+impl HeapSize for File {
+    fn heap_bytes(&self) -> usize {
+        self.name.heap_bytes() + self.tags.heap_bytes()
+    }
+}
+
+impl<T: HeapSize> HeapSize for BTreeSet<T> {
+    fn heap_bytes(&self) -> usize {
+        btree_bytes(self.len(), size_of::<T>()) + self.iter().map(|x| x.heap_bytes()).sum::<usize>()
+    }
+}
+
+impl<K: HeapSize, V: HeapSize> HeapSize for BTreeMap<K, V> {
+    fn heap_bytes(&self) -> usize {
+        btree_bytes(self.len(), size_of::<(K, V)>())
+            + self
+                .iter()
+                .map(|(k, v)| k.heap_bytes() + v.heap_bytes())
+                .sum::<usize>()
+    }
+}
+
+impl<T: HeapSize> HeapSize for HashSet<T> {
+    fn heap_bytes(&self) -> usize {
+        hash_bytes(self.capacity(), size_of::<T>())
+            + self.iter().map(|x| x.heap_bytes()).sum::<usize>()
+    }
+}
+
+impl<K: HeapSize, V: HeapSize> HeapSize for HashMap<K, V> {
+    fn heap_bytes(&self) -> usize {
+        hash_bytes(self.capacity(), size_of::<(K, V)>())
+            + self
+                .iter()
+                .map(|(k, v)| k.heap_bytes() + v.heap_bytes())
+                .sum::<usize>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HeapSize;
+    use crate::File;
+    use std::collections::btree_set::BTreeSet;
+
+    #[test]
+    fn heap_size_should_count_contents() {
+        let empty = String::new();
+        let name = String::from("beach.jpg");
+        assert_eq!(0, empty.heap_bytes());
+        assert_eq!(name.capacity(), name.heap_bytes());
+
+        let file = File::new_cloned("beach.jpg", ["photos", "2021"]);
+        let set: BTreeSet<File> = [file.clone()].into_iter().collect();
+        assert!(file.heap_bytes() >= "beach.jpg".len() + "photos".len() + "2021".len());
+        assert!(set.heap_bytes() > file.heap_bytes());
+        assert_eq!(0, BTreeSet::<File>::new().heap_bytes());
+    }
+}
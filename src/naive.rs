@@ -1,28 +1,56 @@
+use crate::nameindex::NameIndex;
 use crate::FileDB;
-use crate::{fdb_trait::GetFileError, File, FileQuery};
+use crate::TagHierarchy;
+use crate::explain::QueryStats;
+use crate::fdb_trait::{AddFileError, Candidates, GetFileError};
+use crate::memory::HeapSize;
+use crate::{File, FileQuery};
 use std::collections::hash_set::{HashSet, IntoIter};
 
+#[derive(Clone)]
 pub struct NaiveDBFS {
     files: HashSet<File>,
+    hierarchy: TagHierarchy,
+    names: NameIndex,
+}
+
+impl Default for NaiveDBFS {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl NaiveDBFS {
     pub fn new() -> NaiveDBFS {
+        NaiveDBFS::with_hierarchy(TagHierarchy::new())
+    }
+
+    pub fn with_hierarchy(hierarchy: TagHierarchy) -> NaiveDBFS {
         NaiveDBFS {
             files: HashSet::new(),
+            hierarchy,
+            names: NameIndex::new(),
         }
     }
+
+    // An estimate of the bytes allocated by the DB and its indexes.
+    pub fn memory_usage(&self) -> usize {
+        self.files.heap_bytes() + self.names.heap_bytes() + self.hierarchy.heap_bytes()
+    }
 }
 
 impl FileDB for NaiveDBFS {
     type FileIterator = IntoIter<File>;
 
-    fn add_file(&mut self, new_file: &File) -> Option<()> {
-        if self.files.contains(new_file) {
-            None
+    fn add_file(&mut self, new_file: &File) -> Result<(), AddFileError> {
+        AddFileError::check_tags(new_file)?;
+        let new_file = self.hierarchy.expand_file(new_file);
+        if self.files.contains(new_file.as_ref()) {
+            Err(AddFileError::Duplicate)
         } else {
-            self.files.insert(new_file.clone());
-            Some(())
+            self.names.insert(&new_file);
+            self.files.insert(new_file.into_owned());
+            Ok(())
         }
     }
 
@@ -30,31 +58,47 @@ impl FileDB for NaiveDBFS {
         let mut result = HashSet::new();
 
         for f in &self.files {
-            if query.could_match(&f) {
+            if query.could_match(f) {
                 result.insert(f.clone());
             }
         }
         result.into_iter()
     }
 
+    // Every file is checked against the query.
+    fn explain<F: FileQuery>(&self, query: &F) -> QueryStats {
+        let mut stats = QueryStats::default();
+        let matched = self.files.iter().filter(|f| query.could_match(f)).count();
+        stats.scanned(self.files.len(), matched);
+        stats
+    }
+
     fn get_file<F: FileQuery>(&self, query: &F) -> Result<File, GetFileError> {
-        // Just fail if the query isn't a well formed file.
-        let mut result: Result<File, GetFileError> = Err(GetFileError::NoSuchFile);
+        if let Some(result) = self.names.get_file(query) {
+            return result;
+        }
+
+        let mut candidates = Candidates::new();
 
         for f in &self.files {
-            if query.could_match(&f) {
-                if result == Err(GetFileError::NoSuchFile) {
-                    result = Ok(f.clone());
-                } else {
-                    // Early return, as if there are two matches
-                    // then we know this is a failure; we only want
-                    // one file.
-                    return Err(GetFileError::TooManyFiles);
-                }
+            // Stop early once we have as many matches as an ambiguity error
+            // will list; we only want one file.
+            if query.could_match(f) && !candidates.push(f.clone()) {
+                break;
             }
         }
-        // Either we found a single match, or no match at all, so return what
-        // we have.
-        result
+        // Either we found a single match, no match at all, or too many.
+        candidates.into_result()
+    }
+
+    fn remove_file<F: FileQuery>(&mut self, query: &F) -> Result<File, GetFileError> {
+        let file = self.get_file(query)?;
+        self.files.remove(&file);
+        self.names.remove(&file);
+        Ok(file)
+    }
+
+    fn find_by_name(&self, name: &str) -> Vec<File> {
+        self.names.find(name)
     }
 }
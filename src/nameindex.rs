@@ -0,0 +1,125 @@
+use crate::fdb_trait::{Candidates, GetFileError};
+use crate::memory::HeapSize;
+use crate::{File, FileQuery, TagSet};
+use std::collections::btree_set::BTreeSet;
+use std::collections::hash_map::HashMap;
+
+// Secondary index from a file name to every tag set a file of that name is
+// stored under. Lookups by name cost time proportional to the number of
+// files with that name, rather than the size of the database.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct NameIndex {
+    names: HashMap<String, BTreeSet<TagSet>>,
+}
+
+impl NameIndex {
+    pub(crate) fn new() -> Self {
+        NameIndex {
+            names: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn heap_bytes(&self) -> usize {
+        self.names.heap_bytes()
+    }
+
+    pub(crate) fn insert(&mut self, file: &File) {
+        self.names
+            .entry(file.name.clone())
+            .or_default()
+            .insert(file.tags.clone());
+    }
+
+    pub(crate) fn remove(&mut self, file: &File) -> bool {
+        let tag_sets = match self.names.get_mut(&file.name) {
+            Some(tag_sets) => tag_sets,
+            None => return false,
+        };
+        let removed = tag_sets.remove(&file.tags);
+        if tag_sets.is_empty() {
+            self.names.remove(&file.name);
+        }
+        removed
+    }
+
+    pub(crate) fn contains(&self, file: &File) -> bool {
+        self.names
+            .get(&file.name)
+            .is_some_and(|tag_sets| tag_sets.contains(&file.tags))
+    }
+
+    pub(crate) fn find(&self, name: &str) -> Vec<File> {
+        self.names
+            .get(name)
+            .into_iter()
+            .flatten()
+            .map(|tags| File::new(name.to_string(), tags.clone()))
+            .collect()
+    }
+
+    pub(crate) fn all_files(&self) -> BTreeSet<File> {
+        self.names
+            .iter()
+            .flat_map(|(name, tag_sets)| {
+                tag_sets
+                    .iter()
+                    .map(move |tags| File::new(name.clone(), tags.clone()))
+            })
+            .collect()
+    }
+
+    // Answer get_file for a query with an exact name. Returns None if the
+    // query has no name, so the caller must search some other way.
+    pub(crate) fn get_file<F: FileQuery>(&self, query: &F) -> Option<Result<File, GetFileError>> {
+        let name = query.name()?;
+
+        let mut candidates = Candidates::new();
+        for tags in self.names.get(name).into_iter().flatten() {
+            let candidate = File::new(name.to_string(), tags.clone());
+            if query.could_match(&candidate) && !candidates.push(candidate) {
+                break;
+            }
+        }
+        Some(candidates.into_result())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NameIndex;
+    use crate::{fdb_trait::GetFileError, fromstr::FromStr, File, TagSet};
+
+    #[test]
+    fn should_find_all_files_with_name() {
+        let mut index = NameIndex::new();
+        index.insert(&File::from_str("/a/b/Cargo.toml").unwrap());
+        index.insert(&File::from_str("/c/Cargo.toml").unwrap());
+        index.insert(&File::from_str("/c/main.rs").unwrap());
+
+        let mut expected = vec![
+            File::from_str("/a/b/Cargo.toml").unwrap(),
+            File::from_str("/c/Cargo.toml").unwrap(),
+        ];
+        expected.sort();
+
+        assert_eq!(expected, index.find("Cargo.toml"));
+        assert!(index.find("lib.rs").is_empty());
+    }
+
+    #[test]
+    fn should_get_file_by_name_and_tags() {
+        let mut index = NameIndex::new();
+        index.insert(&File::from_str("/a/b/Cargo.toml").unwrap());
+        index.insert(&File::from_str("/a/c/Cargo.toml").unwrap());
+
+        assert_eq!(
+            Some(Ok(File::from_str("/a/b/Cargo.toml").unwrap())),
+            index.get_file(&File::from_str("/b/Cargo.toml").unwrap())
+        );
+        assert!(matches!(
+            index.get_file(&File::from_str("/a/Cargo.toml").unwrap()),
+            Some(Err(GetFileError::TooManyFiles(_)))
+        ));
+        assert_eq!(None, index.get_file(&TagSet::from_str("/a").unwrap()));
+    }
+}
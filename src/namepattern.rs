@@ -0,0 +1,243 @@
+use regex::Regex;
+
+// A pattern over file names, rather than a single exact name. Made with
+// NamePattern::glob or NamePattern::regex, which check the pattern first.
+#[derive(Debug, Clone)]
+pub struct NamePattern(Pattern);
+
+#[derive(Debug, Clone)]
+enum Pattern {
+    // Shell style: '*' is any run of characters, '?' is any one character,
+    // "[a-z]" and "[!a-z]" are character classes, and '\' escapes the next
+    // character.
+    Glob(String),
+    Regex(Regex),
+}
+
+impl NamePattern {
+    pub fn glob(pattern: &str) -> Option<NamePattern> {
+        // Check the pattern is well formed up front, so matching can't fail.
+        let mut chars = pattern.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => {
+                    chars.next()?;
+                }
+                '[' => {
+                    if chars.peek() == Some(&'!') {
+                        chars.next();
+                    }
+                    // A ']' straight after the opening is part of the class.
+                    if chars.peek() == Some(&']') {
+                        chars.next();
+                    }
+                    chars.find(|x| *x == ']')?;
+                }
+                _ => {}
+            }
+        }
+        Some(NamePattern(Pattern::Glob(pattern.to_string())))
+    }
+
+    pub fn regex(pattern: &str) -> Option<NamePattern> {
+        Some(NamePattern(Pattern::Regex(Regex::new(pattern).ok()?)))
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        match &self.0 {
+            Pattern::Glob(pattern) => {
+                let pattern: Vec<char> = pattern.chars().collect();
+                let name: Vec<char> = name.chars().collect();
+                glob_matches(&pattern, &name)
+            }
+            Pattern::Regex(re) => re.is_match(name),
+        }
+    }
+
+    // Text that every matching name must start with. Names are kept sorted,
+    // so this lets us look at only the range of names that could match.
+    pub fn literal_prefix(&self) -> String {
+        match &self.0 {
+            Pattern::Glob(pattern) => {
+                let mut prefix = String::new();
+                let mut chars = pattern.chars();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '*' | '?' | '[' => break,
+                        '\\' => prefix.extend(chars.next()),
+                        _ => prefix.push(c),
+                    }
+                }
+                prefix
+            }
+            Pattern::Regex(re) => {
+                // Only an anchored pattern has a prefix. Stop at anything that
+                // isn't plain text; a following quantifier could make the last
+                // character optional, so drop that too.
+                let mut prefix = String::new();
+                if re.as_str().contains('|') {
+                    // An alternation may not be covered by the anchor.
+                    return prefix;
+                }
+                if let Some(rest) = re.as_str().strip_prefix('^') {
+                    for c in rest.chars() {
+                        if c.is_alphanumeric() || c == '_' || c == '-' || c == ' ' {
+                            prefix.push(c);
+                        } else {
+                            if matches!(c, '?' | '*' | '{') {
+                                prefix.pop();
+                            }
+                            break;
+                        }
+                    }
+                }
+                prefix
+            }
+        }
+    }
+}
+
+impl PartialEq for NamePattern {
+    fn eq(&self, other: &Self) -> bool {
+        match (&self.0, &other.0) {
+            (Pattern::Glob(a), Pattern::Glob(b)) => a == b,
+            (Pattern::Regex(a), Pattern::Regex(b)) => a.as_str() == b.as_str(),
+            _ => false,
+        }
+    }
+}
+
+// Match a glob against a name, a character at a time. On a mismatch only
+// the last '*' is given another character, which is enough: whatever an
+// earlier '*' could take instead, the last one can take too. So this takes
+// at most pattern length times name length steps.
+fn glob_matches(pattern: &[char], name: &[char]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    // Where to carry on after the last '*', and the name position it has
+    // taken characters up to.
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        if pattern.get(p) == Some(&'*') {
+            p += 1;
+            star = Some((p, n));
+            continue;
+        }
+        match glob_matches_one(pattern, p, name[n]) {
+            Some(next) => {
+                p = next;
+                n += 1;
+            }
+            None => match star {
+                Some((after, taken)) => {
+                    p = after;
+                    n = taken + 1;
+                    star = Some((after, n));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|x| *x == '*')
+}
+
+// If the pattern element at p matches the character, where the next
+// element starts.
+fn glob_matches_one(pattern: &[char], p: usize, c: char) -> Option<usize> {
+    match pattern.get(p)? {
+        '?' => Some(p + 1),
+        '\\' => (pattern.get(p + 1) == Some(&c)).then_some(p + 2),
+        '[' => {
+            let negate = pattern.get(p + 1) == Some(&'!');
+            let start = if negate { p + 2 } else { p + 1 };
+            // The closing ']' is only a terminator after the first class member.
+            let end = start + 1 + pattern[start + 1..].iter().position(|x| *x == ']').unwrap();
+            let class = &pattern[start..end];
+
+            let mut found = false;
+            let mut i = 0;
+            while i < class.len() {
+                if i + 2 < class.len() && class[i + 1] == '-' {
+                    found |= class[i] <= c && c <= class[i + 2];
+                    i += 3;
+                } else {
+                    found |= class[i] == c;
+                    i += 1;
+                }
+            }
+
+            (found != negate).then_some(end + 1)
+        }
+        x => (*x == c).then_some(p + 1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NamePattern;
+
+    #[test]
+    fn glob_should_match_names() {
+        let p = NamePattern::glob("*.jpg").unwrap();
+
+        assert!(p.matches("holiday.jpg"));
+        assert!(p.matches(".jpg"));
+        assert!(!p.matches("holiday.jpeg"));
+
+        let p = NamePattern::glob("img_[0-9]?[!a].png").unwrap();
+
+        assert!(p.matches("img_12b.png"));
+        assert!(!p.matches("img_12a.png"));
+        assert!(!p.matches("img_x2b.png"));
+    }
+
+    #[test]
+    fn glob_should_escape_wildcards() {
+        let p = NamePattern::glob("what\\?.txt").unwrap();
+
+        assert!(p.matches("what?.txt"));
+        assert!(!p.matches("whatx.txt"));
+        assert_eq!("what?.txt", p.literal_prefix());
+    }
+
+    #[test]
+    fn glob_should_backtrack_to_the_last_star() {
+        let p = NamePattern::glob("*a*b?[cd]*").unwrap();
+
+        assert!(p.matches("xaxbyd"));
+        assert!(p.matches("aabbbc..."));
+        assert!(!p.matches("aabbb"));
+        assert!(NamePattern::glob("**").unwrap().matches(""));
+        assert!(!NamePattern::glob("a\\*").unwrap().matches("abc"));
+    }
+
+    #[test]
+    fn glob_should_not_take_exponential_time() {
+        let p = NamePattern::glob("*a*a*a*a*a*a*a*a*a*a*a*a*b").unwrap();
+
+        assert!(!p.matches(&"a".repeat(40)));
+        assert!(p.matches(&format!("{}b", "a".repeat(40))));
+    }
+
+    #[test]
+    fn glob_should_reject_unclosed_class() {
+        assert_eq!(None, NamePattern::glob("img_[0-9"));
+        assert_eq!(None, NamePattern::glob("trailing\\"));
+    }
+
+    #[test]
+    fn should_find_literal_prefix() {
+        assert_eq!(
+            "img_",
+            NamePattern::glob("img_*.png").unwrap().literal_prefix()
+        );
+        assert_eq!("", NamePattern::glob("*.png").unwrap().literal_prefix());
+        assert_eq!(
+            "img",
+            NamePattern::regex("^img_?[0-9]+").unwrap().literal_prefix()
+        );
+        assert_eq!(
+            "",
+            NamePattern::regex("img_[0-9]+").unwrap().literal_prefix()
+        );
+    }
+}
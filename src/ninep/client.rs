@@ -0,0 +1,187 @@
+use super::message::{decode_stats, Fcall, Message, Qid, Stat, DMDIR, IOHDRSZ, NOFID, NOTAG};
+use super::message::{OREAD, VERSION};
+use super::MAX_MSIZE;
+use std::io::{self, Read, Write};
+
+#[derive(Debug)]
+pub enum NinePError {
+    Io(io::Error),
+    // The server replied with an error.
+    Remote(String),
+    // The server replied with the wrong message.
+    Unexpected,
+}
+
+impl From<io::Error> for NinePError {
+    fn from(error: io::Error) -> Self {
+        NinePError::Io(error)
+    }
+}
+
+// A minimal synchronous 9P2000 client, enough to drive a NinePServer from
+// tests or tools without mounting it. Requests are sent one at a time.
+pub struct NinePClient<S: Read + Write> {
+    stream: S,
+    msize: u32,
+    next_tag: u16,
+    next_fid: u32,
+}
+
+impl<S: Read + Write> NinePClient<S> {
+    // Connect over the stream, agreeing the protocol version.
+    pub fn new(stream: S) -> Result<Self, NinePError> {
+        let mut client = NinePClient {
+            stream,
+            msize: MAX_MSIZE,
+            next_tag: 0,
+            next_fid: 0,
+        };
+        let request = Message::new(
+            NOTAG,
+            Fcall::Tversion {
+                msize: MAX_MSIZE,
+                version: VERSION.to_string(),
+            },
+        );
+        match client.send(request)? {
+            Fcall::Rversion { msize, version } if version == VERSION => {
+                client.msize = msize;
+                Ok(client)
+            }
+            Fcall::Rversion { version, .. } => Err(NinePError::Remote(version)),
+            _ => Err(NinePError::Unexpected),
+        }
+    }
+
+    // Get a fid for the root of the tree.
+    pub fn attach(&mut self) -> Result<u32, NinePError> {
+        let fid = self.new_fid();
+        let reply = self.call(Fcall::Tattach {
+            fid,
+            afid: NOFID,
+            uname: "none".to_string(),
+            aname: String::new(),
+        })?;
+        match reply {
+            Fcall::Rattach { .. } => Ok(fid),
+            _ => Err(NinePError::Unexpected),
+        }
+    }
+
+    // Walk from fid along the names, returning a new fid for where it ends.
+    // Fails unless every name is found.
+    pub fn walk(&mut self, fid: u32, names: &[&str]) -> Result<u32, NinePError> {
+        let newfid = self.new_fid();
+        let reply = self.call(Fcall::Twalk {
+            fid,
+            newfid,
+            wnames: names.iter().map(|n| n.to_string()).collect(),
+        })?;
+        match reply {
+            Fcall::Rwalk { qids } if qids.len() == names.len() => Ok(newfid),
+            Fcall::Rwalk { .. } => Err(NinePError::Remote("file not found".to_string())),
+            _ => Err(NinePError::Unexpected),
+        }
+    }
+
+    pub fn open(&mut self, fid: u32) -> Result<Qid, NinePError> {
+        match self.call(Fcall::Topen { fid, mode: OREAD })? {
+            Fcall::Ropen { qid, .. } => Ok(qid),
+            _ => Err(NinePError::Unexpected),
+        }
+    }
+
+    pub fn read(&mut self, fid: u32, offset: u64) -> Result<Vec<u8>, NinePError> {
+        let count = self.msize.saturating_sub(IOHDRSZ);
+        match self.call(Fcall::Tread { fid, offset, count })? {
+            Fcall::Rread { data } => Ok(data),
+            _ => Err(NinePError::Unexpected),
+        }
+    }
+
+    // Read every entry of an open directory.
+    pub fn read_dir(&mut self, fid: u32) -> Result<Vec<Stat>, NinePError> {
+        let mut offset = 0;
+        let mut stats = vec![];
+        loop {
+            let data = self.read(fid, offset)?;
+            if data.is_empty() {
+                return Ok(stats);
+            }
+            offset += data.len() as u64;
+            stats.extend(decode_stats(&data)?);
+        }
+    }
+
+    // Make a file, or a directory, in the directory fid refers to; fid then
+    // refers to the new entry.
+    pub fn create(&mut self, fid: u32, name: &str, dir: bool) -> Result<Qid, NinePError> {
+        let perm = if dir { DMDIR | 0o755 } else { 0o644 };
+        let reply = self.call(Fcall::Tcreate {
+            fid,
+            name: name.to_string(),
+            perm,
+            mode: OREAD,
+        })?;
+        match reply {
+            Fcall::Rcreate { qid, .. } => Ok(qid),
+            _ => Err(NinePError::Unexpected),
+        }
+    }
+
+    pub fn stat(&mut self, fid: u32) -> Result<Stat, NinePError> {
+        match self.call(Fcall::Tstat { fid })? {
+            Fcall::Rstat { stat } => Ok(stat),
+            _ => Err(NinePError::Unexpected),
+        }
+    }
+
+    // Rename the entry fid refers to, within its directory.
+    pub fn rename(&mut self, fid: u32, new_name: &str) -> Result<(), NinePError> {
+        let mut stat = self.stat(fid)?;
+        stat.name = new_name.to_string();
+        match self.call(Fcall::Twstat { fid, stat })? {
+            Fcall::Rwstat => Ok(()),
+            _ => Err(NinePError::Unexpected),
+        }
+    }
+
+    // Remove the entry fid refers to; the fid is released either way.
+    pub fn remove(&mut self, fid: u32) -> Result<(), NinePError> {
+        match self.call(Fcall::Tremove { fid })? {
+            Fcall::Rremove => Ok(()),
+            _ => Err(NinePError::Unexpected),
+        }
+    }
+
+    pub fn clunk(&mut self, fid: u32) -> Result<(), NinePError> {
+        match self.call(Fcall::Tclunk { fid })? {
+            Fcall::Rclunk => Ok(()),
+            _ => Err(NinePError::Unexpected),
+        }
+    }
+
+    fn new_fid(&mut self) -> u32 {
+        self.next_fid += 1;
+        self.next_fid
+    }
+
+    fn call(&mut self, body: Fcall) -> Result<Fcall, NinePError> {
+        let tag = self.next_tag;
+        // NOTAG is reserved for Tversion.
+        self.next_tag = self.next_tag.wrapping_add(1) % NOTAG;
+        match self.send(Message::new(tag, body))? {
+            Fcall::Rerror { ename } => Err(NinePError::Remote(ename)),
+            reply => Ok(reply),
+        }
+    }
+
+    fn send(&mut self, request: Message) -> Result<Fcall, NinePError> {
+        request.write_to(&mut self.stream)?;
+        let reply = Message::read_from(&mut self.stream, self.msize)?;
+        if reply.tag != request.tag {
+            return Err(NinePError::Unexpected);
+        }
+        Ok(reply.body)
+    }
+}
@@ -0,0 +1,669 @@
+use std::io::{self, Read, Write};
+
+// The only protocol version we speak.
+pub const VERSION: &str = "9P2000";
+
+pub const NOTAG: u16 = 0xFFFF;
+pub const NOFID: u32 = 0xFFFF_FFFF;
+
+// Qid types.
+pub const QTDIR: u8 = 0x80;
+pub const QTFILE: u8 = 0x00;
+
+// Set in the mode of a directory.
+pub const DMDIR: u32 = 0x8000_0000;
+
+// Open modes.
+pub const OREAD: u8 = 0;
+
+// Bytes in every message before the body: size[4] type[1] tag[2].
+const HEADER_SIZE: u32 = 7;
+
+// The overhead of a Tread or Rwrite reply, which limits how much data a
+// single read can return within the negotiated message size.
+pub const IOHDRSZ: u32 = 24;
+
+// The server's unique identifier for a file.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Qid {
+    pub kind: u8,
+    pub version: u32,
+    pub path: u64,
+}
+
+impl Qid {
+    pub fn is_dir(&self) -> bool {
+        self.kind & QTDIR != 0
+    }
+}
+
+// A directory entry, as returned by stat and by reading a directory.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Stat {
+    pub kind: u16,
+    pub dev: u32,
+    pub qid: Qid,
+    pub mode: u32,
+    pub atime: u32,
+    pub mtime: u32,
+    pub length: u64,
+    pub name: String,
+    pub uid: String,
+    pub gid: String,
+    pub muid: String,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub(crate) enum Fcall {
+    Tversion {
+        msize: u32,
+        version: String,
+    },
+    Rversion {
+        msize: u32,
+        version: String,
+    },
+    Tauth {
+        afid: u32,
+        uname: String,
+        aname: String,
+    },
+    Tattach {
+        fid: u32,
+        afid: u32,
+        uname: String,
+        aname: String,
+    },
+    Rattach {
+        qid: Qid,
+    },
+    Rerror {
+        ename: String,
+    },
+    Tflush {
+        oldtag: u16,
+    },
+    Rflush,
+    Twalk {
+        fid: u32,
+        newfid: u32,
+        wnames: Vec<String>,
+    },
+    Rwalk {
+        qids: Vec<Qid>,
+    },
+    Topen {
+        fid: u32,
+        mode: u8,
+    },
+    Ropen {
+        qid: Qid,
+        iounit: u32,
+    },
+    Tcreate {
+        fid: u32,
+        name: String,
+        perm: u32,
+        mode: u8,
+    },
+    Rcreate {
+        qid: Qid,
+        iounit: u32,
+    },
+    Tread {
+        fid: u32,
+        offset: u64,
+        count: u32,
+    },
+    Rread {
+        data: Vec<u8>,
+    },
+    Twrite {
+        fid: u32,
+        offset: u64,
+        data: Vec<u8>,
+    },
+    Rwrite {
+        count: u32,
+    },
+    Tclunk {
+        fid: u32,
+    },
+    Rclunk,
+    Tremove {
+        fid: u32,
+    },
+    Rremove,
+    Tstat {
+        fid: u32,
+    },
+    Rstat {
+        stat: Stat,
+    },
+    Twstat {
+        fid: u32,
+        stat: Stat,
+    },
+    Rwstat,
+}
+
+impl Fcall {
+    fn kind(&self) -> u8 {
+        match self {
+            Fcall::Tversion { .. } => 100,
+            Fcall::Rversion { .. } => 101,
+            Fcall::Tauth { .. } => 102,
+            Fcall::Tattach { .. } => 104,
+            Fcall::Rattach { .. } => 105,
+            Fcall::Rerror { .. } => 107,
+            Fcall::Tflush { .. } => 108,
+            Fcall::Rflush => 109,
+            Fcall::Twalk { .. } => 110,
+            Fcall::Rwalk { .. } => 111,
+            Fcall::Topen { .. } => 112,
+            Fcall::Ropen { .. } => 113,
+            Fcall::Tcreate { .. } => 114,
+            Fcall::Rcreate { .. } => 115,
+            Fcall::Tread { .. } => 116,
+            Fcall::Rread { .. } => 117,
+            Fcall::Twrite { .. } => 118,
+            Fcall::Rwrite { .. } => 119,
+            Fcall::Tclunk { .. } => 120,
+            Fcall::Rclunk => 121,
+            Fcall::Tremove { .. } => 122,
+            Fcall::Rremove => 123,
+            Fcall::Tstat { .. } => 124,
+            Fcall::Rstat { .. } => 125,
+            Fcall::Twstat { .. } => 126,
+            Fcall::Rwstat => 127,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub(crate) struct Message {
+    pub(crate) tag: u16,
+    pub(crate) body: Fcall,
+}
+
+impl Message {
+    pub(crate) fn new(tag: u16, body: Fcall) -> Self {
+        Message { tag, body }
+    }
+
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut e = Encoder::new();
+        e.u32(0); // Filled in below, once we know it.
+        e.u8(self.body.kind());
+        e.u16(self.tag);
+
+        match &self.body {
+            Fcall::Tversion { msize, version } | Fcall::Rversion { msize, version } => {
+                e.u32(*msize);
+                e.str(version);
+            }
+            Fcall::Tauth { afid, uname, aname } => {
+                e.u32(*afid);
+                e.str(uname);
+                e.str(aname);
+            }
+            Fcall::Tattach {
+                fid,
+                afid,
+                uname,
+                aname,
+            } => {
+                e.u32(*fid);
+                e.u32(*afid);
+                e.str(uname);
+                e.str(aname);
+            }
+            Fcall::Rattach { qid } => e.qid(qid),
+            Fcall::Rerror { ename } => e.str(ename),
+            Fcall::Tflush { oldtag } => e.u16(*oldtag),
+            Fcall::Twalk {
+                fid,
+                newfid,
+                wnames,
+            } => {
+                e.u32(*fid);
+                e.u32(*newfid);
+                e.u16(wnames.len() as u16);
+                wnames.iter().for_each(|n| e.str(n));
+            }
+            Fcall::Rwalk { qids } => {
+                e.u16(qids.len() as u16);
+                qids.iter().for_each(|q| e.qid(q));
+            }
+            Fcall::Topen { fid, mode } => {
+                e.u32(*fid);
+                e.u8(*mode);
+            }
+            Fcall::Ropen { qid, iounit } | Fcall::Rcreate { qid, iounit } => {
+                e.qid(qid);
+                e.u32(*iounit);
+            }
+            Fcall::Tcreate {
+                fid,
+                name,
+                perm,
+                mode,
+            } => {
+                e.u32(*fid);
+                e.str(name);
+                e.u32(*perm);
+                e.u8(*mode);
+            }
+            Fcall::Tread { fid, offset, count } => {
+                e.u32(*fid);
+                e.u64(*offset);
+                e.u32(*count);
+            }
+            Fcall::Rread { data } => e.data(data),
+            Fcall::Twrite { fid, offset, data } => {
+                e.u32(*fid);
+                e.u64(*offset);
+                e.data(data);
+            }
+            Fcall::Rwrite { count } => e.u32(*count),
+            Fcall::Tclunk { fid } | Fcall::Tremove { fid } | Fcall::Tstat { fid } => e.u32(*fid),
+            Fcall::Rstat { stat } => {
+                // The stat is wrapped in a second size, for historical reasons.
+                let stat = encode_stat(stat);
+                e.u16(stat.len() as u16);
+                e.bytes(&stat);
+            }
+            Fcall::Twstat { fid, stat } => {
+                e.u32(*fid);
+                let stat = encode_stat(stat);
+                e.u16(stat.len() as u16);
+                e.bytes(&stat);
+            }
+            Fcall::Rflush | Fcall::Rclunk | Fcall::Rremove | Fcall::Rwstat => {}
+        }
+
+        let mut buf = e.buf;
+        let size = buf.len() as u32;
+        buf[0..4].copy_from_slice(&size.to_le_bytes());
+        buf
+    }
+
+    // Decode a whole message, including its size.
+    pub(crate) fn decode(buf: &[u8]) -> io::Result<Message> {
+        let mut d = Decoder::new(buf);
+        let size = d.u32()?;
+        if size as usize != buf.len() {
+            return Err(invalid("message size doesn't match its length"));
+        }
+        let kind = d.u8()?;
+        let tag = d.u16()?;
+
+        let body = match kind {
+            100 => Fcall::Tversion {
+                msize: d.u32()?,
+                version: d.str()?,
+            },
+            101 => Fcall::Rversion {
+                msize: d.u32()?,
+                version: d.str()?,
+            },
+            102 => Fcall::Tauth {
+                afid: d.u32()?,
+                uname: d.str()?,
+                aname: d.str()?,
+            },
+            104 => Fcall::Tattach {
+                fid: d.u32()?,
+                afid: d.u32()?,
+                uname: d.str()?,
+                aname: d.str()?,
+            },
+            105 => Fcall::Rattach { qid: d.qid()? },
+            107 => Fcall::Rerror { ename: d.str()? },
+            108 => Fcall::Tflush { oldtag: d.u16()? },
+            109 => Fcall::Rflush,
+            110 => {
+                let fid = d.u32()?;
+                let newfid = d.u32()?;
+                let count = d.u16()?;
+                let wnames = (0..count).map(|_| d.str()).collect::<io::Result<_>>()?;
+                Fcall::Twalk {
+                    fid,
+                    newfid,
+                    wnames,
+                }
+            }
+            111 => {
+                let count = d.u16()?;
+                let qids = (0..count).map(|_| d.qid()).collect::<io::Result<_>>()?;
+                Fcall::Rwalk { qids }
+            }
+            112 => Fcall::Topen {
+                fid: d.u32()?,
+                mode: d.u8()?,
+            },
+            113 => Fcall::Ropen {
+                qid: d.qid()?,
+                iounit: d.u32()?,
+            },
+            114 => Fcall::Tcreate {
+                fid: d.u32()?,
+                name: d.str()?,
+                perm: d.u32()?,
+                mode: d.u8()?,
+            },
+            115 => Fcall::Rcreate {
+                qid: d.qid()?,
+                iounit: d.u32()?,
+            },
+            116 => Fcall::Tread {
+                fid: d.u32()?,
+                offset: d.u64()?,
+                count: d.u32()?,
+            },
+            117 => Fcall::Rread { data: d.data()? },
+            118 => Fcall::Twrite {
+                fid: d.u32()?,
+                offset: d.u64()?,
+                data: d.data()?,
+            },
+            119 => Fcall::Rwrite { count: d.u32()? },
+            120 => Fcall::Tclunk { fid: d.u32()? },
+            121 => Fcall::Rclunk,
+            122 => Fcall::Tremove { fid: d.u32()? },
+            123 => Fcall::Rremove,
+            124 => Fcall::Tstat { fid: d.u32()? },
+            125 => {
+                d.u16()?;
+                Fcall::Rstat { stat: d.stat()? }
+            }
+            126 => {
+                let fid = d.u32()?;
+                d.u16()?;
+                Fcall::Twstat {
+                    fid,
+                    stat: d.stat()?,
+                }
+            }
+            127 => Fcall::Rwstat,
+            _ => return Err(invalid("unknown message type")),
+        };
+
+        if !d.is_empty() {
+            return Err(invalid("message has trailing bytes"));
+        }
+        Ok(Message { tag, body })
+    }
+
+    // Read one message, refusing any larger than max_size.
+    pub(crate) fn read_from<R: Read>(reader: &mut R, max_size: u32) -> io::Result<Message> {
+        let mut size = [0; 4];
+        reader.read_exact(&mut size)?;
+        let size = u32::from_le_bytes(size);
+        if !(HEADER_SIZE..=max_size).contains(&size) {
+            return Err(invalid("message size out of range"));
+        }
+
+        let mut buf = vec![0; size as usize];
+        buf[0..4].copy_from_slice(&size.to_le_bytes());
+        reader.read_exact(&mut buf[4..])?;
+        Message::decode(&buf)
+    }
+
+    pub(crate) fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&self.encode())?;
+        writer.flush()
+    }
+}
+
+// A stat as it appears in a directory read, including its leading size.
+pub(crate) fn encode_stat(stat: &Stat) -> Vec<u8> {
+    let mut e = Encoder::new();
+    e.u16(0); // Filled in below, once we know it.
+    e.u16(stat.kind);
+    e.u32(stat.dev);
+    e.qid(&stat.qid);
+    e.u32(stat.mode);
+    e.u32(stat.atime);
+    e.u32(stat.mtime);
+    e.u64(stat.length);
+    e.str(&stat.name);
+    e.str(&stat.uid);
+    e.str(&stat.gid);
+    e.str(&stat.muid);
+
+    let mut buf = e.buf;
+    let size = (buf.len() - 2) as u16;
+    buf[0..2].copy_from_slice(&size.to_le_bytes());
+    buf
+}
+
+// Split the data from a directory read into its stats.
+pub(crate) fn decode_stats(data: &[u8]) -> io::Result<Vec<Stat>> {
+    let mut d = Decoder::new(data);
+    let mut stats = vec![];
+    while !d.is_empty() {
+        stats.push(d.stat()?);
+    }
+    Ok(stats)
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+struct Encoder {
+    buf: Vec<u8>,
+}
+
+impl Encoder {
+    fn new() -> Self {
+        Encoder { buf: vec![] }
+    }
+
+    fn u8(&mut self, v: u8) {
+        self.buf.push(v);
+    }
+
+    fn u16(&mut self, v: u16) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn u32(&mut self, v: u32) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn u64(&mut self, v: u64) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn bytes(&mut self, v: &[u8]) {
+        self.buf.extend_from_slice(v);
+    }
+
+    fn str(&mut self, v: &str) {
+        self.u16(v.len() as u16);
+        self.bytes(v.as_bytes());
+    }
+
+    fn data(&mut self, v: &[u8]) {
+        self.u32(v.len() as u32);
+        self.bytes(v);
+    }
+
+    fn qid(&mut self, qid: &Qid) {
+        self.u8(qid.kind);
+        self.u32(qid.version);
+        self.u64(qid.path);
+    }
+}
+
+struct Decoder<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Decoder { buf }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    fn take(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        if self.buf.len() < n {
+            return Err(invalid("message is too short"));
+        }
+        let (head, tail) = self.buf.split_at(n);
+        self.buf = tail;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn str(&mut self) -> io::Result<String> {
+        let len = self.u16()? as usize;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|_| invalid("string isn't UTF-8"))
+    }
+
+    fn data(&mut self) -> io::Result<Vec<u8>> {
+        let len = self.u32()? as usize;
+        Ok(self.take(len)?.to_vec())
+    }
+
+    fn qid(&mut self) -> io::Result<Qid> {
+        Ok(Qid {
+            kind: self.u8()?,
+            version: self.u32()?,
+            path: self.u64()?,
+        })
+    }
+
+    fn stat(&mut self) -> io::Result<Stat> {
+        let size = self.u16()? as usize;
+        let mut d = Decoder::new(self.take(size)?);
+        let stat = Stat {
+            kind: d.u16()?,
+            dev: d.u32()?,
+            qid: d.qid()?,
+            mode: d.u32()?,
+            atime: d.u32()?,
+            mtime: d.u32()?,
+            length: d.u64()?,
+            name: d.str()?,
+            uid: d.str()?,
+            gid: d.str()?,
+            muid: d.str()?,
+        };
+        if !d.is_empty() {
+            return Err(invalid("stat has trailing bytes"));
+        }
+        Ok(stat)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_stats, encode_stat, Fcall, Message, Qid, Stat, QTDIR};
+
+    fn stat(name: &str) -> Stat {
+        Stat {
+            kind: 0,
+            dev: 0,
+            qid: Qid {
+                kind: QTDIR,
+                version: 0,
+                path: 7,
+            },
+            mode: 0o755,
+            atime: 0,
+            mtime: 0,
+            length: 0,
+            name: name.to_string(),
+            uid: "none".to_string(),
+            gid: "none".to_string(),
+            muid: "none".to_string(),
+        }
+    }
+
+    #[test]
+    fn messages_should_round_trip() {
+        let messages = [
+            Fcall::Tversion {
+                msize: 8192,
+                version: "9P2000".to_string(),
+            },
+            Fcall::Twalk {
+                fid: 1,
+                newfid: 2,
+                wnames: vec!["photos".to_string(), "2021".to_string()],
+            },
+            Fcall::Rread {
+                data: vec![1, 2, 3],
+            },
+            Fcall::Rstat { stat: stat("a") },
+            Fcall::Twstat {
+                fid: 3,
+                stat: stat("b"),
+            },
+            Fcall::Rclunk,
+        ];
+
+        for body in messages {
+            let message = Message::new(5, body);
+            assert_eq!(message, Message::decode(&message.encode()).unwrap());
+        }
+    }
+
+    #[test]
+    fn tversion_should_encode_as_in_the_spec() {
+        let message = Message::new(
+            0xFFFF,
+            Fcall::Tversion {
+                msize: 8192,
+                version: "9P2000".to_string(),
+            },
+        );
+
+        let expected = [
+            19, 0, 0, 0, 100, 0xFF, 0xFF, 0x00, 0x20, 0, 0, 6, 0, b'9', b'P', b'2', b'0', b'0',
+            b'0',
+        ];
+
+        assert_eq!(expected.to_vec(), message.encode());
+    }
+
+    #[test]
+    fn decode_should_reject_bad_messages() {
+        let mut bytes = Message::new(1, Fcall::Tclunk { fid: 4 }).encode();
+        bytes.push(0);
+        assert!(Message::decode(&bytes).is_err());
+
+        let len = bytes.len() as u32 - 1;
+        bytes[0..4].copy_from_slice(&len.to_le_bytes());
+        assert!(Message::decode(&bytes).is_err());
+    }
+
+    #[test]
+    fn stats_should_split() {
+        let mut data = encode_stat(&stat("a"));
+        data.extend(encode_stat(&stat("b")));
+
+        assert_eq!(vec![stat("a"), stat("b")], decode_stats(&data).unwrap());
+    }
+}
@@ -0,0 +1,428 @@
+pub(crate) mod client;
+pub(crate) mod message;
+
+use crate::vfs::{Attr, EntryKind, Vfs, VfsError, ROOT_INODE};
+use crate::FileDB;
+use message::{encode_stat, Fcall, Message, Qid, Stat, DMDIR, IOHDRSZ, OREAD};
+use message::{QTDIR, QTFILE, VERSION};
+use std::collections::hash_map::HashMap;
+use std::io::{self, Read, Write};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+// The largest message we will agree to.
+pub const MAX_MSIZE: u32 = 64 * 1024;
+
+// The smallest; anything less can't hold a useful read reply or error.
+pub const MIN_MSIZE: u32 = 256;
+
+// Serves a Vfs over 9P2000, so the tag view of a database can be mounted
+// by any 9P client. Walking into a directory adds a tag to the query, and
+// reading a directory lists the matching files and the tags that narrow
+// it. Files have no content; reading one gives nothing, and writing fails.
+//
+// Every connection shares the one Vfs, so they all see the same inodes.
+pub struct NinePServer<DB: FileDB> {
+    vfs: Arc<Mutex<Vfs<DB>>>,
+}
+
+impl<DB: FileDB> Clone for NinePServer<DB> {
+    fn clone(&self) -> Self {
+        NinePServer {
+            vfs: Arc::clone(&self.vfs),
+        }
+    }
+}
+
+impl<DB: FileDB> NinePServer<DB> {
+    pub fn new(vfs: Vfs<DB>) -> Self {
+        NinePServer {
+            vfs: Arc::new(Mutex::new(vfs)),
+        }
+    }
+
+    // The served Vfs, for changing the database while clients are connected.
+    pub fn vfs(&self) -> &Arc<Mutex<Vfs<DB>>> {
+        &self.vfs
+    }
+
+    // Answer requests on a single connection until the client hangs up.
+    pub fn serve<S: Read + Write>(&self, mut stream: S) -> io::Result<()> {
+        let mut session = Session::new(self);
+        loop {
+            let request = match Message::read_from(&mut stream, session.msize) {
+                Ok(request) => request,
+                // A clean hang up between messages.
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+                Err(e) => return Err(e),
+            };
+            let reply = Message::new(request.tag, session.handle(request.body));
+            reply.write_to(&mut stream)?;
+        }
+    }
+}
+
+impl<DB: FileDB + Send + 'static> NinePServer<DB> {
+    // Accept connections forever, serving each on its own thread.
+    pub fn serve_tcp(&self, listener: TcpListener) -> io::Result<()> {
+        for stream in listener.incoming() {
+            let server = self.clone();
+            let stream = stream?;
+            thread::spawn(move || server.serve(stream));
+        }
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    pub fn serve_unix(&self, listener: std::os::unix::net::UnixListener) -> io::Result<()> {
+        for stream in listener.incoming() {
+            let server = self.clone();
+            let stream = stream?;
+            thread::spawn(move || server.serve(stream));
+        }
+        Ok(())
+    }
+}
+
+// What a client's fid points at.
+#[derive(Clone)]
+struct Fid {
+    // The inodes and names walked from the root to this entry, so ".." and
+    // remove know the parent; a tag directory can be reached from many.
+    path: Vec<(u64, String)>,
+    open: bool,
+    // A directory's listing, taken when it is first read, so that reads at
+    // later offsets continue the same listing.
+    listing: Vec<Vec<u8>>,
+}
+
+impl Fid {
+    fn root() -> Self {
+        Fid {
+            path: vec![(ROOT_INODE, "/".to_string())],
+            open: false,
+            listing: vec![],
+        }
+    }
+
+    fn ino(&self) -> u64 {
+        self.path.last().unwrap().0
+    }
+
+    fn name(&self) -> &str {
+        &self.path.last().unwrap().1
+    }
+
+    fn parent(&self) -> Option<u64> {
+        self.path.iter().rev().nth(1).map(|(ino, _)| *ino)
+    }
+}
+
+struct Session<'a, DB: FileDB> {
+    server: &'a NinePServer<DB>,
+    fids: HashMap<u32, Fid>,
+    msize: u32,
+}
+
+type Reply = Result<Fcall, String>;
+
+impl<'a, DB: FileDB> Session<'a, DB> {
+    fn new(server: &'a NinePServer<DB>) -> Self {
+        Session {
+            server,
+            fids: HashMap::new(),
+            msize: MAX_MSIZE,
+        }
+    }
+
+    fn handle(&mut self, request: Fcall) -> Fcall {
+        let reply = match request {
+            Fcall::Tversion { msize, version } => Ok(self.version(msize, &version)),
+            Fcall::Tauth { .. } => Err("authentication not required".to_string()),
+            Fcall::Tattach { fid, .. } => self.attach(fid),
+            Fcall::Tflush { .. } => Ok(Fcall::Rflush),
+            Fcall::Twalk {
+                fid,
+                newfid,
+                wnames,
+            } => self.walk(fid, newfid, &wnames),
+            Fcall::Topen { fid, mode } => self.open(fid, mode),
+            Fcall::Tcreate {
+                fid, name, perm, ..
+            } => self.create(fid, &name, perm),
+            Fcall::Tread { fid, offset, count } => self.read(fid, offset, count),
+            Fcall::Twrite { .. } => Err("files have no content".to_string()),
+            Fcall::Tclunk { fid } => self.clunk(fid),
+            Fcall::Tremove { fid } => self.remove(fid),
+            Fcall::Tstat { fid } => self.stat(fid),
+            Fcall::Twstat { fid, stat } => self.wstat(fid, &stat),
+            _ => Err("unexpected message".to_string()),
+        };
+        reply.unwrap_or_else(|ename| Fcall::Rerror { ename })
+    }
+
+    fn vfs(&self) -> std::sync::MutexGuard<'_, Vfs<DB>> {
+        // A panic while serving another connection doesn't leave the Vfs
+        // half changed, so carry on with it.
+        self.server
+            .vfs
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn fid(&self, fid: u32) -> Result<&Fid, String> {
+        self.fids.get(&fid).ok_or_else(|| "unknown fid".to_string())
+    }
+
+    fn new_fid(&mut self, fid: u32, state: Fid) -> Result<(), String> {
+        if self.fids.contains_key(&fid) {
+            return Err("fid in use".to_string());
+        }
+        self.fids.insert(fid, state);
+        Ok(())
+    }
+
+    fn version(&mut self, msize: u32, version: &str) -> Fcall {
+        // A version message starts a new session.
+        self.fids.clear();
+        // A client offering too small a size is refused, as for an unknown
+        // version, but the size stays large enough to send the refusal.
+        self.msize = msize.clamp(MIN_MSIZE, MAX_MSIZE);
+        let version = if version.starts_with(VERSION) && msize >= MIN_MSIZE {
+            VERSION
+        } else {
+            "unknown"
+        };
+        Fcall::Rversion {
+            msize: self.msize,
+            version: version.to_string(),
+        }
+    }
+
+    fn attach(&mut self, fid: u32) -> Reply {
+        self.new_fid(fid, Fid::root())?;
+        let attr = self.vfs().getattr(ROOT_INODE).map_err(ename)?;
+        Ok(Fcall::Rattach { qid: qid(&attr) })
+    }
+
+    fn walk(&mut self, fid: u32, newfid: u32, wnames: &[String]) -> Reply {
+        let mut state = self.fid(fid)?.clone();
+        if state.open {
+            return Err("fid is open".to_string());
+        }
+        if newfid != fid && self.fids.contains_key(&newfid) {
+            return Err("fid in use".to_string());
+        }
+
+        let mut qids = vec![];
+        {
+            let mut vfs = self.vfs();
+            for name in wnames {
+                let attr = if name == ".." {
+                    if state.path.len() > 1 {
+                        state.path.pop();
+                    }
+                    vfs.getattr(state.ino())
+                } else {
+                    vfs.lookup(state.ino(), name)
+                        .inspect(|attr| state.path.push((attr.ino, name.clone())))
+                };
+                match attr {
+                    Ok(attr) => qids.push(qid(&attr)),
+                    // Only a failure on the first name is an error; otherwise
+                    // the client learns how far it got.
+                    Err(e) if qids.is_empty() => return Err(ename(e)),
+                    Err(_) => break,
+                }
+            }
+        }
+
+        if qids.len() == wnames.len() {
+            state.listing = vec![];
+            self.fids.insert(newfid, state);
+        }
+        Ok(Fcall::Rwalk { qids })
+    }
+
+    fn open(&mut self, fid: u32, mode: u8) -> Reply {
+        let ino = self.fid(fid)?.ino();
+        let attr = self.vfs().getattr(ino).map_err(ename)?;
+        if attr.kind == EntryKind::Directory && mode & 3 != OREAD {
+            return Err(ename(VfsError::IsADirectory));
+        }
+        let state = self.fids.get_mut(&fid).unwrap();
+        state.open = true;
+        Ok(Fcall::Ropen {
+            qid: qid(&attr),
+            iounit: 0,
+        })
+    }
+
+    // Make a file, or with DMDIR a tag, in the directory; the fid then
+    // refers to the new entry.
+    fn create(&mut self, fid: u32, name: &str, perm: u32) -> Reply {
+        let dir = self.fid(fid)?.ino();
+        let attr = if perm & DMDIR != 0 {
+            self.vfs().mkdir(dir, name)
+        } else {
+            self.vfs().create(dir, name)
+        }
+        .map_err(ename)?;
+
+        let state = self.fids.get_mut(&fid).unwrap();
+        state.path.push((attr.ino, name.to_string()));
+        state.open = true;
+        Ok(Fcall::Rcreate {
+            qid: qid(&attr),
+            iounit: 0,
+        })
+    }
+
+    fn read(&mut self, fid: u32, offset: u64, count: u32) -> Reply {
+        let state = self.fid(fid)?;
+        if !state.open {
+            return Err("fid not open".to_string());
+        }
+        let ino = state.ino();
+        let attr = self.vfs().getattr(ino).map_err(ename)?;
+        if attr.kind == EntryKind::File {
+            return Ok(Fcall::Rread { data: vec![] });
+        }
+
+        if offset == 0 {
+            let listing = self
+                .vfs()
+                .readdir(ino)
+                .map_err(ename)?
+                .into_iter()
+                .map(|e| {
+                    let attr = Attr {
+                        ino: e.ino,
+                        kind: e.kind,
+                    };
+                    encode_stat(&stat(&attr, e.name))
+                })
+                .collect();
+            self.fids.get_mut(&fid).unwrap().listing = listing;
+        }
+
+        // Directory reads must return whole entries, starting where the last
+        // read stopped.
+        let count = count.min(self.msize.saturating_sub(IOHDRSZ)) as usize;
+        let mut start = 0;
+        let mut data = vec![];
+        for entry in &self.fid(fid)?.listing {
+            if (start as u64) < offset {
+                start += entry.len();
+                continue;
+            }
+            if start as u64 != offset {
+                return Err("bad directory offset".to_string());
+            }
+            if data.len() + entry.len() > count {
+                break;
+            }
+            data.extend_from_slice(entry);
+        }
+        Ok(Fcall::Rread { data })
+    }
+
+    fn clunk(&mut self, fid: u32) -> Reply {
+        self.fids
+            .remove(&fid)
+            .map(|_| Fcall::Rclunk)
+            .ok_or_else(|| "unknown fid".to_string())
+    }
+
+    // The fid is clunked even if the remove fails.
+    fn remove(&mut self, fid: u32) -> Reply {
+        let state = self
+            .fids
+            .remove(&fid)
+            .ok_or_else(|| "unknown fid".to_string())?;
+        let parent = state
+            .parent()
+            .ok_or_else(|| "can't remove the root".to_string())?;
+        self.vfs().unlink(parent, state.name()).map_err(ename)?;
+        Ok(Fcall::Rremove)
+    }
+
+    fn stat(&mut self, fid: u32) -> Reply {
+        let state = self.fid(fid)?;
+        let name = state.name().to_string();
+        let attr = self.vfs().getattr(state.ino()).map_err(ename)?;
+        Ok(Fcall::Rstat {
+            stat: stat(&attr, name),
+        })
+    }
+
+    // Only renaming within the same directory is supported; everything else
+    // in the stat is ignored.
+    fn wstat(&mut self, fid: u32, new: &Stat) -> Reply {
+        let state = self.fid(fid)?;
+        if new.name.is_empty() || new.name == state.name() {
+            return Ok(Fcall::Rwstat);
+        }
+        let parent = state
+            .parent()
+            .ok_or_else(|| "can't rename the root".to_string())?;
+        let name = state.name().to_string();
+
+        let attr = {
+            let mut vfs = self.vfs();
+            vfs.rename(parent, &name, parent, &new.name)
+                .and_then(|()| vfs.lookup(parent, &new.name))
+                .map_err(ename)?
+        };
+        let state = self.fids.get_mut(&fid).unwrap();
+        state.path.pop();
+        state.path.push((attr.ino, new.name.clone()));
+        Ok(Fcall::Rwstat)
+    }
+}
+
+fn qid(attr: &Attr) -> Qid {
+    Qid {
+        kind: match attr.kind {
+            EntryKind::Directory => QTDIR,
+            EntryKind::File => QTFILE,
+        },
+        version: 0,
+        path: attr.ino,
+    }
+}
+
+fn stat(attr: &Attr, name: String) -> Stat {
+    let mode = match attr.kind {
+        EntryKind::Directory => DMDIR | 0o755,
+        EntryKind::File => 0o644,
+    };
+    Stat {
+        kind: 0,
+        dev: 0,
+        qid: qid(attr),
+        mode,
+        atime: 0,
+        mtime: 0,
+        length: 0,
+        name,
+        uid: "none".to_string(),
+        gid: "none".to_string(),
+        muid: "none".to_string(),
+    }
+}
+
+fn ename(error: VfsError) -> String {
+    match error {
+        VfsError::NotFound => "file not found",
+        VfsError::NotADirectory => "not a directory",
+        VfsError::IsADirectory => "is a directory",
+        VfsError::Exists => "file exists",
+        VfsError::InvalidName => "invalid name",
+        VfsError::Ambiguous(_) => "ambiguous file name",
+        VfsError::Add(_) => "can't add file",
+        VfsError::Lost { .. } => "file lost",
+    }
+    .to_string()
+}
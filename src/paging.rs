@@ -0,0 +1,293 @@
+use crate::fromstr::{FromStr, ParseError, ParseErrorKind};
+use crate::File;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::fmt;
+
+// What to sort a page of results by. Ties are broken by the name and then
+// the tags, so every order is total and pages don't depend on the backend.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum SortOrder {
+    #[default]
+    Name,
+    TagCount,
+}
+
+// How to order and cut down the results of a query. By default every
+// result is returned, sorted by name.
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct QueryOptions {
+    order: SortOrder,
+    descending: bool,
+    limit: Option<usize>,
+    after: Option<Cursor>,
+}
+
+impl QueryOptions {
+    pub fn new() -> Self {
+        QueryOptions::default()
+    }
+
+    pub fn order_by(mut self, order: SortOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    pub fn descending(mut self) -> Self {
+        self.descending = true;
+        self
+    }
+
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    // Start after the file the cursor was taken at. The cursor should come
+    // from a page fetched with the same order.
+    pub fn after(mut self, cursor: Cursor) -> Self {
+        self.after = Some(cursor);
+        self
+    }
+
+    fn compare(&self, a: &File, b: &File) -> Ordering {
+        let ordering = match self.order {
+            SortOrder::Name => a.cmp(b),
+            SortOrder::TagCount => a.tags.len().cmp(&b.tags.len()).then_with(|| a.cmp(b)),
+        };
+        if self.descending {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    }
+}
+
+// Where a page of results ended, to carry on from in the next call. It
+// holds the last file returned, so pages stay consistent even if files are
+// added or removed in between. The string form is opaque to clients.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Cursor {
+    last: File,
+}
+
+impl fmt::Display for Cursor {
+    // Each string as its length then its bytes, all in hex, so the cursor
+    // can go in a URL as it is.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for s in std::iter::once(&self.last.name).chain(&self.last.tags) {
+            write!(f, "{:08x}", s.len())?;
+            for b in s.bytes() {
+                write!(f, "{:02x}", b)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Cursor {
+    fn from_str(s: &str) -> Result<Cursor, ParseError> {
+        // One string from the start of rest, and its length in hex.
+        fn string(rest: &str) -> Option<(String, usize)> {
+            let len = usize::from_str_radix(rest.get(..8)?, 16).ok()?;
+            let hex = rest.get(8..8 + len.checked_mul(2)?)?;
+            let bytes = (0..len)
+                .map(|i| u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).ok())
+                .collect::<Option<Vec<u8>>>()?;
+            Some((String::from_utf8(bytes).ok()?, 8 + 2 * len))
+        }
+
+        let mut strings = vec![];
+        let mut rest = s;
+        while !rest.is_empty() {
+            match string(rest) {
+                Some((string, used)) => {
+                    strings.push(string);
+                    rest = &rest[used..];
+                }
+                None => {
+                    return Err(ParseError::new(s.len() - rest.len(), ParseErrorKind::BadCursor))
+                }
+            }
+        }
+        let mut strings = strings.into_iter();
+        match strings.next() {
+            Some(name) => Ok(Cursor {
+                last: File::new(name, strings.collect()),
+            }),
+            None => Err(ParseError::new(0, ParseErrorKind::BadCursor)),
+        }
+    }
+}
+
+// One page of the results of a query.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct ResultPage {
+    pub files: Vec<File>,
+    // Where to carry on from, if there are more results.
+    pub next: Option<Cursor>,
+}
+
+// A file in a heap, ordered by the options.
+struct Ranked<'a> {
+    file: File,
+    options: &'a QueryOptions,
+}
+
+impl PartialEq for Ranked<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Ranked<'_> {}
+
+impl PartialOrd for Ranked<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Ranked<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.options.compare(&self.file, &other.file)
+    }
+}
+
+// Pick a page out of the files. With a limit of k only the first k + 1
+// files are kept, in a heap, so this takes O(n log k) rather than sorting
+// everything.
+pub(crate) fn page<I: Iterator<Item = File>>(files: I, options: &QueryOptions) -> ResultPage {
+    let mut files = files.filter(|f| match &options.after {
+        Some(cursor) => options.compare(f, &cursor.last) == Ordering::Greater,
+        None => true,
+    });
+
+    let mut sorted = match options.limit {
+        Some(limit) => {
+            // One more than the limit, to tell whether there's another page.
+            // The heap grows as files come, as the limit may be far more
+            // than there are.
+            let keep = limit.saturating_add(1);
+            let mut heap = BinaryHeap::new();
+            for file in &mut files {
+                heap.push(Ranked { file, options });
+                if heap.len() > keep {
+                    heap.pop();
+                }
+            }
+            heap.into_sorted_vec().into_iter().map(|r| r.file).collect()
+        }
+        None => {
+            let mut all: Vec<File> = files.collect();
+            all.sort_by(|a, b| options.compare(a, b));
+            all
+        }
+    };
+
+    let next = match options.limit {
+        Some(limit) if sorted.len() > limit => {
+            sorted.truncate(limit);
+            sorted.last().map(|last| Cursor { last: last.clone() })
+        }
+        _ => None,
+    };
+    ResultPage {
+        files: sorted,
+        next,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{page, Cursor, QueryOptions, SortOrder};
+    use crate::fromstr::FromStr;
+    use crate::File;
+
+    fn files() -> Vec<File> {
+        ["/a/b/c/one", "/two", "/a/three", "/b/three", "/a/b/four"]
+            .iter()
+            .map(|p| File::from_str(p).unwrap())
+            .collect()
+    }
+
+    fn paths(files: &[File]) -> Vec<String> {
+        files
+            .iter()
+            .map(|f| {
+                let tags: Vec<&str> = f.tags.iter().map(|t| t.as_str()).collect();
+                format!("{}/{}", tags.join("/"), f.name)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn cursor_should_round_trip_through_a_string() {
+        let cursor = Cursor {
+            last: File::new_cloned("snow 1/2.jpg", ["photos", "", "ünïcode"]),
+        };
+        let encoded = cursor.to_string();
+
+        assert!(encoded.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_eq!(Ok(cursor), Cursor::from_str(&encoded));
+        assert!(Cursor::from_str("").is_err());
+        assert!(Cursor::from_str("00000002ff").is_err());
+        assert!(Cursor::from_str("00000001zz").is_err());
+        assert!(Cursor::from_str("ffffffffffffffff").is_err());
+    }
+
+    #[test]
+    fn page_should_sort_by_the_requested_order() {
+        let by_name = page(files().into_iter(), &QueryOptions::new());
+        assert_eq!(
+            vec!["a/b/four", "a/b/c/one", "a/three", "b/three", "/two"],
+            paths(&by_name.files)
+        );
+        assert_eq!(None, by_name.next);
+
+        let by_count = QueryOptions::new()
+            .order_by(SortOrder::TagCount)
+            .descending();
+        assert_eq!(
+            vec!["a/b/c/one", "a/b/four", "b/three", "a/three", "/two"],
+            paths(&page(files().into_iter(), &by_count).files)
+        );
+    }
+
+    #[test]
+    fn pages_should_follow_on_from_their_cursors() {
+        for options in [
+            QueryOptions::new(),
+            QueryOptions::new().order_by(SortOrder::TagCount),
+            QueryOptions::new().descending(),
+        ] {
+            let all = page(files().into_iter(), &options).files;
+            let mut paged = vec![];
+            let mut options = options.with_limit(2);
+            loop {
+                let result = page(files().into_iter().rev(), &options);
+                assert!(result.files.len() <= 2);
+                paged.extend(result.files);
+                match result.next {
+                    Some(cursor) => options = options.after(cursor),
+                    None => break,
+                }
+            }
+            assert_eq!(all, paged);
+        }
+    }
+
+    #[test]
+    fn a_limit_of_zero_should_return_nothing() {
+        let result = page(files().into_iter(), &QueryOptions::new().with_limit(0));
+        assert!(result.files.is_empty());
+        assert_eq!(None, result.next);
+    }
+
+    #[test]
+    fn a_huge_limit_should_return_everything() {
+        let result = page(files().into_iter(), &QueryOptions::new().with_limit(usize::MAX));
+        assert_eq!(files().len(), result.files.len());
+        assert_eq!(None, result.next);
+    }
+}
@@ -0,0 +1,154 @@
+use crate::fromstr::{components, FromStr, ParseError, ParseErrorKind};
+use crate::keyvalue::KeyPredicate;
+use crate::namepattern::NamePattern;
+use crate::File;
+use crate::FileQuery;
+use crate::TagSet;
+
+// A query that can combine plain tags, comparisons on structured
+// "key=value" tags, and either an exact file name or a name pattern.
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct Query {
+    tags: TagSet,
+    predicates: Vec<KeyPredicate>,
+    name: Option<String>,
+    name_pattern: Option<NamePattern>,
+}
+
+impl Query {
+    pub fn new(tags: TagSet) -> Self {
+        Query {
+            tags,
+            predicates: vec![],
+            name: None,
+            name_pattern: None,
+        }
+    }
+
+    pub fn with_predicate(mut self, predicate: KeyPredicate) -> Self {
+        self.predicates.push(predicate);
+        self
+    }
+
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    pub fn with_name_pattern(mut self, pattern: NamePattern) -> Self {
+        self.name_pattern = Some(pattern);
+        self
+    }
+
+    // The same query as any other, but with its tags replaced. Used by
+    // wrappers that rewrite queries before handing them on.
+    pub(crate) fn with_tags_of<F: FileQuery>(query: &F, tags: TagSet) -> Self {
+        Query {
+            tags,
+            predicates: query.predicates().to_vec(),
+            name: query.name().map(|x| x.to_string()),
+            name_pattern: query.name_pattern().cloned(),
+        }
+    }
+}
+
+impl FromStr for Query {
+    // Every component of the path is either a plain tag or a comparison on a
+    // structured tag, e.g. "/photos/year>=2020/size<10M". A quoted
+    // component is always a plain tag, e.g. "/\"a<b\"".
+    fn from_str(path: &str) -> Result<Query, ParseError> {
+        let mut query = Query::default();
+        for component in components(path)?.0 {
+            if component.quoted {
+                query.tags.insert(component.text);
+            } else if let Some(predicate) = KeyPredicate::parse(&component.text) {
+                query.predicates.push(predicate);
+            } else if component.text.contains(['<', '>', '=', '^']) {
+                // Looks like a comparison, but isn't a valid one.
+                return Err(ParseError::new(
+                    component.position,
+                    ParseErrorKind::BadComparison(component.text),
+                ));
+            } else {
+                query.tags.insert(component.text);
+            }
+        }
+        Ok(query)
+    }
+}
+
+impl FileQuery for Query {
+    fn could_match(&self, to_match: &File) -> bool {
+        to_match.has_tags(&self.tags)
+            && self
+                .predicates
+                .iter()
+                .all(|p| p.matches_any(&to_match.tags))
+            && self.name.as_ref().is_none_or(|n| n == &to_match.name)
+            && self
+                .name_pattern
+                .as_ref()
+                .is_none_or(|p| p.matches(&to_match.name))
+    }
+
+    fn tags(&self) -> &TagSet {
+        &self.tags
+    }
+
+    fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    fn predicates(&self) -> &[KeyPredicate] {
+        &self.predicates
+    }
+
+    fn name_pattern(&self) -> Option<&NamePattern> {
+        self.name_pattern.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Query;
+    use crate::fromstr::{FromStr, ParseError, ParseErrorKind};
+    use crate::{File, FileQuery, KeyPredicate, TagSet};
+
+    #[test]
+    fn should_split_tags_and_predicates() {
+        let expected = Query::new(TagSet::from_str("/photos").unwrap())
+            .with_predicate(KeyPredicate::parse("year>=2020").unwrap())
+            .with_predicate(KeyPredicate::parse("size<10M").unwrap());
+
+        assert_eq!(
+            Ok(expected),
+            Query::from_str("/photos/year>=2020/size<10M")
+        );
+    }
+
+    #[test]
+    fn should_reject_malformed_comparison() {
+        assert_eq!(
+            Err(ParseError::new(
+                8,
+                ParseErrorKind::BadComparison("year>".to_string())
+            )),
+            Query::from_str("/photos/year>")
+        );
+    }
+
+    #[test]
+    fn quoted_components_should_be_tags() {
+        let expected = Query::new(TagSet::from_iter(["a<b".to_string(), "c=d".to_string()]));
+        assert_eq!(Ok(expected), Query::from_str("/\"a<b\"/\"c=d\""));
+    }
+
+    #[test]
+    fn should_match_on_typed_value() {
+        let f = File::from_str("/photos/size=12M/holiday.jpg").unwrap();
+
+        assert!(Query::from_str("/photos/size>10M").unwrap().could_match(&f));
+        assert!(!Query::from_str("/photos/size<10M").unwrap().could_match(&f));
+        assert!(Query::from_str("/size=12582912").unwrap().could_match(&f));
+    }
+}
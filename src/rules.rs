@@ -0,0 +1,223 @@
+use crate::TagSet;
+use std::collections::btree_map::BTreeMap;
+use std::collections::btree_set::BTreeSet;
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum RuleError {
+    // Adding the rule would make a tag imply or alias itself. Holds the tags
+    // around the cycle, starting and ending with the same tag.
+    Cycle(Vec<String>),
+}
+
+// Rules that rewrite tags. An alias replaces one tag with another everywhere,
+// e.g. "pic" is an alias of "photo". An implication adds a tag whenever
+// another is present, e.g. "jpg" implies "image". Implications are followed
+// transitively, and apply to the canonical (de-aliased) names of both tags.
+#[derive(Debug, Clone, Default)]
+pub struct RuleSet {
+    aliases: BTreeMap<String, String>,
+    implications: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl RuleSet {
+    pub fn new() -> Self {
+        RuleSet {
+            aliases: BTreeMap::new(),
+            implications: BTreeMap::new(),
+        }
+    }
+
+    pub fn add_alias(&mut self, alias: &str, canonical: &str) -> Result<(), RuleError> {
+        let previous = self
+            .aliases
+            .insert(alias.to_string(), canonical.to_string());
+        self.check_or_revert(|rules| match previous {
+            Some(p) => {
+                rules.aliases.insert(alias.to_string(), p);
+            }
+            None => {
+                rules.aliases.remove(alias);
+            }
+        })
+    }
+
+    pub fn add_implication(&mut self, tag: &str, implied: &str) -> Result<(), RuleError> {
+        let added = self
+            .implications
+            .entry(tag.to_string())
+            .or_default()
+            .insert(implied.to_string());
+        self.check_or_revert(|rules| {
+            if added {
+                let targets = rules.implications.get_mut(tag).unwrap();
+                targets.remove(implied);
+                if targets.is_empty() {
+                    rules.implications.remove(tag);
+                }
+            }
+        })
+    }
+
+    fn check_or_revert<R: FnOnce(&mut Self)>(&mut self, revert: R) -> Result<(), RuleError> {
+        if let Some(cycle) = self.find_cycle() {
+            revert(self);
+            Err(RuleError::Cycle(cycle))
+        } else {
+            Ok(())
+        }
+    }
+
+    // Resolve a chain of aliases to the tag it finally names. Only safe to
+    // call once the rules are known to be free of cycles.
+    pub fn canonical<'a>(&'a self, tag: &'a str) -> &'a str {
+        let mut current = tag;
+        while let Some(next) = self.aliases.get(current) {
+            current = next;
+        }
+        current
+    }
+
+    // The tags a query should use; aliases replaced with what they name.
+    pub fn normalize(&self, tags: &TagSet) -> TagSet {
+        tags.iter().map(|t| self.canonical(t).to_string()).collect()
+    }
+
+    // The tags a file should be stored with; normalized, plus everything the
+    // tags imply.
+    pub fn expand(&self, tags: &TagSet) -> TagSet {
+        let mut result = TagSet::new();
+        let mut to_visit: Vec<String> = self.normalize(tags).into_iter().collect();
+        while let Some(t) = to_visit.pop() {
+            if result.insert(t.clone()) {
+                to_visit.extend(self.implied_by(&t).map(|x| x.to_string()));
+            }
+        }
+        result
+    }
+
+    // Canonical tags directly implied by a canonical tag. Implications that
+    // were declared on an alias of the tag also count.
+    fn implied_by<'a>(&'a self, canonical: &'a str) -> impl Iterator<Item = &'a str> {
+        self.implications
+            .iter()
+            .filter(move |(from, _)| self.canonical(from) == canonical)
+            .flat_map(|(_, to)| to.iter())
+            .map(|t| self.canonical(t))
+    }
+
+    // Aliases and implications both form edges of one graph; an alias must
+    // never lead back to itself, and neither may an implication, as that
+    // would make the tags equivalent, which is what an alias is for.
+    fn find_cycle(&self) -> Option<Vec<String>> {
+        let mut edges: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+        for (alias, canonical) in &self.aliases {
+            edges.entry(alias).or_default().insert(canonical);
+        }
+        for (from, to) in &self.implications {
+            for t in to {
+                edges.entry(from).or_default().insert(t);
+            }
+        }
+
+        let mut done = BTreeSet::new();
+        for start in edges.keys() {
+            let mut path = vec![];
+            if let Some(cycle) = Self::visit(start, &edges, &mut path, &mut done) {
+                return Some(cycle);
+            }
+        }
+        None
+    }
+
+    fn visit<'a>(
+        tag: &'a str,
+        edges: &BTreeMap<&'a str, BTreeSet<&'a str>>,
+        path: &mut Vec<&'a str>,
+        done: &mut BTreeSet<&'a str>,
+    ) -> Option<Vec<String>> {
+        if let Some(pos) = path.iter().position(|x| *x == tag) {
+            let mut cycle: Vec<String> = path[pos..].iter().map(|x| x.to_string()).collect();
+            cycle.push(tag.to_string());
+            return Some(cycle);
+        }
+        if done.contains(tag) {
+            return None;
+        }
+
+        path.push(tag);
+        for next in edges.get(tag).into_iter().flatten() {
+            if let Some(cycle) = Self::visit(next, edges, path, done) {
+                return Some(cycle);
+            }
+        }
+        path.pop();
+        done.insert(tag);
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RuleError, RuleSet};
+    use crate::{fromstr::FromStr, TagSet};
+
+    #[test]
+    fn should_expand_implications_transitively() {
+        let mut rules = RuleSet::new();
+        rules.add_implication("jpg", "image").unwrap();
+        rules.add_implication("image", "media").unwrap();
+
+        assert_eq!(
+            TagSet::from_str("/jpg/image/media/holiday").unwrap(),
+            rules.expand(&TagSet::from_str("/jpg/holiday").unwrap())
+        );
+    }
+
+    #[test]
+    fn should_replace_aliases() {
+        let mut rules = RuleSet::new();
+        rules.add_alias("pic", "photo").unwrap();
+        rules.add_implication("photo", "image").unwrap();
+
+        assert_eq!(
+            TagSet::from_str("/photo").unwrap(),
+            rules.normalize(&TagSet::from_str("/pic").unwrap())
+        );
+        assert_eq!(
+            TagSet::from_str("/photo/image").unwrap(),
+            rules.expand(&TagSet::from_str("/pic").unwrap())
+        );
+    }
+
+    #[test]
+    fn should_reject_implication_cycle() {
+        let mut rules = RuleSet::new();
+        rules.add_implication("a", "b").unwrap();
+        rules.add_implication("b", "c").unwrap();
+
+        assert_eq!(
+            Err(RuleError::Cycle(vec![
+                "a".to_string(),
+                "b".to_string(),
+                "c".to_string(),
+                "a".to_string()
+            ])),
+            rules.add_implication("c", "a")
+        );
+
+        // The rejected rule must not have been kept.
+        assert_eq!(
+            TagSet::from_str("/c").unwrap(),
+            rules.expand(&TagSet::from_str("/c").unwrap())
+        );
+    }
+
+    #[test]
+    fn should_reject_alias_cycle_through_implication() {
+        let mut rules = RuleSet::new();
+        rules.add_implication("photo", "pic").unwrap();
+
+        assert!(rules.add_alias("pic", "photo").is_err());
+        assert!(rules.add_alias("pic", "picture").is_ok());
+    }
+}
@@ -0,0 +1,160 @@
+use crate::query::Query;
+use crate::rules::RuleSet;
+use crate::fdb_trait::{AddFileError, GetFileError};
+use crate::{File, FileDB, FileQuery, QueryStats, TagSet};
+
+// Why set_rules left the rules and files as they were.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum SetRulesError {
+    // The wrapped database wouldn't remove one of its own files, so it
+    // couldn't be rebuilt.
+    Clear(File, GetFileError),
+    // Original files that can't be added under the new rules.
+    Rejected(Vec<File>),
+}
+
+// Wraps another database, applying a rule set to the tags of every file
+// added, and normalizing the tags of every query. The files as originally
+// added are kept, so that the rules can be changed and re-applied later.
+#[derive(Clone)]
+pub struct RulesDBFS<DB: FileDB> {
+    db: DB,
+    rules: RuleSet,
+    originals: Vec<File>,
+}
+
+impl<DB: FileDB> RulesDBFS<DB> {
+    pub fn new(db: DB, rules: RuleSet) -> Self {
+        RulesDBFS {
+            db,
+            rules,
+            originals: vec![],
+        }
+    }
+
+    pub fn rules(&self) -> &RuleSet {
+        &self.rules
+    }
+
+    pub fn inner(&self) -> &DB {
+        &self.db
+    }
+
+    // Replace the rule set, and rebuild the wrapped database by re-adding
+    // every original file under the new rules. If any file could no longer
+    // be added, e.g. because two files now normalize to the same tags,
+    // nothing changes and those files are returned in the error.
+    //
+    // The rebuild is done on a copy of the wrapped database, emptied rather
+    // than made afresh so it keeps its own configuration, such as a tag
+    // hierarchy. It only replaces the original once it has succeeded.
+    pub fn set_rules(&mut self, rules: RuleSet) -> Result<(), SetRulesError>
+    where
+        DB: Clone,
+    {
+        let (staged, rejected) = self.rebuild(rules)?;
+        if !rejected.is_empty() {
+            return Err(SetRulesError::Rejected(rejected));
+        }
+        *self = staged;
+        Ok(())
+    }
+
+    // As set_rules, but drop the files that can't be added under the new
+    // rules, returning them.
+    pub fn set_rules_dropping_rejected(
+        &mut self,
+        rules: RuleSet,
+    ) -> Result<Vec<File>, SetRulesError>
+    where
+        DB: Clone,
+    {
+        let (staged, rejected) = self.rebuild(rules)?;
+        *self = staged;
+        Ok(rejected)
+    }
+
+    // A copy of the DB with the new rules applied to every original file,
+    // and the originals that couldn't be added.
+    fn rebuild(&self, rules: RuleSet) -> Result<(Self, Vec<File>), SetRulesError>
+    where
+        DB: Clone,
+    {
+        let mut staged = RulesDBFS::new(self.db.clone(), rules);
+        staged.clear_inner()?;
+
+        let mut rejected = vec![];
+        for f in &self.originals {
+            if staged.add_file(f).is_err() {
+                rejected.push(f.clone());
+            }
+        }
+        Ok((staged, rejected))
+    }
+
+    // Remove every file from the wrapped database. Each file is removed by
+    // its own tags, largest tag sets first, so that a file with the same
+    // name and a superset of the tags is already gone and can't make the
+    // query ambiguous.
+    fn clear_inner(&mut self) -> Result<(), SetRulesError> {
+        let mut stored: Vec<File> = self.db.get_files(&TagSet::new()).collect();
+        stored.sort_by_key(|f| std::cmp::Reverse(f.tags.len()));
+        for f in stored {
+            if let Err(error) = self.db.remove_file(&f) {
+                return Err(SetRulesError::Clear(f, error));
+            }
+        }
+        Ok(())
+    }
+
+    fn normalize_query<F: FileQuery>(&self, query: &F) -> Query {
+        Query::with_tags_of(query, self.rules.normalize(query.tags()))
+    }
+}
+
+impl<DB: FileDB> FileDB for RulesDBFS<DB> {
+    type FileIterator = DB::FileIterator;
+
+    fn add_file(&mut self, new_file: &File) -> Result<(), AddFileError> {
+        let expanded = File::new(new_file.name.clone(), self.rules.expand(&new_file.tags));
+        self.db.add_file(&expanded)?;
+        self.originals.push(new_file.clone());
+        Ok(())
+    }
+
+    fn remove_file<F: FileQuery>(&mut self, query: &F) -> Result<File, GetFileError> {
+        let removed = self.db.remove_file(&self.normalize_query(query))?;
+
+        // The wrapped DB may have added tags of its own, so forget the most
+        // specific original that could have become the removed file.
+        let original = self
+            .originals
+            .iter()
+            .enumerate()
+            .filter(|(_, f)| f.name == removed.name)
+            .map(|(i, f)| (i, self.rules.expand(&f.tags)))
+            .filter(|(_, tags)| removed.tags.is_superset(tags))
+            .max_by_key(|(_, tags)| tags.len())
+            .map(|(i, _)| i);
+        if let Some(i) = original {
+            self.originals.remove(i);
+        }
+        Ok(removed)
+    }
+
+    fn get_files<F: FileQuery>(&self, query: &F) -> Self::FileIterator {
+        self.db.get_files(&self.normalize_query(query))
+    }
+
+    fn explain<F: FileQuery>(&self, query: &F) -> QueryStats {
+        self.db.explain(&self.normalize_query(query))
+    }
+
+    fn get_file<F: FileQuery>(&self, query: &F) -> Result<File, GetFileError> {
+        self.db.get_file(&self.normalize_query(query))
+    }
+
+    fn find_by_name(&self, name: &str) -> Vec<File> {
+        self.db.find_by_name(name)
+    }
+}
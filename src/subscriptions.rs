@@ -0,0 +1,163 @@
+use crate::batch::{Batch, BatchError, BatchOp};
+use crate::fdb_trait::{AddFileError, GetFileError, RetagFileError};
+use crate::{File, FileDB, FileQuery, QueryStats, TagSet};
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+// A change to the result set of a subscribed query.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum FileEvent {
+    // The file now matches the query.
+    Added(File),
+    // The file no longer matches the query.
+    Removed(File),
+    // The file matched before and after its tags were changed.
+    Retagged { old: File, new: File },
+}
+
+struct Subscriber {
+    query: Box<dyn FileQuery>,
+    sender: Sender<FileEvent>,
+}
+
+// Wraps another database, telling subscribers whenever a file enters or
+// leaves the result set of their query. Events are sent after the change
+// has been made, and a subscriber is dropped once its receiver is.
+pub struct SubscribedDBFS<DB: FileDB> {
+    db: DB,
+    subscribers: Vec<Subscriber>,
+}
+
+impl<DB: FileDB> SubscribedDBFS<DB> {
+    pub fn new(db: DB) -> Self {
+        SubscribedDBFS {
+            db,
+            subscribers: vec![],
+        }
+    }
+
+    pub fn inner(&self) -> &DB {
+        &self.db
+    }
+
+    pub fn into_inner(self) -> DB {
+        self.db
+    }
+
+    // Start receiving events for the query. Files that already match are
+    // not reported; use get_files for those.
+    pub fn subscribe<F: FileQuery + 'static>(&mut self, query: F) -> Receiver<FileEvent> {
+        let (sender, receiver) = channel();
+        self.subscribers.push(Subscriber {
+            query: Box::new(query),
+            sender,
+        });
+        receiver
+    }
+
+    // The number of subscribers still listening, as of the last event sent.
+    pub fn subscriber_count(&self) -> usize {
+        self.subscribers.len()
+    }
+
+    // Send an event to every subscriber for which the file went from
+    // matching `old` to matching `new`, or either way round.
+    fn notify(&mut self, old: Option<&File>, new: Option<&File>) {
+        self.subscribers.retain(|s| {
+            let before = old.filter(|f| s.query.could_match(f));
+            let after = new.filter(|f| s.query.could_match(f));
+            let event = match (before, after) {
+                (Some(old), Some(new)) => FileEvent::Retagged {
+                    old: old.clone(),
+                    new: new.clone(),
+                },
+                (Some(old), None) => FileEvent::Removed(old.clone()),
+                (None, Some(new)) => FileEvent::Added(new.clone()),
+                (None, None) => return true,
+            };
+            // Sending only fails once the receiver is gone.
+            s.sender.send(event).is_ok()
+        });
+    }
+
+    // The file as the wrapped DB stored it, which may have more tags than
+    // it was added with.
+    fn stored(&self, file: &File) -> File {
+        self.db.get_file(file).unwrap_or_else(|_| file.clone())
+    }
+
+    // Apply the batch to the wrapped DB, then report every file added or
+    // removed, in the order of the batch.
+    pub fn apply_batch(&mut self, batch: &Batch) -> Result<Vec<File>, BatchError>
+    where
+        DB: Clone,
+    {
+        let removed = self.db.apply_batch(batch)?;
+        let mut removals = removed.iter();
+        for op in batch.ops() {
+            match op {
+                BatchOp::Add(file) => {
+                    let added = self.stored(file);
+                    self.notify(None, Some(&added));
+                }
+                BatchOp::Remove(_) => {
+                    // There is one removed file for each Remove op.
+                    self.notify(removals.next(), None);
+                }
+            }
+        }
+        Ok(removed)
+    }
+}
+
+impl<DB: FileDB> FileDB for SubscribedDBFS<DB> {
+    type FileIterator = DB::FileIterator;
+
+    fn add_file(&mut self, new_file: &File) -> Result<(), AddFileError> {
+        self.db.add_file(new_file)?;
+        let added = self.stored(new_file);
+        self.notify(None, Some(&added));
+        Ok(())
+    }
+
+    fn remove_file<F: FileQuery>(&mut self, query: &F) -> Result<File, GetFileError> {
+        let removed = self.db.remove_file(query)?;
+        self.notify(Some(&removed), None);
+        Ok(removed)
+    }
+
+    fn retag_file<F: FileQuery>(
+        &mut self,
+        query: &F,
+        tags: TagSet,
+    ) -> Result<File, RetagFileError> {
+        let old = match self.db.retag_file(query, tags.clone()) {
+            Ok(old) => old,
+            Err(error) => {
+                // A file lost on the way has still left the DB.
+                if let RetagFileError::Lost { file, .. } = &error {
+                    self.notify(Some(file), None);
+                }
+                return Err(error);
+            }
+        };
+        let new = self.stored(&File::new(old.name.clone(), tags));
+        self.notify(Some(&old), Some(&new));
+        Ok(old)
+    }
+
+    fn get_files<F: FileQuery>(&self, query: &F) -> Self::FileIterator {
+        self.db.get_files(query)
+    }
+
+    fn explain<F: FileQuery>(&self, query: &F) -> QueryStats {
+        self.db.explain(query)
+    }
+
+    fn get_file<F: FileQuery>(&self, query: &F) -> Result<File, GetFileError> {
+        self.db.get_file(query)
+    }
+
+    fn find_by_name(&self, name: &str) -> Vec<File> {
+        self.db.find_by_name(name)
+    }
+}
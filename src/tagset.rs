@@ -1,19 +1,36 @@
-use crate::fromstr::FromStr;
+use crate::fromstr::{components, write_component, FromStr, ParseError};
 use crate::File;
 use crate::FileQuery;
 use std::collections::btree_set::BTreeSet;
+use std::fmt;
 
 pub type TagSet = BTreeSet<String>;
 
 impl FromStr for TagSet {
-    fn from_str(path: &str) -> Option<TagSet> {
-        let split = path.rsplit("/");
-        // Filter out empty strings to remove "" before the first '/'
-        Some(split.filter(|x| x != &"").map(|x| x.to_string()).collect())
+    fn from_str(path: &str) -> Result<TagSet, ParseError> {
+        let (found, _) = components(path)?;
+        Ok(found.into_iter().map(|c| c.text).collect())
     }
 }
 
-impl<'a> FileQuery for TagSet {
+// A tag set shown as a path, e.g. "/2021/photos", or "/" if it's empty.
+// TagSet::from_str reads it back.
+pub struct TagPath<'a>(pub &'a TagSet);
+
+impl fmt::Display for TagPath<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0.is_empty() {
+            return f.write_str("/");
+        }
+        for tag in self.0 {
+            f.write_str("/")?;
+            write_component(f, tag)?;
+        }
+        Ok(())
+    }
+}
+
+impl FileQuery for TagSet {
     fn could_match(&self, to_match: &File) -> bool {
         to_match.has_tags(self)
     }
@@ -1,16 +1,24 @@
 use super::endnode::EndNode;
 use super::multiendnodeiterator::MultiNodeIterator;
 use super::tagmaskbits::TagMaskBits;
-use super::Node;
-use crate::{fdb_trait::GetFileError, File, FileDB, FileQuery, TagSet};
-use std::collections::hash_map::HashMap;
-
-type TagMasks = HashMap<String, TagMaskBits>;
-
+use super::{NeedsSplit, Node};
+use crate::fdb_trait::{AddFileError, Candidates, GetFileError};
+use crate::memory::{HeapSize, ARC_HEADER};
+use crate::{File, FileDB, FileQuery, KeyPredicate, QueryStats, TagSet};
+use std::collections::btree_map::BTreeMap;
+use std::mem::size_of;
+use std::ops::Bound;
+use std::sync::Arc;
+
+// Sorted, so that all the structured tags for one key can be found as a range.
+type TagMasks = BTreeMap<String, TagMaskBits>;
+
+// Children are shared with any snapshots of the tree, and are copied on the
+// first write after a snapshot, so only the path to a change is duplicated.
 #[derive(Debug, Clone)]
 pub struct BranchNode {
     masks: TagMasks,
-    nodes: [Box<Node>; TagMaskBits::BITS],
+    nodes: [Arc<Node>; TagMaskBits::BITS],
     empty: TagMaskBits,
 }
 
@@ -19,8 +27,8 @@ impl BranchNode {
         Self {
             masks: TagMasks::new(),
             nodes: (0..TagMaskBits::BITS)
-                .map(|_| Box::new(Node::Empty))
-                .collect::<Vec<Box<Node>>>()
+                .map(|_| Arc::new(Node::Empty))
+                .collect::<Vec<Arc<Node>>>()
                 .try_into()
                 .unwrap(),
             empty: TagMaskBits::ALL,
@@ -53,6 +61,32 @@ impl BranchNode {
         result
     }
 
+    // Children that have at least one structured tag satisfying the predicate.
+    fn get_predicate_union(&self, predicate: &KeyPredicate) -> TagMaskBits {
+        let prefix = predicate.tag_prefix();
+        let mut result = TagMaskBits::CLEAR;
+        for (t, mask) in self
+            .masks
+            .range::<str, _>((Bound::Included(prefix.as_str()), Bound::Unbounded))
+            .take_while(|(t, _)| t.starts_with(prefix.as_str()))
+        {
+            if predicate.matches_tag(t) {
+                result.set_union(mask);
+            }
+        }
+        result
+    }
+
+    // Children that could hold a match for the query; they must have every tag, and
+    // something satisfying every predicate.
+    fn get_query_mask<F: FileQuery>(&self, query: &F) -> TagMaskBits {
+        let mut result = self.get_intersect(query.tags().iter());
+        for p in query.predicates() {
+            result.set_intersect(&self.get_predicate_union(p));
+        }
+        result
+    }
+
     fn set_index_for_tags<'a, I: Iterator<Item = &'a String>>(&mut self, idx: usize, tags: I) {
         for t in tags {
             let current = self
@@ -63,19 +97,25 @@ impl BranchNode {
         }
     }
 
-    fn add_file_to_child(&mut self, idx: usize, file: &File, do_end_replace: bool) -> Option<()> {
-        match self.nodes[idx].as_mut() {
+    fn add_file_to_child(
+        &mut self,
+        idx: usize,
+        file: &File,
+        do_end_replace: bool,
+    ) -> Result<(), NeedsSplit> {
+        // Look before writing, so that children shared with a snapshot are only
+        // copied when they are about to change.
+        match self.nodes[idx].as_ref() {
             // Matches are in order; Empty -> End -> Branch as more things are added.
             Node::Empty => {
                 // Mark bit as no longer empty
                 self.replace_node(idx, Some(file.tags()));
-                let res = self.nodes[idx].add_file(file);
-                return res;
+                Arc::make_mut(&mut self.nodes[idx]).insert_file(file)
             }
             Node::End(node) => {
                 // Only exact matches can be added
-                if let Some(res) = node.add_file(file) {
-                    return Some(res);
+                if node.accepts(file) {
+                    Arc::make_mut(&mut self.nodes[idx]).insert_file(file)
                 } else {
                     // We only need to make a new branch if there isn't already a better option in
                     // self. Rely on the caller telling is if it's better to turn into a branch or
@@ -89,7 +129,7 @@ impl BranchNode {
                         self.replace_node(idx, None);
                         // Adding to node that replaced self, this appears to cause recurssion to
                         // be infinite.
-                        match self.nodes[idx].as_mut() {
+                        match Arc::make_mut(&mut self.nodes[idx]) {
                             Node::Branch(new_node) => {
                                 // Add a node with the exact definition we expect, so that
                                 // recursion ends when we add after this match.
@@ -101,23 +141,23 @@ impl BranchNode {
                                 panic!("Created a branch node, but then immediate found it to be something else.");
                             }
                         }
-                        return self.nodes[idx].add_file(file);
+                        Arc::make_mut(&mut self.nodes[idx]).insert_file(file)
                         // We know exactly what we did here, so we should just forcibly add a new
                         // "Node" where we want it.
                     } else {
                         // Fail to add to this one if we have other options.
-                        return None;
+                        Err(NeedsSplit)
                     }
                 }
             }
-            Node::Branch(node) => {
+            Node::Branch(_) => {
                 // If we fail for a branch we must have run out of space. Splitting the node will
                 // give us more space.
-                if let Some(success) = node.add_file(file) {
-                    return Some(success);
+                if let Ok(success) = Arc::make_mut(&mut self.nodes[idx]).insert_file(file) {
+                    Ok(success)
                 } else {
                     self.replace_node(idx, None);
-                    return None;
+                    Err(NeedsSplit)
                 }
             }
         }
@@ -127,10 +167,12 @@ impl BranchNode {
         let mut replacement = BranchNode::new();
 
         for i in &mut target_nodes.clone() {
-            // TODO: We shouldn't actually clone here, we should have two boxes pointing
-            // at the same thing, then remove the old Box when we are ready. This will be
-            // 'unsafe', so will need some testing.
-            replacement.nodes[i] = Box::new(*(self.nodes[i]).clone());
+            // The children are shared, not copied; the old node keeps them
+            // for anyone still reading it.
+            replacement.nodes[i] = Arc::clone(&self.nodes[i]);
+            if !self.empty.is_set(i) {
+                replacement.empty.unset_bit(i);
+            }
         }
 
         for (k, v) in &self.masks {
@@ -161,8 +203,10 @@ impl BranchNode {
         replacement.set_index_for_tags(0, lower.masks.keys());
         replacement.set_index_for_tags(1, upper.masks.keys());
 
-        replacement.nodes[0] = Box::new(Node::Branch(lower));
-        replacement.nodes[1] = Box::new(Node::Branch(upper));
+        replacement.nodes[0] = Arc::new(Node::Branch(lower));
+        replacement.nodes[1] = Arc::new(Node::Branch(upper));
+        replacement.empty.unset_bit(0);
+        replacement.empty.unset_bit(1);
 
         replacement
     }
@@ -170,7 +214,8 @@ impl BranchNode {
     pub(crate) fn make_branch_from_end(end: &EndNode) -> Self {
         let mut replacement = BranchNode::new();
         // TODO: it would be good to note clone here...
-        replacement.nodes[0] = Box::new(Node::End(end.clone()));
+        replacement.nodes[0] = Arc::new(Node::End(end.clone()));
+        replacement.empty.unset_bit(0);
         let tags = end.all_tags();
         for t in tags {
             replacement.masks.insert(t, TagMaskBits::FIRST);
@@ -187,7 +232,7 @@ impl BranchNode {
         match node {
             // In order of progression as new files are added, Empty -> End -> Branch -> Branch
             Node::Empty => Node::End(BranchNode::turn_empty_into_end(tags.unwrap())),
-            Node::End(node) => Node::Branch(BranchNode::make_branch_from_end(&node)),
+            Node::End(node) => Node::Branch(BranchNode::make_branch_from_end(node)),
             Node::Branch(node) => Node::Branch(node.make_half_split_node()),
         }
     }
@@ -196,10 +241,10 @@ impl BranchNode {
     // call (currently there is no checking to make sure this is safe even afterwards). There may
     // still be searchers access the data through the 'stale' pointers.
     fn replace_node(&mut self, to_replace: usize, tags: Option<&TagSet>) {
-        let replacement = BranchNode::make_replacement_node(&*(self.nodes[to_replace]), tags);
+        let replacement = BranchNode::make_replacement_node(&self.nodes[to_replace], tags);
 
         // This should be made atomic.
-        self.nodes[to_replace] = Box::new(replacement);
+        self.nodes[to_replace] = Arc::new(replacement);
 
         // Unsetting empty doesn't need to be atomic with above, as we know that there are
         // no searchers currently inside it; it's empty. This does nothing for other node
@@ -213,10 +258,119 @@ impl BranchNode {
     }
 }
 
-impl FileDB for BranchNode {
-    type FileIterator = MultiNodeIterator;
+impl BranchNode {
+    // Returns false if the file isn't in this subtree.
+    pub(crate) fn delete_file(&mut self, file: &File) -> bool {
+        // Only children with every tag of the file can hold it.
+        let mut mask = self.get_intersect(file.tags.iter());
+        for idx in &mut mask {
+            // Look before writing, so only the path to the file is copied.
+            if self.nodes[idx].contains_file(file) {
+                Arc::make_mut(&mut self.nodes[idx]).delete_file(file);
+                self.trim_child(idx);
+                return true;
+            }
+        }
+        false
+    }
+
+    pub(crate) fn contains_file(&self, file: &File) -> bool {
+        let mut mask = self.get_intersect(file.tags.iter());
+        (&mut mask).any(|idx| self.nodes[idx].contains_file(file))
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.empty == TagMaskBits::ALL
+    }
+
+    pub(crate) fn all_tags(&self) -> TagSet {
+        self.masks.keys().cloned().collect()
+    }
+
+    // For the invariant checker, which needs to see inside.
+    pub(crate) fn children(&self) -> impl Iterator<Item = &Node> {
+        self.nodes.iter().map(|n| n.as_ref())
+    }
+
+    pub(crate) fn fanout(&self) -> usize {
+        TagMaskBits::ALL.without(&self.empty).count()
+    }
+
+    pub(crate) fn tag_count(&self) -> usize {
+        self.masks.len()
+    }
+
+    // The masks, and an allocation for every child, even Empty ones; not
+    // what the children hold. The buffer of pointers to the children is an
+    // array inside this node, so it is part of the Node allocation that
+    // the parent, or TreeStats for the root, counts for us.
+    pub(crate) fn own_heap_bytes(&self) -> usize {
+        const _: () = assert!(size_of::<Node>() > size_of::<[Arc<Node>; TagMaskBits::BITS]>());
+        self.masks.heap_bytes() + self.nodes.len() * (size_of::<Node>() + ARC_HEADER)
+    }
+
+    pub(crate) fn is_marked_empty(&self, idx: usize) -> bool {
+        self.empty.is_set(idx)
+    }
+
+    // The tags whose masks have the child's bit set.
+    pub(crate) fn mask_tags(&self, idx: usize) -> impl Iterator<Item = &String> {
+        self.masks
+            .iter()
+            .filter(move |(_, mask)| mask.is_set(idx))
+            .map(|(t, _)| t)
+    }
+
+    pub(crate) fn has_tag_bit(&self, tag: &str, idx: usize) -> bool {
+        self.masks.get(tag).is_some_and(|mask| mask.is_set(idx))
+    }
 
-    fn add_file(&mut self, file: &File) -> Option<()> {
+    pub(crate) fn empty_masks(&self) -> impl Iterator<Item = &String> {
+        self.masks
+            .iter()
+            .filter(|(_, mask)| **mask == TagMaskBits::CLEAR)
+            .map(|(t, _)| t)
+    }
+
+    pub(crate) fn explain<F: FileQuery>(&self, query: &F, stats: &mut QueryStats) {
+        stats.branch_nodes_visited += 1;
+        let live = TagMaskBits::ALL.without(&self.empty);
+        let mut mask = self.get_query_mask(query);
+        stats.children_pruned += live.without(&mask).count();
+
+        for t in query.tags() {
+            let tag_mask = self.masks.get(t).copied().unwrap_or(TagMaskBits::CLEAR);
+            *stats.pruned_by.entry(t.to_string()).or_default() += live.without(&tag_mask).count();
+        }
+        for p in query.predicates() {
+            let predicate_mask = self.get_predicate_union(p);
+            *stats.pruned_by.entry(p.key().to_string()).or_default() +=
+                live.without(&predicate_mask).count();
+        }
+
+        for idx in &mut mask {
+            self.nodes[idx].explain(query, stats);
+        }
+    }
+
+    // After removing from a child, stop claiming tags it no longer has, and
+    // free it if it has nothing left, so later searches skip it.
+    fn trim_child(&mut self, idx: usize) {
+        if self.nodes[idx].is_empty() {
+            self.nodes[idx] = Arc::new(Node::Empty);
+            self.empty.set_bit(idx);
+        }
+
+        let remaining = self.nodes[idx].all_tags();
+        self.masks.retain(|t, mask| {
+            if !remaining.contains(t) {
+                mask.unset_bit(idx);
+            }
+            *mask != TagMaskBits::CLEAR
+        });
+    }
+
+    pub(crate) fn insert_file(&mut self, file: &File) -> Result<(), NeedsSplit> {
         // Best match is all tags match entry
         let mut all_match = self.get_intersect(file.tags.iter());
         let last = all_match.last_idx();
@@ -227,8 +381,8 @@ impl FileDB for BranchNode {
 
             // It's safe to unwrap last because we *must* have a last if we're in the loop
             // as all.
-            if let Some(res) = self.add_file_to_child(idx, file, idx==last.unwrap()) {
-                return Some(res);
+            if let Ok(res) = self.add_file_to_child(idx, file, idx==last.unwrap()) {
+                return Ok(res);
             }
         }
 
@@ -240,11 +394,11 @@ impl FileDB for BranchNode {
             // Check that the add was successful before annotating all the tags.
             // This is more correct, and should work better for thread safety;
             // make sure all targets exist before they can be searched for.
-            if let Some(res) = self.add_file_to_child(idx, file, idx==last.unwrap()) {
+            if let Ok(res) = self.add_file_to_child(idx, file, idx==last.unwrap()) {
                 self.set_index_for_tags(idx, file.tags().iter());
-                return Some(res);
+                return Ok(res);
             }
-            if first_match == None {
+            if first_match.is_none() {
                 first_match = Some(idx);
             }
         }
@@ -252,64 +406,128 @@ impl FileDB for BranchNode {
         // If we just didn't have space, make space in the first partial match.
         // Assume the first is the best match.
         if let Some(idx) = first_match {
-            return self.add_file_to_child(idx, file, true);
+            let res = self.add_file_to_child(idx, file, true);
+            // The child now has tags it didn't before, so mark them, as for
+            // any other partial match.
+            if res.is_ok() {
+                self.set_index_for_tags(idx, file.tags().iter());
+            }
+            return res;
         }
 
-        let mut empty_items = self.empty.clone();
+        let mut empty_items = self.empty;
         let last = empty_items.last_idx();
         for idx in &mut empty_items {
             // Check that the add was successful before annotating all the tags.
             // This is more correct, and should work better for thread safety;
             // make sure all targets exist before they can be searched for.
-            if let Some(res) = self.add_file_to_child(idx, file, idx==last.unwrap()) {
+            if let Ok(res) = self.add_file_to_child(idx, file, idx==last.unwrap()) {
                 self.set_index_for_tags(idx, file.tags().iter());
-                return Some(res);
+                return Ok(res);
             }
         }
 
         // If we get here, then our parent will split this node and try again.
-        None
+        Err(NeedsSplit)
+    }
+}
+
+impl FileDB for BranchNode {
+    type FileIterator = MultiNodeIterator;
+
+    fn add_file(&mut self, file: &File) -> Result<(), AddFileError> {
+        self.insert_file(file)
+            .map_err(|NeedsSplit| AddFileError::Capacity)
+    }
+
+    fn remove_file<F: FileQuery>(&mut self, query: &F) -> Result<File, GetFileError> {
+        let file = self.get_file(query)?;
+        self.delete_file(&file);
+        Ok(file)
     }
 
     fn get_files<F: FileQuery>(&self, query: &F) -> Self::FileIterator {
         // Use intersect, as the target MUST have every tag, not just a subset
-        let mut mask = self.get_intersect(query.tags().iter());
+        let mut mask = self.get_query_mask(query);
         MultiNodeIterator::new(mask.map(|x| self.nodes[x].get_files(query)))
     }
 
     fn get_file<F: FileQuery>(&self, query: &F) -> Result<File, GetFileError> {
         // Use intersect, as the target MUST have every tag, not just a subset
-        let mut mask = self.get_intersect(query.tags().iter());
+        let mut mask = self.get_query_mask(query);
 
-        let mut result: Result<File, GetFileError> = Err(GetFileError::NoSuchFile);
+        let mut candidates = Candidates::new();
 
         for node_idx in &mut mask {
-            match self.nodes[node_idx].get_file(query) {
-                // Too many files in one branch, so that's the result
-                Err(GetFileError::TooManyFiles) => {
-                    return Err(GetFileError::TooManyFiles);
-                }
-                // Ignore nothing found; we might find it elsewhere
-                Err(GetFileError::NoSuchFile) => {}
-                Ok(e) => {
-                    // We found a single result, fail if we did this more than once
-                    if result.is_ok() {
-                        return Err(GetFileError::TooManyFiles);
-                    } else {
-                        result = Ok(e);
-                    }
-                }
+            // Nothing found is ignored, we might find it elsewhere. Keep
+            // gathering matches from other children until we have as many as
+            // an ambiguity error will list.
+            if !candidates.push_result(self.nodes[node_idx].get_file(query)) {
+                break;
             }
         }
-        result
+        candidates.into_result()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::{BranchNode, FileDB};
+    use crate::tagtree::invariants::{Checker, Violation};
+    use crate::tagtree::Node;
     use crate::{fromstr::FromStr, File, TagSet};
+    use std::collections::btree_set::BTreeSet;
     use std::collections::hash_set::HashSet;
+    use std::sync::Arc;
+
+    #[test]
+    fn branchnode_copy_should_share_children_until_written() {
+        let mut db = BranchNode::new();
+        db.add_file(&File::from_str("/one/a.txt").unwrap()).unwrap();
+        db.add_file(&File::from_str("/two/b.txt").unwrap()).unwrap();
+
+        let copy = db.clone();
+        db.add_file(&File::from_str("/one/c.txt").unwrap()).unwrap();
+
+        let shared = (0..db.nodes.len())
+            .filter(|&i| Arc::ptr_eq(&db.nodes[i], &copy.nodes[i]))
+            .count();
+
+        // Only the child holding "/one" was copied.
+        assert_eq!(db.nodes.len() - 1, shared);
+        assert_eq!(1, copy.get_files(&TagSet::from_str("/one").unwrap()).count());
+        assert_eq!(2, db.get_files(&TagSet::from_str("/one").unwrap()).count());
+    }
+
+    #[test]
+    fn invariant_checker_should_find_stale_bits() {
+        let mut db = BranchNode::new();
+        let file = File::from_str("/one/a.txt").unwrap();
+        db.add_file(&file).unwrap();
+
+        // Break the node as a bad split might: claim a tag for a child that
+        // doesn't have it, and mark a full child as empty.
+        db.masks.get_mut("one").unwrap().set_bit(1);
+        db.empty.set_bit(0);
+
+        let mut checker = Checker::new();
+        checker.check_root(&Node::Branch(db));
+        let report = checker.finish(BTreeSet::new());
+
+        let violations: Vec<Violation> =
+            report.problems.into_iter().map(|p| p.violation).collect();
+        assert_eq!(
+            vec![
+                Violation::MarkedEmpty { child: 0 },
+                Violation::MaskClaimsTag {
+                    child: 1,
+                    tag: "one".to_string()
+                },
+                Violation::NotIndexed(file),
+            ],
+            violations
+        );
+    }
 
     #[test]
     fn branchnode_should_allow_differently_tagged_files() {
@@ -0,0 +1,202 @@
+use super::branchnode::BranchNode;
+use super::endnode::EndNode;
+use super::tagmaskbits::TagMaskBits;
+use super::Node;
+use std::io::{self, Write};
+
+// What to draw when writing a TagTreeDBFS as Graphviz DOT. By default the
+// whole tree is drawn.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct DotOptions {
+    max_depth: Option<usize>,
+    collapse_above: Option<usize>,
+    max_label_tags: usize,
+}
+
+impl Default for DotOptions {
+    fn default() -> Self {
+        DotOptions {
+            max_depth: None,
+            collapse_above: None,
+            max_label_tags: 8,
+        }
+    }
+}
+
+impl DotOptions {
+    pub fn new() -> Self {
+        DotOptions::default()
+    }
+
+    // Draw branches at this depth, the root being 0, as a summary of
+    // what is under them rather than their children.
+    pub fn with_max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    // Draw any branch below the root holding more than this many files as
+    // a summary.
+    pub fn collapse_above(mut self, files: usize) -> Self {
+        self.collapse_above = Some(files);
+        self
+    }
+
+    // List at most this many tags in a label, then how many were left out.
+    pub fn with_max_label_tags(mut self, tags: usize) -> Self {
+        self.max_label_tags = tags;
+        self
+    }
+}
+
+// Branches, end nodes and files in a subtree.
+#[derive(Default)]
+struct Counts {
+    branches: usize,
+    end_nodes: usize,
+    files: usize,
+}
+
+impl Counts {
+    fn of(node: &Node) -> Counts {
+        let mut counts = Counts::default();
+        counts.add(node);
+        counts
+    }
+
+    fn add(&mut self, node: &Node) {
+        match node {
+            Node::Branch(node) => {
+                self.branches += 1;
+                node.children().for_each(|c| self.add(c));
+            }
+            Node::End(node) => {
+                self.end_nodes += 1;
+                self.files += node.len();
+            }
+            Node::Empty => (),
+        }
+    }
+}
+
+pub(crate) struct DotWriter<'a, W: Write> {
+    out: &'a mut W,
+    options: &'a DotOptions,
+    next_id: usize,
+}
+
+impl<'a, W: Write> DotWriter<'a, W> {
+    pub(crate) fn new(out: &'a mut W, options: &'a DotOptions) -> Self {
+        DotWriter {
+            out,
+            options,
+            next_id: 0,
+        }
+    }
+
+    pub(crate) fn write(mut self, root: &Node) -> io::Result<()> {
+        writeln!(self.out, "digraph tagtree {{")?;
+        writeln!(self.out, "    node [fontname=\"monospace\"];")?;
+        self.node(root, 0)?;
+        writeln!(self.out, "}}")
+    }
+
+    // Write the node and whatever is drawn under it, returning its id.
+    fn node(&mut self, node: &Node, depth: usize) -> io::Result<usize> {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        match node {
+            Node::Branch(branch) => {
+                // Counting walks the whole subtree, so only count when the
+                // branch might be drawn as a summary.
+                let at_depth = self.options.max_depth.is_some_and(|d| depth >= d);
+                let collapse_above = self.options.collapse_above.filter(|_| depth > 0);
+                let may_collapse = collapse_above.is_some();
+                let summary = (at_depth || may_collapse)
+                    .then(|| Counts::of(node))
+                    .filter(|counts| at_depth || collapse_above.is_some_and(|n| counts.files > n));
+                match summary {
+                    Some(counts) => self.summary(id, &counts)?,
+                    None => self.branch(id, branch, depth)?,
+                }
+            }
+            Node::End(end) => self.end(id, end)?,
+            Node::Empty => (),
+        }
+        Ok(id)
+    }
+
+    fn branch(&mut self, id: usize, node: &BranchNode, depth: usize) -> io::Result<()> {
+        let label = format!(
+            "branch\n{}/{} children\n{} tags",
+            node.fanout(),
+            TagMaskBits::BITS,
+            node.tag_count()
+        );
+        writeln!(
+            self.out,
+            "    n{} [shape=box, label=\"{}\"];",
+            id,
+            escape(&label)
+        )?;
+
+        for (idx, child) in node.children().enumerate() {
+            if let Node::Empty = child {
+                continue;
+            }
+            let child_id = self.node(child, depth + 1)?;
+            let label = format!("{}: {}", idx, self.tag_list(node.mask_tags(idx)));
+            writeln!(
+                self.out,
+                "    n{} -> n{} [label=\"{}\"];",
+                id,
+                child_id,
+                escape(&label)
+            )?;
+        }
+        Ok(())
+    }
+
+    fn end(&mut self, id: usize, node: &EndNode) -> io::Result<()> {
+        let tags = node.all_tags();
+        let label = format!("{}\n{} files", self.tag_list(tags.iter()), node.len());
+        writeln!(
+            self.out,
+            "    n{} [shape=ellipse, label=\"{}\"];",
+            id,
+            escape(&label)
+        )
+    }
+
+    fn summary(&mut self, id: usize, counts: &Counts) -> io::Result<()> {
+        let label = format!(
+            "{} branches\n{} end nodes\n{} files",
+            counts.branches, counts.end_nodes, counts.files
+        );
+        writeln!(
+            self.out,
+            "    n{} [shape=box, style=dashed, label=\"{}\"];",
+            id,
+            escape(&label)
+        )
+    }
+
+    fn tag_list<'t, I: Iterator<Item = &'t String>>(&self, tags: I) -> String {
+        let tags: Vec<&str> = tags.map(|t| t.as_str()).collect();
+        let max = self.options.max_label_tags;
+        if tags.len() > max {
+            format!("{}, +{} more", tags[..max].join(", "), tags.len() - max)
+        } else {
+            tags.join(", ")
+        }
+    }
+}
+
+// Quote a label for DOT, keeping line breaks.
+fn escape(label: &str) -> String {
+    label
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -1,6 +1,10 @@
 use super::endnodeiterator::EndNodeIterator;
-use crate::{fdb_trait::GetFileError, File, FileDB, FileQuery, TagSet};
+use super::NeedsSplit;
+use crate::fdb_trait::{AddFileError, Candidates, GetFileError};
+use crate::memory::HeapSize;
+use crate::{File, FileDB, FileQuery, QueryStats, TagSet};
 use std::collections::btree_set::BTreeSet;
+use std::ops::Bound;
 
 #[derive(Debug, Clone)]
 pub struct EndNode {
@@ -16,58 +20,128 @@ impl EndNode {
         }
     }
 
+    // Only files with exactly our tags can be added.
+    pub(crate) fn accepts(&self, file: &File) -> bool {
+        file.tags == self.tags
+    }
+
+    pub(crate) fn insert_file(&mut self, new_file: &File) -> Result<(), NeedsSplit> {
+        if self.accepts(new_file) {
+            self.file_names.insert(new_file.name.clone());
+            Ok(())
+        } else {
+            Err(NeedsSplit)
+        }
+    }
+
+    // Returns false if the file isn't here.
+    pub(crate) fn delete_file(&mut self, file: &File) -> bool {
+        self.accepts(file) && self.file_names.remove(&file.name)
+    }
+
+    pub(crate) fn contains_file(&self, file: &File) -> bool {
+        self.accepts(file) && self.file_names.contains(&file.name)
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.file_names.is_empty()
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.file_names.len()
+    }
+
+    pub(crate) fn own_heap_bytes(&self) -> usize {
+        self.file_names.heap_bytes() + self.tags.heap_bytes()
+    }
+
     pub(crate) fn all_tags(&self) -> TagSet {
         self.tags.clone()
     }
+
+    pub(crate) fn files(&self) -> impl Iterator<Item = File> + '_ {
+        self.file_names
+            .iter()
+            .map(|name| File::new(name.clone(), self.tags.clone()))
+    }
+
+    // Names in this node that satisfy the name part of the query.
+    fn matching_names<F: FileQuery>(&self, query: &F) -> Vec<&String> {
+        let mut names = self.candidate_names(query);
+        if let (None, Some(pattern)) = (query.name(), query.name_pattern()) {
+            names.retain(|x| pattern.matches(x));
+        }
+        names
+    }
+
+    // Names in this node that need checking against the name part of the
+    // query. A pattern only needs to look at the range of names sharing its
+    // literal prefix.
+    fn candidate_names<F: FileQuery>(&self, query: &F) -> Vec<&String> {
+        if let Some(name) = query.name() {
+            self.file_names.get(name).into_iter().collect()
+        } else if let Some(pattern) = query.name_pattern() {
+            let prefix = pattern.literal_prefix();
+            self.file_names
+                .range::<str, _>((Bound::Included(prefix.as_str()), Bound::Unbounded))
+                .take_while(|x| x.starts_with(prefix.as_str()))
+                .collect()
+        } else {
+            self.file_names.iter().collect()
+        }
+    }
+
+    // A node whose tags rule out the query isn't scanned at all, and only
+    // the names matching_names checks are counted as scanned.
+    pub(crate) fn explain<F: FileQuery>(&self, query: &F, stats: &mut QueryStats) {
+        if !self.tags_could_match(query) {
+            return;
+        }
+        stats.end_nodes_scanned += 1;
+        let scanned = self.candidate_names(query).len();
+        stats.scanned(scanned, self.matching_names(query).len());
+    }
+
+    fn tags_could_match<F: FileQuery>(&self, query: &F) -> bool {
+        self.tags.is_superset(query.tags())
+            && query.predicates().iter().all(|p| p.matches_any(&self.tags))
+    }
 }
 
 impl FileDB for EndNode {
     type FileIterator = EndNodeIterator;
 
-    fn add_file(&mut self, new_file: &File) -> Option<()> {
-        if new_file.tags == self.tags {
-            self.file_names.insert(new_file.name.clone());
-            Some(())
-        } else {
-            None
-        }
+    fn add_file(&mut self, new_file: &File) -> Result<(), AddFileError> {
+        self.insert_file(new_file)
+            .map_err(|NeedsSplit| AddFileError::Capacity)
+    }
+
+    fn remove_file<F: FileQuery>(&mut self, query: &F) -> Result<File, GetFileError> {
+        let file = self.get_file(query)?;
+        self.delete_file(&file);
+        Ok(file)
     }
 
     fn get_files<F: FileQuery>(&self, query: &F) -> Self::FileIterator {
-        if self.tags.is_superset(query.tags()) {
-            if let Some(file_name) = query.name() {
-                if self.file_names.contains(file_name) {
-                    EndNodeIterator::new([file_name.to_string()].iter(), &self.tags)
-                } else {
-                    EndNodeIterator::empty()
-                }
-            } else {
-                EndNodeIterator::new(self.file_names.iter(), &self.tags)
-            }
+        if self.tags_could_match(query) {
+            EndNodeIterator::new(self.matching_names(query).into_iter(), &self.tags)
         } else {
             EndNodeIterator::empty()
         }
     }
 
     fn get_file<F: FileQuery>(&self, query: &F) -> Result<File, GetFileError> {
-        if !self.tags.is_superset(query.tags()) {
+        if !self.tags_could_match(query) {
             return Err(GetFileError::NoSuchFile);
         }
 
-        if let Some(name) = query.name() {
-            if self.file_names.contains(name) {
-                return Ok(File::new(name.to_string(), self.tags.clone()));
-            } else {
-                return Err(GetFileError::NoSuchFile);
-            }
-        } else {
-            if self.file_names.len() == 1 {
-                let only_file = self.file_names.iter().next().unwrap();
-                return Ok(File::new(only_file.to_string(), self.tags.clone()));
-            } else {
-                return Err(GetFileError::TooManyFiles);
+        let mut candidates = Candidates::new();
+        for name in self.matching_names(query) {
+            if !candidates.push(File::new(name.to_string(), self.tags.clone())) {
+                break;
             }
         }
+        candidates.into_result()
     }
 }
 
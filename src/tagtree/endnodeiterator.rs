@@ -31,9 +31,9 @@ impl Iterator for EndNodeIterator {
     fn next(&mut self) -> Option<Self::Item> {
         if let Some(file_name) = self.file_names.get(self.current) {
             self.current += 1;
-            return Some(File::new(file_name.to_string(), self.tags.clone()));
+            Some(File::new(file_name.to_string(), self.tags.clone()))
         } else {
-            return None;
+            None
         }
     }
 }
@@ -61,7 +61,7 @@ mod tests {
     fn non_empty_should_yeild_files() {
         let tags = TagSet::from_str("/one/two").unwrap();
         let actual =
-            EndNodeIterator::new(vec!["blue".to_string(), "red".to_string()].iter(), &tags);
+            EndNodeIterator::new(["blue".to_string(), "red".to_string()].iter(), &tags);
         let expected = vec![
             File::new("blue".to_string(), tags.clone()),
             File::new("red".to_string(), tags.clone()),
@@ -0,0 +1,190 @@
+use super::branchnode::BranchNode;
+use super::endnode::EndNode;
+use super::Node;
+use crate::{File, TagSet};
+use std::collections::btree_set::BTreeSet;
+use std::fmt;
+
+// Something wrong with the shape of a tag tree. Any of these can make
+// searches miss files, or find files in the wrong place.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum Violation {
+    // A mask bit is set for a child that doesn't have the tag.
+    MaskClaimsTag { child: usize, tag: String },
+    // A child has a tag whose mask bit isn't set, so searches skip it.
+    MaskMissesTag { child: usize, tag: String },
+    // A tag is kept with no bits set.
+    EmptyMask { tag: String },
+    // The empty bit is set, but the child isn't Empty.
+    MarkedEmpty { child: usize },
+    // The child is Empty, but its empty bit isn't set.
+    NotMarkedEmpty { child: usize },
+    // An EndNode with no files, which should have been freed.
+    EmptyEndNode,
+    // A branch other than the root with nothing under it.
+    EmptyBranch,
+    // The same file is stored more than once.
+    DuplicateFile(File),
+    // The file is in the tree but not the name index.
+    NotIndexed(File),
+    // The file is in the name index but not the tree.
+    NotInTree(File),
+}
+
+// A problem, and the child indexes leading to the node it was found in.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Problem {
+    pub path: Vec<usize>,
+    pub violation: Violation,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let path: Vec<String> = self.path.iter().map(|i| i.to_string()).collect();
+        write!(f, "/{}: {:?}", path.join("/"), self.violation)
+    }
+}
+
+// What TagTreeDBFS::check_invariants found.
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct InvariantReport {
+    pub branch_nodes: usize,
+    pub end_nodes: usize,
+    pub files: usize,
+    pub problems: Vec<Problem>,
+}
+
+impl InvariantReport {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+impl fmt::Display for InvariantReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} branch nodes, {} end nodes, {} files, {} problems",
+            self.branch_nodes,
+            self.end_nodes,
+            self.files,
+            self.problems.len()
+        )?;
+        for p in &self.problems {
+            write!(f, "\n  {}", p)?;
+        }
+        Ok(())
+    }
+}
+
+// Walks a tree, gathering its files as it checks each node.
+pub(crate) struct Checker {
+    report: InvariantReport,
+    path: Vec<usize>,
+    seen: BTreeSet<File>,
+}
+
+impl Checker {
+    pub(crate) fn new() -> Self {
+        Checker {
+            report: InvariantReport::default(),
+            path: vec![],
+            seen: BTreeSet::new(),
+        }
+    }
+
+    fn problem(&mut self, violation: Violation) {
+        self.report.problems.push(Problem {
+            path: self.path.clone(),
+            violation,
+        });
+    }
+
+    pub(crate) fn check_root(&mut self, root: &Node) {
+        match root {
+            // The root may be left empty; any other branch may not.
+            Node::Branch(node) => self.check_branch(node),
+            node => self.check_node(node),
+        }
+    }
+
+    fn check_node(&mut self, node: &Node) {
+        match node {
+            Node::Branch(node) => {
+                if node.is_empty() {
+                    self.problem(Violation::EmptyBranch);
+                }
+                self.check_branch(node);
+            }
+            Node::End(node) => self.check_end(node),
+            Node::Empty => (),
+        }
+    }
+
+    fn check_branch(&mut self, node: &BranchNode) {
+        self.report.branch_nodes += 1;
+
+        for (idx, child) in node.children().enumerate() {
+            let is_empty = matches!(child, Node::Empty);
+            if node.is_marked_empty(idx) && !is_empty {
+                self.problem(Violation::MarkedEmpty { child: idx });
+            } else if !node.is_marked_empty(idx) && is_empty {
+                self.problem(Violation::NotMarkedEmpty { child: idx });
+            }
+
+            let child_tags: TagSet = child.all_tags();
+            for tag in node.mask_tags(idx) {
+                if !child_tags.contains(tag) {
+                    self.problem(Violation::MaskClaimsTag {
+                        child: idx,
+                        tag: tag.to_string(),
+                    });
+                }
+            }
+            for tag in &child_tags {
+                if !node.has_tag_bit(tag, idx) {
+                    self.problem(Violation::MaskMissesTag {
+                        child: idx,
+                        tag: tag.to_string(),
+                    });
+                }
+            }
+
+            self.path.push(idx);
+            self.check_node(child);
+            self.path.pop();
+        }
+
+        for tag in node.empty_masks() {
+            self.problem(Violation::EmptyMask {
+                tag: tag.to_string(),
+            });
+        }
+    }
+
+    fn check_end(&mut self, node: &EndNode) {
+        self.report.end_nodes += 1;
+        if node.is_empty() {
+            self.problem(Violation::EmptyEndNode);
+        }
+        for file in node.files() {
+            self.report.files += 1;
+            if !self.seen.insert(file.clone()) {
+                self.problem(Violation::DuplicateFile(file));
+            }
+        }
+    }
+
+    // Compare the files found in the tree with the name index.
+    pub(crate) fn finish(mut self, indexed: BTreeSet<File>) -> InvariantReport {
+        self.path.clear();
+        let seen = std::mem::take(&mut self.seen);
+        for f in seen.difference(&indexed) {
+            self.problem(Violation::NotIndexed(f.clone()));
+        }
+        for f in indexed.difference(&seen) {
+            self.problem(Violation::NotInTree(f.clone()));
+        }
+        self.report
+    }
+}
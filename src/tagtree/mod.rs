@@ -1,16 +1,40 @@
 pub(crate) mod branchnode;
+pub(crate) mod dot;
 pub(crate) mod endnode;
 pub(crate) mod endnodeiterator;
+pub(crate) mod invariants;
 pub(crate) mod multiendnodeiterator;
 pub(crate) mod nodeiterator;
+mod shortest;
+pub(crate) mod snapshot;
+pub(crate) mod stats;
 mod tagmaskbits;
 
-use crate::{fdb_trait::GetFileError, File, FileDB, FileQuery};
+use crate::nameindex::NameIndex;
+use crate::fdb_trait::{AddFileError, Candidates, GetFileError};
+use crate::batch::{Batch, BatchError, BatchOp};
+use crate::{File, FileDB, FileQuery, QueryStats, TagHierarchy, TagSet};
 use branchnode::BranchNode;
+use dot::{DotOptions, DotWriter};
 use endnode::EndNode;
+use invariants::{Checker, InvariantReport};
 use multiendnodeiterator::MultiNodeIterator;
 use nodeiterator::NodeIterator;
+use snapshot::TagTreeSnapshot;
+use stats::TreeStats;
+use std::collections::btree_map::BTreeMap;
+use std::collections::btree_set::BTreeSet;
+use std::io::{self, Write};
+use std::sync::Arc;
 
+// Returned when adding to a node that has no room for the file; the parent
+// must split the node and try again. This never leaves the tree.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct NeedsSplit;
+
+// Branches live behind an Arc in their parent, so the size difference
+// between variants only costs us at the root.
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Clone)]
 pub enum Node {
     Branch(BranchNode),
@@ -18,17 +42,71 @@ pub enum Node {
     Empty,
 }
 
-impl FileDB for Node {
-    type FileIterator = NodeIterator;
+impl Node {
+    pub(crate) fn insert_file(&mut self, new_file: &File) -> Result<(), NeedsSplit> {
+        match self {
+            Self::Branch(node) => node.insert_file(new_file),
+            Self::End(node) => node.insert_file(new_file),
+            Self::Empty => Err(NeedsSplit),
+        }
+    }
+
+    pub(crate) fn delete_file(&mut self, file: &File) -> bool {
+        match self {
+            Self::Branch(node) => node.delete_file(file),
+            Self::End(node) => node.delete_file(file),
+            Self::Empty => false,
+        }
+    }
 
-    fn add_file(&mut self, new_file: &File) -> Option<()> {
+    pub(crate) fn contains_file(&self, file: &File) -> bool {
         match self {
-            Self::Branch(node) => node.add_file(new_file),
-            Self::End(node) => node.add_file(new_file),
-            Self::Empty => None,
+            Self::Branch(node) => node.contains_file(file),
+            Self::End(node) => node.contains_file(file),
+            Self::Empty => false,
         }
     }
 
+    pub(crate) fn is_empty(&self) -> bool {
+        match self {
+            Self::Branch(node) => node.is_empty(),
+            Self::End(node) => node.is_empty(),
+            Self::Empty => true,
+        }
+    }
+
+    pub(crate) fn all_tags(&self) -> TagSet {
+        match self {
+            Self::Branch(node) => node.all_tags(),
+            Self::End(node) => node.all_tags(),
+            Self::Empty => TagSet::new(),
+        }
+    }
+
+    // Walk the tree as get_files does, counting what is visited.
+    pub(crate) fn explain<F: FileQuery>(&self, query: &F, stats: &mut QueryStats) {
+        match self {
+            Self::Branch(node) => node.explain(query, stats),
+            Self::End(node) => node.explain(query, stats),
+            Self::Empty => (),
+        }
+    }
+}
+
+impl FileDB for Node {
+    type FileIterator = NodeIterator;
+
+    fn add_file(&mut self, new_file: &File) -> Result<(), AddFileError> {
+        self.insert_file(new_file)
+            .map_err(|NeedsSplit| AddFileError::Capacity)
+    }
+
+    fn remove_file<F: FileQuery>(&mut self, query: &F) -> Result<File, GetFileError> {
+        let file = self.get_file(query)?;
+        self.delete_file(&file);
+        Ok(file)
+    }
+
     fn get_files<F: FileQuery>(&self, query: &F) -> Self::FileIterator {
         match self {
             Self::Branch(node) => NodeIterator::MultiNodeIter(node.get_files(query)),
@@ -46,45 +124,256 @@ impl FileDB for Node {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct TagTreeDBFS {
-    root: Node,
+    // Shared with any snapshots; see snapshot().
+    root: Arc<Node>,
+    hierarchy: TagHierarchy,
+    names: NameIndex,
+    // Counts the changes made, so snapshots can say how old they are.
+    version: u64,
+}
+
+impl Default for TagTreeDBFS {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl TagTreeDBFS {
     pub fn new() -> TagTreeDBFS {
+        TagTreeDBFS::with_hierarchy(TagHierarchy::new())
+    }
+
+    pub fn with_hierarchy(hierarchy: TagHierarchy) -> TagTreeDBFS {
         TagTreeDBFS {
-            root: Node::Branch(BranchNode::new()),
+            root: Arc::new(Node::Branch(BranchNode::new())),
+            hierarchy,
+            names: NameIndex::new(),
+            version: 0,
+        }
+    }
+
+    // The number of changes made to the DB so far.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    // Pin the current state of the DB, so it can be read while this one
+    // keeps changing. This shares the tree rather than copying it; the next
+    // change copies only the nodes it touches, and the old nodes are freed
+    // when the last snapshot holding them is dropped.
+    pub fn snapshot(&self) -> TagTreeSnapshot {
+        TagTreeSnapshot::new(Arc::clone(&self.root), self.version)
+    }
+
+    // Describe the shape of the tree and estimate its memory use. This
+    // walks every node.
+    pub fn stats(&self) -> TreeStats {
+        let mut stats = TreeStats::new(&self.root);
+        stats.heap_bytes += self.names.heap_bytes() + self.hierarchy.heap_bytes();
+        stats
+    }
+
+    // An estimate of the bytes allocated by the DB, comparable with the
+    // other backends' memory_usage.
+    pub fn memory_usage(&self) -> usize {
+        self.stats().heap_bytes
+    }
+
+    // Draw the tree in Graphviz DOT, e.g. for `dot -Tsvg`: a box for each
+    // branch, with an edge to each child labelled with its mask tags, and an
+    // ellipse for each end node with its tags and number of files.
+    pub fn write_dot<W: Write>(&self, out: &mut W, options: &DotOptions) -> io::Result<()> {
+        DotWriter::new(out, options).write(&self.root)
+    }
+
+    pub fn to_dot(&self, options: &DotOptions) -> String {
+        let mut out = vec![];
+        // Writing to a Vec can't fail.
+        self.write_dot(&mut out, options).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    // Walk the whole tree, checking that its masks and empty bits agree
+    // with what the children hold, that nothing is stored twice, and that
+    // the name index agrees with the tree. Meant for tests and debugging;
+    // it looks at every node.
+    pub fn check_invariants(&self) -> InvariantReport {
+        let mut checker = Checker::new();
+        checker.check_root(&self.root);
+        checker.finish(self.names.all_files())
+    }
+
+    // The stored file with only as many of its tags as it takes to pick it
+    // out by name, e.g. to show it as a short path. get_file with the result
+    // returns the whole file. Where several sets of tags are as small, which
+    // one is returned isn't specified. Fails as get_file would for the whole
+    // file if it isn't stored, or if another file of its name has all its
+    // tags, so that no set of them can pick it out.
+    pub fn shortest_path(&self, file: &File) -> Result<File, GetFileError> {
+        let file = self.hierarchy.expand_file(file);
+        if !self.names.contains(&file) {
+            return Err(GetFileError::NoSuchFile);
+        }
+        match shortest::shortest_tags(&self.root, &self.names, &file) {
+            Some(tags) => Ok(File::new(file.name.clone(), tags)),
+            None => match self.get_file(file.as_ref()) {
+                Err(error) => Err(error),
+                Ok(_) => unreachable!("a file no other file matches has a shortest path"),
+            },
+        }
+    }
+
+    // Add a file already known to be valid and not a duplicate.
+    fn insert_checked(&mut self, new_file: &File) -> Result<(), AddFileError> {
+        Self::insert_into(&mut self.root, new_file)?;
+        self.names.insert(new_file);
+        self.version += 1;
+        Ok(())
+    }
+
+    // Add a file to the tree under the given root, splitting the root if
+    // it is full.
+    fn insert_into(root: &mut Arc<Node>, new_file: &File) -> Result<(), AddFileError> {
+        // Splitting the root always makes room, so only try again once.
+        if Arc::make_mut(root).insert_file(new_file).is_err() {
+            Self::replace_node(root);
+            Arc::make_mut(root)
+                .insert_file(new_file)
+                .map_err(|NeedsSplit| AddFileError::Capacity)?;
         }
+        Ok(())
     }
 
-    fn replace_node(&mut self) {
+    fn replace_node(root: &mut Arc<Node>) {
         // It is safe to pass None here, because we know that the our node is
         // always a Branch (in fact, we should replace this just with split).
-        let replacement = BranchNode::make_replacement_node(&self.root, None);
+        let replacement = BranchNode::make_replacement_node(root, None);
 
         // This should be made atomic.
-        self.root = replacement;
+        *root = Arc::new(replacement);
+    }
+
+    // The single file a batch's Remove op picks out: one stored before the
+    // batch and not yet removed by it, or one it added.
+    fn get_staged_file<F: FileQuery>(
+        &self,
+        query: &F,
+        added: &BTreeMap<File, usize>,
+        removed: &BTreeSet<File>,
+    ) -> Result<File, GetFileError> {
+        let mut candidates = Candidates::new();
+        let stored = self.get_files(query).filter(|f| !removed.contains(f));
+        let new = added.keys().filter(|f| query.could_match(f)).cloned();
+        for file in stored.chain(new) {
+            if !candidates.push(file) {
+                break;
+            }
+        }
+        candidates.into_result()
     }
 }
 
 impl FileDB for TagTreeDBFS {
     type FileIterator = NodeIterator;
 
-    fn add_file(&mut self, new_file: &File) -> Option<()> {
-        if let Some(res) = self.root.add_file(new_file) {
-            return Some(res);
-        } else {
-            self.replace_node();
-            return self.root.add_file(new_file);
+    fn add_file(&mut self, new_file: &File) -> Result<(), AddFileError> {
+        AddFileError::check_tags(new_file)?;
+        // Store the file with all ancestor tags, so the branch masks can prune
+        // queries on any of them.
+        let expanded = self.hierarchy.expand_file(new_file);
+        let new_file = expanded.as_ref();
+        if self.names.contains(new_file) {
+            return Err(AddFileError::Duplicate);
         }
+        self.insert_checked(new_file)
+    }
+
+    // Works out what the whole batch adds and removes before changing
+    // anything, then builds the new tree on a copy of the root, which
+    // shares every node it doesn't change with the current one. The copy
+    // is swapped in only once every file has found room, so a failure part
+    // way through leaves the DB as it was. Files with the same tags share
+    // an EndNode, so they are placed together, and only the first of each
+    // tag set can cause a split.
+    fn apply_batch(&mut self, batch: &Batch) -> Result<Vec<File>, BatchError> {
+        // The files the batch adds, with the index of the op that adds
+        // each, and the stored files it removes.
+        let mut added = BTreeMap::new();
+        let mut removed = BTreeSet::new();
+        let mut removals = vec![];
+        for (index, op) in batch.ops().iter().enumerate() {
+            match op {
+                BatchOp::Add(file) => {
+                    AddFileError::check_tags(file).map_err(|error| BatchError::add(index, error))?;
+                    let file = self.hierarchy.expand_file(file).into_owned();
+                    let stored = self.names.contains(&file) && !removed.contains(&file);
+                    if stored || added.contains_key(&file) {
+                        return Err(BatchError::add(index, AddFileError::Duplicate));
+                    }
+                    added.insert(file, index);
+                }
+                BatchOp::Remove(query) => {
+                    let file = self
+                        .get_staged_file(query, &added, &removed)
+                        .map_err(|error| BatchError::remove(index, error))?;
+                    if added.remove(&file).is_none() {
+                        removed.insert(file.clone());
+                    }
+                    removals.push(file);
+                }
+            }
+        }
+
+        let mut root = self.root.clone();
+        for file in &removed {
+            Arc::make_mut(&mut root).delete_file(file);
+        }
+        let mut to_add: Vec<(&File, &usize)> = added.iter().collect();
+        to_add.sort_by(|(a, _), (b, _)| a.tags.cmp(&b.tags));
+        for (file, index) in to_add {
+            Self::insert_into(&mut root, file).map_err(|error| BatchError::add(*index, error))?;
+        }
+
+        self.root = root;
+        for file in &removed {
+            self.names.remove(file);
+        }
+        for file in added.keys() {
+            self.names.insert(file);
+        }
+        self.version += batch.len() as u64;
+        Ok(removals)
+    }
+
+    fn remove_file<F: FileQuery>(&mut self, query: &F) -> Result<File, GetFileError> {
+        let file = self.get_file(query)?;
+        // The root stays a branch, even when it is left empty.
+        Arc::make_mut(&mut self.root).delete_file(&file);
+        self.names.remove(&file);
+        self.version += 1;
+        Ok(file)
     }
 
     fn get_files<F: FileQuery>(&self, query: &F) -> Self::FileIterator {
         self.root.get_files(query)
     }
 
+    fn explain<F: FileQuery>(&self, query: &F) -> QueryStats {
+        let mut stats = QueryStats::default();
+        self.root.explain(query, &mut stats);
+        stats
+    }
+
     fn get_file<F: FileQuery>(&self, query: &F) -> Result<File, GetFileError> {
+        if let Some(result) = self.names.get_file(query) {
+            return result;
+        }
         self.root.get_file(query)
     }
+
+    fn find_by_name(&self, name: &str) -> Vec<File> {
+        self.names.find(name)
+    }
 }
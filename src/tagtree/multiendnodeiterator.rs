@@ -51,7 +51,7 @@ impl Iterator for MultiNodeIterator {
 
 impl ExactSizeIterator for MultiNodeIterator {
     fn len(&self) -> usize {
-        return self.backing.iter().map(|x| x.len()).sum();
+        self.backing.iter().map(|x| x.len()).sum()
     }
 }
 
@@ -74,7 +74,7 @@ mod tests {
     fn non_empty_should_yield_files() {
         let tags = TagSet::from_str("/one/two").unwrap();
         let internal =
-            EndNodeIterator::new(vec!["blue".to_string(), "red".to_string()].iter(), &tags);
+            EndNodeIterator::new(["blue".to_string(), "red".to_string()].iter(), &tags);
         let actual =
             MultiNodeIterator::new(vec![NodeIterator::EndNodeIter(internal)].into_iter());
  
@@ -90,9 +90,9 @@ mod tests {
         let tags1 = TagSet::from_str("/one/two").unwrap();
         let tags2 = TagSet::from_str("/one/three").unwrap();
         let internal1 =
-            EndNodeIterator::new(vec!["blue".to_string(), "red".to_string()].iter(), &tags1);
+            EndNodeIterator::new(["blue".to_string(), "red".to_string()].iter(), &tags1);
         let internal2 =
-            EndNodeIterator::new(vec!["blue".to_string(), "red".to_string()].iter(), &tags2);
+            EndNodeIterator::new(["blue".to_string(), "red".to_string()].iter(), &tags2);
  
         let actual =
             MultiNodeIterator::new(vec![NodeIterator::EndNodeIter(internal1), NodeIterator::EndNodeIter(internal2)].into_iter());
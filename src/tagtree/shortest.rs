@@ -0,0 +1,67 @@
+// Finding the fewest tags that pick out a stored file by its name, so it
+// can be shown as a short path.
+//
+// Some tags pick out the file if no other file of the same name, a rival,
+// has all of them. Any answer must include a tag the file has and a given
+// rival lacks, so rather than trying every subset of the file's tags, the
+// search takes the rival missing the fewest and tries adding each of those
+// tags in turn. Limiting the number of tags, one more each time round,
+// finds a smallest set first. Rivals are looked up in the tree with the
+// tags chosen so far, so the branch masks skip every child without them.
+
+use super::Node;
+use crate::nameindex::NameIndex;
+use crate::{File, FileDB, Query, TagSet};
+
+// The rival with all the chosen tags that lacks the fewest of the file's,
+// or None if there isn't one.
+fn closest_rival(root: &Node, names: &NameIndex, file: &File, chosen: &TagSet) -> Option<File> {
+    let missing = |rival: &File| file.tags.difference(&rival.tags).count();
+    // With no tags to prune by, the name index is quicker than the tree.
+    let rivals = if chosen.is_empty() {
+        names.find(&file.name)
+    } else {
+        root.get_files(&Query::new(chosen.clone()).with_name(&file.name))
+            .collect()
+    };
+    rivals
+        .into_iter()
+        .filter(|rival| rival != file)
+        .min_by_key(missing)
+}
+
+// Add at most limit more tags to chosen so that it picks out the file.
+// Leaves chosen as it was if that can't be done.
+fn search(root: &Node, names: &NameIndex, file: &File, chosen: &mut TagSet, limit: usize) -> bool {
+    let rival = match closest_rival(root, names, file, chosen) {
+        Some(rival) => rival,
+        None => return true,
+    };
+    if limit == 0 {
+        return false;
+    }
+    for tag in file.tags.difference(&rival.tags) {
+        chosen.insert(tag.clone());
+        if search(root, names, file, chosen, limit - 1) {
+            return true;
+        }
+        chosen.remove(tag);
+    }
+    false
+}
+
+// A smallest set of the stored file's tags that picks it out, or None if
+// even all of them don't.
+pub(crate) fn shortest_tags(root: &Node, names: &NameIndex, file: &File) -> Option<TagSet> {
+    if closest_rival(root, names, file, &file.tags).is_some() {
+        return None;
+    }
+    (0..=file.tags.len()).find_map(|limit| {
+        let mut chosen = TagSet::new();
+        if search(root, names, file, &mut chosen, limit) {
+            Some(chosen)
+        } else {
+            None
+        }
+    })
+}
@@ -0,0 +1,50 @@
+use super::nodeiterator::NodeIterator;
+use super::Node;
+use crate::fdb_trait::GetFileError;
+use crate::paging::page;
+use crate::{File, FileDB, FileQuery, Query, QueryOptions, QueryStats, ResultPage, TagSet};
+use std::sync::Arc;
+
+// A read-only view of a TagTreeDBFS as it was when the snapshot was taken.
+// Later changes to the DB are not seen. Snapshots are cheap to take and to
+// clone, and can be sent to other threads.
+#[derive(Debug, Clone)]
+pub struct TagTreeSnapshot {
+    root: Arc<Node>,
+    version: u64,
+}
+
+impl TagTreeSnapshot {
+    pub(crate) fn new(root: Arc<Node>, version: u64) -> Self {
+        TagTreeSnapshot { root, version }
+    }
+
+    // The version of the DB this is a snapshot of.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    pub fn get_files<F: FileQuery>(&self, query: &F) -> NodeIterator {
+        self.root.get_files(query)
+    }
+
+    pub fn get_page<F: FileQuery>(&self, query: &F, options: &QueryOptions) -> ResultPage {
+        page(self.get_files(query), options)
+    }
+
+    pub fn explain<F: FileQuery>(&self, query: &F) -> QueryStats {
+        let mut stats = QueryStats::default();
+        self.root.explain(query, &mut stats);
+        stats
+    }
+
+    pub fn get_file<F: FileQuery>(&self, query: &F) -> Result<File, GetFileError> {
+        self.root.get_file(query)
+    }
+
+    // The name index isn't part of the snapshot, so this searches the tree.
+    pub fn find_by_name(&self, name: &str) -> Vec<File> {
+        self.get_files(&Query::new(TagSet::new()).with_name(name))
+            .collect()
+    }
+}
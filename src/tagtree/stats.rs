@@ -0,0 +1,121 @@
+use super::tagmaskbits::TagMaskBits;
+use super::Node;
+use crate::memory::ARC_HEADER;
+use crate::TagSet;
+use std::collections::btree_map::BTreeMap;
+use std::fmt;
+use std::mem::size_of;
+
+// The shape of one BranchNode.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct BranchStats {
+    // The root is at depth 0.
+    pub depth: usize,
+    // Children that aren't Empty.
+    pub fanout: usize,
+    // Tags with a mask in this node.
+    pub tags: usize,
+}
+
+impl BranchStats {
+    // The fraction of the node's children in use.
+    pub fn occupancy(&self) -> f64 {
+        self.fanout as f64 / TagMaskBits::BITS as f64
+    }
+}
+
+// The shape and size of a TagTreeDBFS, as returned by
+// TagTreeDBFS::stats.
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct TreeStats {
+    // Every branch, in depth first order.
+    pub branches: Vec<BranchStats>,
+    pub end_nodes: usize,
+    // How many EndNodes are at each depth.
+    pub end_node_depths: BTreeMap<usize, usize>,
+    // How many EndNodes hold each number of files.
+    pub files_per_end_node: BTreeMap<usize, usize>,
+    pub files: usize,
+    pub distinct_tags: usize,
+    // An estimate of the memory allocated by the DB, tree and indexes.
+    // Nodes shared with snapshots are counted as if they weren't.
+    pub heap_bytes: usize,
+}
+
+impl TreeStats {
+    pub(crate) fn new(root: &Node) -> Self {
+        let mut stats = TreeStats::default();
+        let mut tags = TagSet::new();
+        stats.heap_bytes = size_of::<Node>() + ARC_HEADER;
+        stats.walk(root, 0, &mut tags);
+        stats.distinct_tags = tags.len();
+        stats
+    }
+
+    fn walk(&mut self, node: &Node, depth: usize, tags: &mut TagSet) {
+        match node {
+            Node::Branch(node) => {
+                self.branches.push(BranchStats {
+                    depth,
+                    fanout: node.fanout(),
+                    tags: node.tag_count(),
+                });
+                self.heap_bytes += node.own_heap_bytes();
+                for child in node.children() {
+                    self.walk(child, depth + 1, tags);
+                }
+            }
+            Node::End(node) => {
+                self.end_nodes += 1;
+                *self.end_node_depths.entry(depth).or_default() += 1;
+                *self.files_per_end_node.entry(node.len()).or_default() += 1;
+                self.files += node.len();
+                self.heap_bytes += node.own_heap_bytes();
+                tags.extend(node.all_tags());
+            }
+            Node::Empty => (),
+        }
+    }
+
+    pub fn max_depth(&self) -> usize {
+        self.end_node_depths.keys().last().copied().unwrap_or(0)
+    }
+
+    // The mean of the branches' fanouts.
+    pub fn mean_fanout(&self) -> f64 {
+        if self.branches.is_empty() {
+            return 0.0;
+        }
+        let total: usize = self.branches.iter().map(|b| b.fanout).sum();
+        total as f64 / self.branches.len() as f64
+    }
+}
+
+fn histogram(counts: &BTreeMap<usize, usize>) -> String {
+    let parts: Vec<String> = counts
+        .iter()
+        .map(|(k, v)| format!("{}: {}", k, v))
+        .collect();
+    parts.join(", ")
+}
+
+impl fmt::Display for TreeStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "branch nodes: {}", self.branches.len())?;
+        writeln!(f, "mean fanout: {:.2}", self.mean_fanout())?;
+        writeln!(f, "end nodes: {}", self.end_nodes)?;
+        writeln!(
+            f,
+            "end nodes by depth: {}",
+            histogram(&self.end_node_depths)
+        )?;
+        writeln!(
+            f,
+            "end nodes by files: {}",
+            histogram(&self.files_per_end_node)
+        )?;
+        writeln!(f, "files: {}", self.files)?;
+        writeln!(f, "distinct tags: {}", self.distinct_tags)?;
+        write!(f, "heap bytes: {}", self.heap_bytes)
+    }
+}
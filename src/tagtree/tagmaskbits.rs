@@ -1,3 +1,5 @@
+use crate::memory::HeapSize;
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub(crate) struct TagMaskBits(u32);
 
@@ -37,6 +39,15 @@ impl TagMaskBits {
         TagMaskBits(self.0 & other.0)
     }
 
+    // The bits set here but not in other.
+    pub fn without(&self, other: &Self) -> Self {
+        TagMaskBits(self.0 & !other.0)
+    }
+
+    pub fn count(&self) -> usize {
+        self.0.count_ones() as usize
+    }
+
     pub fn last_idx(&self) -> Option<usize> {
         if self.0 == 0 {
             None
@@ -47,6 +58,12 @@ impl TagMaskBits {
     }
 }
 
+impl HeapSize for TagMaskBits {
+    fn heap_bytes(&self) -> usize {
+        0
+    }
+}
+
 impl Iterator for &mut TagMaskBits {
     type Item = usize;
 
@@ -78,7 +95,7 @@ mod tests {
 
         assert_eq!(
             (0..TagMaskBits::BITS).collect::<Vec<usize>>(),
-            bits.map(|x| x).collect::<Vec<usize>>()
+            (&mut bits).collect::<Vec<usize>>()
         );
     }
 
@@ -88,7 +105,7 @@ mod tests {
 
         assert_eq!(
             (31..32).collect::<Vec<usize>>(),
-            bits.map(|x| x).collect::<Vec<usize>>()
+            (&mut bits).collect::<Vec<usize>>()
         );
     }
 
@@ -98,7 +115,7 @@ mod tests {
 
         assert_eq!(
             (0..1).collect::<Vec<usize>>(),
-            bits.map(|x| x).collect::<Vec<usize>>()
+            (&mut bits).collect::<Vec<usize>>()
         );
     }
 
@@ -106,7 +123,7 @@ mod tests {
     fn test_iterate_no_bits() {
         let mut bits = TagMaskBits(0);
 
-        assert_eq!(Vec::<usize>::new(), bits.map(|x| x).collect::<Vec<usize>>());
+        assert_eq!(Vec::<usize>::new(), (&mut bits).collect::<Vec<usize>>());
     }
 
     #[test]
@@ -0,0 +1,366 @@
+use crate::fdb_trait::{replace_file, AddFileError, GetFileError, RetagFileError};
+use crate::{Ambiguity, File, FileDB, TagSet};
+use std::collections::btree_map::BTreeMap;
+use std::collections::btree_set::BTreeSet;
+use std::collections::hash_map::HashMap;
+
+// The inode of the top directory, which has no tags.
+pub const ROOT_INODE: u64 = 1;
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum EntryKind {
+    Directory,
+    File,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Attr {
+    pub ino: u64,
+    pub kind: EntryKind,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct DirEntry {
+    pub ino: u64,
+    pub name: String,
+    pub kind: EntryKind,
+}
+
+#[derive(PartialEq, Eq, Debug)]
+pub enum VfsError {
+    NotFound,
+    NotADirectory,
+    IsADirectory,
+    Exists,
+    // Tags and file names can't be empty, or contain '/'.
+    InvalidName,
+    // More than one file of that name is in the directory; a deeper
+    // directory will tell them apart.
+    Ambiguous(Ambiguity),
+    Add(AddFileError),
+    // A moved file could neither be added under its new name and tags nor
+    // put back as it was, so it is no longer in the DB.
+    Lost {
+        file: File,
+        retag: AddFileError,
+        restore: AddFileError,
+    },
+}
+
+impl From<GetFileError> for VfsError {
+    fn from(error: GetFileError) -> Self {
+        match error {
+            GetFileError::NoSuchFile => VfsError::NotFound,
+            GetFileError::TooManyFiles(ambiguity) => VfsError::Ambiguous(ambiguity),
+        }
+    }
+}
+
+impl From<RetagFileError> for VfsError {
+    fn from(error: RetagFileError) -> Self {
+        match error {
+            RetagFileError::Get(error) => error.into(),
+            RetagFileError::Add(error) => VfsError::Add(error),
+            RetagFileError::Lost {
+                file,
+                retag,
+                restore,
+            } => VfsError::Lost {
+                file,
+                retag,
+                restore,
+            },
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Hash, Debug, Clone)]
+enum Entry {
+    Dir(TagSet),
+    File(File),
+}
+
+// Presents a database as a filesystem. A directory is a set of tags, and
+// holds every file with those tags, plus a subdirectory for each other tag
+// those files have. So "/photos/2021" and "/2021/photos" are the same
+// directory, and a file can be reached by any path made of its tags.
+//
+// Inode numbers are handed out the first time an entry is seen, and stay
+// the same for as long as the Vfs lives. A file's inode names the file with
+// its tags, so retagging a file gives it a new inode.
+pub struct Vfs<DB: FileDB> {
+    db: DB,
+    entries: Vec<Entry>,
+    inodes: HashMap<Entry, u64>,
+    // Directories made with mkdir, which exist before any file is in them.
+    created: BTreeSet<TagSet>,
+}
+
+impl<DB: FileDB> Vfs<DB> {
+    pub fn new(db: DB) -> Self {
+        let mut vfs = Vfs {
+            db,
+            entries: vec![],
+            inodes: HashMap::new(),
+            created: BTreeSet::new(),
+        };
+        vfs.inode(Entry::Dir(TagSet::new()));
+        vfs
+    }
+
+    pub fn inner(&self) -> &DB {
+        &self.db
+    }
+
+    pub fn into_inner(self) -> DB {
+        self.db
+    }
+
+    pub fn lookup(&mut self, parent: u64, name: &str) -> Result<Attr, VfsError> {
+        let dir = self.dir(parent)?.clone();
+        let entry = self.find_entry(&dir, name)?;
+        Ok(self.attr(entry))
+    }
+
+    pub fn getattr(&mut self, ino: u64) -> Result<Attr, VfsError> {
+        let entry = self.entry(ino)?.clone();
+        let exists = match &entry {
+            Entry::Dir(tags) => self.dir_exists(tags),
+            Entry::File(file) => self.db.get_file(file).as_ref() == Ok(file),
+        };
+        if exists {
+            Ok(self.attr(entry))
+        } else {
+            Err(VfsError::NotFound)
+        }
+    }
+
+    // Entries are sorted by name. Where a file and a tag share a name, the
+    // file is listed. Files whose name is shared by others in the directory
+    // are left out, as they can't be looked up until the directory is
+    // narrowed.
+    pub fn readdir(&mut self, ino: u64) -> Result<Vec<DirEntry>, VfsError> {
+        let dir = self.dir(ino)?.clone();
+
+        let mut listing = BTreeMap::new();
+        for tag in self.refinements(&dir) {
+            let mut tags = dir.clone();
+            tags.insert(tag.clone());
+            listing.insert(tag, Entry::Dir(tags));
+        }
+
+        let mut by_name: BTreeMap<String, Vec<File>> = BTreeMap::new();
+        for f in self.db.get_files(&dir) {
+            by_name.entry(f.name.clone()).or_default().push(f);
+        }
+        for (name, mut files) in by_name {
+            if files.len() == 1 {
+                listing.insert(name, Entry::File(files.pop().unwrap()));
+            }
+        }
+
+        Ok(listing
+            .into_iter()
+            .map(|(name, entry)| {
+                let attr = self.attr(entry);
+                DirEntry {
+                    ino: attr.ino,
+                    name,
+                    kind: attr.kind,
+                }
+            })
+            .collect())
+    }
+
+    // Make a new tag, as a directory inside the parent. It is listed there
+    // even if no file is ever given the tag.
+    pub fn mkdir(&mut self, parent: u64, name: &str) -> Result<Attr, VfsError> {
+        let mut tags = self.vacant(parent, name)?;
+        tags.insert(name.to_string());
+        self.created.insert(tags.clone());
+        Ok(self.attr(Entry::Dir(tags)))
+    }
+
+    // Add an empty file, tagged with the tags of its directory.
+    pub fn create(&mut self, parent: u64, name: &str) -> Result<Attr, VfsError> {
+        let tags = self.vacant(parent, name)?;
+        let file = File::new(name.to_string(), tags);
+        self.db.add_file(&file).map_err(VfsError::Add)?;
+        let stored = self.db.get_file(&file)?;
+        Ok(self.attr(Entry::File(stored)))
+    }
+
+    pub fn unlink(&mut self, parent: u64, name: &str) -> Result<(), VfsError> {
+        let dir = self.dir(parent)?.clone();
+        match self.find_entry(&dir, name)? {
+            Entry::File(file) => {
+                self.db.remove_file(&file)?;
+                Ok(())
+            }
+            Entry::Dir(_) => Err(VfsError::IsADirectory),
+        }
+    }
+
+    // Moving an entry swaps the tags of its old directory for those of the
+    // new one; any other tags are kept. Renaming a directory retags every
+    // file in it, one at a time; if one fails, those before it are moved
+    // back. A file keeps its inode only if its tags don't change.
+    pub fn rename(
+        &mut self,
+        parent: u64,
+        name: &str,
+        new_parent: u64,
+        new_name: &str,
+    ) -> Result<(), VfsError> {
+        let from = self.dir(parent)?.clone();
+        let mut to = self.dir(new_parent)?.clone();
+        check_name(new_name)?;
+
+        match self.find_entry(&from, name)? {
+            Entry::File(file) => {
+                if self.find_entry(&to, new_name).is_ok() {
+                    return Err(VfsError::Exists);
+                }
+                self.move_file(&file, &from, &to, new_name)?;
+                Ok(())
+            }
+            Entry::Dir(old_dir) => {
+                to.insert(new_name.to_string());
+                if self.dir_exists(&to) {
+                    return Err(VfsError::Exists);
+                }
+                let files: Vec<File> = self.db.get_files(&old_dir).collect();
+                let mut moved = vec![];
+                for f in &files {
+                    match self.move_file(f, &old_dir, &to, &f.name) {
+                        Ok(new) => moved.push((f, new)),
+                        Err(error) => {
+                            // Undo the moves made so far, latest first.
+                            for (old, new) in moved.into_iter().rev() {
+                                self.db.retag_file(&new, old.tags.clone())?;
+                            }
+                            return Err(error);
+                        }
+                    }
+                }
+                if self.created.remove(&old_dir) {
+                    self.created.insert(to);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn move_file(
+        &mut self,
+        file: &File,
+        from: &TagSet,
+        to: &TagSet,
+        new_name: &str,
+    ) -> Result<File, VfsError> {
+        let tags: TagSet = file.tags.difference(from).chain(to).cloned().collect();
+        let new = File::new(new_name.to_string(), tags);
+        if new_name == file.name {
+            self.db.retag_file(file, new.tags.clone())?;
+        } else {
+            replace_file(&mut self.db, file, |_| new.clone())?;
+        }
+        Ok(new)
+    }
+
+    // The tags of the parent directory, if nothing called name is in it.
+    fn vacant(&self, parent: u64, name: &str) -> Result<TagSet, VfsError> {
+        let dir = self.dir(parent)?.clone();
+        check_name(name)?;
+        match self.find_entry(&dir, name) {
+            Err(VfsError::NotFound) => Ok(dir),
+            Ok(_) | Err(VfsError::Ambiguous(_)) => Err(VfsError::Exists),
+            Err(error) => Err(error),
+        }
+    }
+
+    // Files are looked for first, so a file hides a tag of the same name.
+    fn find_entry(&self, dir: &TagSet, name: &str) -> Result<Entry, VfsError> {
+        match self.db.get_file(&File::new(name.to_string(), dir.clone())) {
+            Ok(file) => return Ok(Entry::File(file)),
+            Err(GetFileError::TooManyFiles(ambiguity)) => {
+                return Err(VfsError::Ambiguous(ambiguity))
+            }
+            Err(GetFileError::NoSuchFile) => {}
+        }
+
+        if dir.contains(name) {
+            return Err(VfsError::NotFound);
+        }
+        let mut tags = dir.clone();
+        tags.insert(name.to_string());
+        if self.dir_exists(&tags) {
+            Ok(Entry::Dir(tags))
+        } else {
+            Err(VfsError::NotFound)
+        }
+    }
+
+    fn dir_exists(&self, tags: &TagSet) -> bool {
+        tags.is_empty() || self.created.contains(tags) || self.db.get_files(tags).next().is_some()
+    }
+
+    // Tags that narrow the directory: those of the files in it, and those
+    // made in it with mkdir.
+    fn refinements(&self, dir: &TagSet) -> BTreeSet<String> {
+        let mut tags: BTreeSet<String> = self
+            .db
+            .get_files(dir)
+            .flat_map(|f| f.tags.into_iter())
+            .collect();
+        for created in &self.created {
+            if created.len() == dir.len() + 1 && created.is_superset(dir) {
+                tags.extend(created.iter().cloned());
+            }
+        }
+        tags.retain(|t| !dir.contains(t));
+        tags
+    }
+
+    fn dir(&self, ino: u64) -> Result<&TagSet, VfsError> {
+        match self.entry(ino)? {
+            Entry::Dir(tags) => Ok(tags),
+            Entry::File(_) => Err(VfsError::NotADirectory),
+        }
+    }
+
+    fn entry(&self, ino: u64) -> Result<&Entry, VfsError> {
+        ino.checked_sub(ROOT_INODE)
+            .and_then(|i| self.entries.get(i as usize))
+            .ok_or(VfsError::NotFound)
+    }
+
+    fn inode(&mut self, entry: Entry) -> u64 {
+        if let Some(&ino) = self.inodes.get(&entry) {
+            return ino;
+        }
+        let ino = ROOT_INODE + self.entries.len() as u64;
+        self.entries.push(entry.clone());
+        self.inodes.insert(entry, ino);
+        ino
+    }
+
+    fn attr(&mut self, entry: Entry) -> Attr {
+        let kind = match entry {
+            Entry::Dir(_) => EntryKind::Directory,
+            Entry::File(_) => EntryKind::File,
+        };
+        Attr {
+            ino: self.inode(entry),
+            kind,
+        }
+    }
+}
+
+fn check_name(name: &str) -> Result<(), VfsError> {
+    if name.is_empty() || name.contains('/') {
+        Err(VfsError::InvalidName)
+    } else {
+        Ok(())
+    }
+}
@@ -0,0 +1,318 @@
+use crate::File;
+use crate::Query;
+use crate::TagSet;
+use std::collections::hash_map::HashMap;
+
+// A small, fast PRNG (SplitMix64). Workloads use their own rather than a
+// crate so the same seed gives the same dataset whatever the dependencies.
+#[derive(Debug, Clone)]
+pub(crate) struct Rng(u64);
+
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Self {
+        Rng(seed)
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // A number in 0..n, which must not be 0.
+    pub(crate) fn below(&mut self, n: usize) -> usize {
+        ((self.next_u64() as u128 * n as u128) >> 64) as usize
+    }
+
+    // A number in [0, 1).
+    pub(crate) fn unit(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+// Picks ranks in 0..n, rank k with probability proportional to
+// 1 / (k + 1)^exponent. An exponent of 0 makes every rank equally likely.
+#[derive(Debug, Clone)]
+struct Zipf {
+    cumulative: Vec<f64>,
+}
+
+impl Zipf {
+    fn new(n: usize, exponent: f64) -> Self {
+        let mut total = 0.0;
+        let cumulative = (0..n)
+            .map(|k| {
+                total += 1.0 / ((k + 1) as f64).powf(exponent);
+                total
+            })
+            .collect();
+        Zipf { cumulative }
+    }
+
+    fn sample(&self, rng: &mut Rng) -> usize {
+        let total = self.cumulative.last().copied().unwrap_or(0.0);
+        let target = rng.unit() * total;
+        self.cumulative
+            .partition_point(|&c| c <= target)
+            .min(self.cumulative.len() - 1)
+    }
+}
+
+// How many tags each generated file has.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum TagsPerFile {
+    Fixed(usize),
+    // Between the two, inclusive, all equally likely.
+    Uniform(usize, usize),
+    // At least one, with this mean and a long tail.
+    Geometric(f64),
+}
+
+impl TagsPerFile {
+    fn sample(&self, rng: &mut Rng) -> usize {
+        match *self {
+            TagsPerFile::Fixed(n) => n,
+            TagsPerFile::Uniform(min, max) if max > min => min + rng.below(max - min + 1),
+            TagsPerFile::Uniform(min, _) => min,
+            TagsPerFile::Geometric(mean) => {
+                let more = 1.0 - 1.0 / mean.max(1.0);
+                let mut n = 1;
+                while rng.unit() < more {
+                    n += 1;
+                }
+                n
+            }
+        }
+    }
+}
+
+// The kinds of query Workload::generate_queries makes, by relative weight.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct QueryMix {
+    // A single tag, picked by popularity, so usually a broad query.
+    pub popular_tag: u32,
+    // Some of the tags of a generated file, so at least that file matches.
+    pub file_tags: u32,
+    // The name of a generated file, and no tags.
+    pub name: u32,
+    // A tag from a generated file and one no file has, matching nothing.
+    pub miss: u32,
+}
+
+impl Default for QueryMix {
+    fn default() -> Self {
+        QueryMix {
+            popular_tag: 4,
+            file_tags: 4,
+            name: 1,
+            miss: 1,
+        }
+    }
+}
+
+// Describes a synthetic dataset, so backends can be compared on shapes of
+// data other than the test files. The same description, seed included,
+// always generates the same files and queries.
+//
+// Tags are named "tag0", "tag1" and so on in order of popularity, which
+// follows a Zipf distribution. Files are named "file0.txt" and so on, but a
+// fraction of them reuse the name of an earlier file, with tags that keep
+// them apart, so every file can be added to any backend.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Workload {
+    file_count: usize,
+    vocabulary: usize,
+    zipf_exponent: f64,
+    tags_per_file: TagsPerFile,
+    name_collisions: f64,
+    seed: u64,
+}
+
+impl Default for Workload {
+    fn default() -> Self {
+        Workload {
+            file_count: 1000,
+            vocabulary: 100,
+            zipf_exponent: 1.0,
+            tags_per_file: TagsPerFile::Uniform(1, 5),
+            name_collisions: 0.05,
+            seed: 0,
+        }
+    }
+}
+
+// The largest number of tags tried for a file before giving up on finding
+// as many distinct ones as it should have.
+const MAX_DRAWS_PER_TAG: usize = 32;
+
+impl Workload {
+    pub fn new(file_count: usize) -> Self {
+        Workload {
+            file_count,
+            ..Workload::default()
+        }
+    }
+
+    // The number of distinct tags to draw from.
+    pub fn with_vocabulary(mut self, tags: usize) -> Self {
+        self.vocabulary = tags.max(1);
+        self
+    }
+
+    // How skewed tag popularity is; 0 makes every tag equally popular, and
+    // around 1 is typical of real tags.
+    pub fn with_zipf_exponent(mut self, exponent: f64) -> Self {
+        self.zipf_exponent = exponent;
+        self
+    }
+
+    pub fn with_tags_per_file(mut self, tags_per_file: TagsPerFile) -> Self {
+        self.tags_per_file = tags_per_file;
+        self
+    }
+
+    // The fraction of files, from 0 to 1, given the name of an earlier file.
+    pub fn with_name_collisions(mut self, fraction: f64) -> Self {
+        self.name_collisions = fraction;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    fn tag(rank: usize) -> String {
+        format!("tag{}", rank)
+    }
+
+    fn draw_tags(&self, zipf: &Zipf, rng: &mut Rng) -> TagSet {
+        let wanted = self.tags_per_file.sample(rng).clamp(1, self.vocabulary);
+        let mut tags = TagSet::new();
+        for _ in 0..wanted * MAX_DRAWS_PER_TAG {
+            if tags.len() == wanted {
+                break;
+            }
+            tags.insert(Workload::tag(zipf.sample(rng)));
+        }
+        tags
+    }
+
+    pub fn generate_files(&self) -> Vec<File> {
+        let mut rng = Rng::new(self.seed);
+        let zipf = Zipf::new(self.vocabulary, self.zipf_exponent);
+        let mut files = Vec::with_capacity(self.file_count);
+        let mut by_name: HashMap<String, Vec<usize>> = HashMap::new();
+
+        while files.len() < self.file_count {
+            let tags = self.draw_tags(&zipf, &mut rng);
+            let mut name = None;
+            if !files.is_empty() && rng.unit() < self.name_collisions {
+                let other: &File = &files[rng.below(files.len())];
+                let other_name = other.name.clone();
+                // A file whose tags are a subset of another's of the same
+                // name can't be told apart from it.
+                let clashes = by_name[&other_name].iter().any(|&i| {
+                    let other_tags = &files[i].tags;
+                    tags.is_subset(other_tags) || other_tags.is_subset(&tags)
+                });
+                if !clashes {
+                    name = Some(other_name);
+                }
+            }
+            let name = name.unwrap_or_else(|| format!("file{}.txt", files.len()));
+
+            by_name.entry(name.clone()).or_default().push(files.len());
+            files.push(File::new(name, tags));
+        }
+        files
+    }
+
+    // Queries over the given files, which should be the ones this workload
+    // generated, in the proportions the mix asks for.
+    pub fn generate_queries(&self, files: &[File], count: usize, mix: &QueryMix) -> Vec<Query> {
+        let mut rng = Rng::new(self.seed ^ 0x5155_4552_4945_5321);
+        let zipf = Zipf::new(self.vocabulary, self.zipf_exponent);
+        let total = mix.popular_tag + mix.file_tags + mix.name + mix.miss;
+        if files.is_empty() || total == 0 {
+            return vec![];
+        }
+
+        (0..count)
+            .map(|_| {
+                let file = &files[rng.below(files.len())];
+                let tags: Vec<&String> = file.tags.iter().collect();
+                let mut kind = rng.below(total as usize) as u32;
+
+                if kind < mix.popular_tag {
+                    return Query::new([Workload::tag(zipf.sample(&mut rng))].into());
+                }
+                kind -= mix.popular_tag;
+
+                if kind < mix.file_tags {
+                    let wanted = 1 + rng.below(tags.len().max(1));
+                    let mut subset = TagSet::new();
+                    while subset.len() < wanted.min(tags.len()) {
+                        subset.insert(tags[rng.below(tags.len())].clone());
+                    }
+                    return Query::new(subset);
+                }
+                kind -= mix.file_tags;
+
+                if kind < mix.name {
+                    return Query::new(TagSet::new()).with_name(&file.name);
+                }
+
+                let mut subset = TagSet::new();
+                if let Some(tag) = tags.first() {
+                    subset.insert(tag.to_string());
+                }
+                subset.insert(Workload::tag(self.vocabulary));
+                Query::new(subset)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Rng, Zipf};
+
+    #[test]
+    fn rng_should_repeat_for_a_seed() {
+        let a: Vec<u64> = (0..5).scan(Rng::new(7), |r, _| Some(r.next_u64())).collect();
+        let b: Vec<u64> = (0..5).scan(Rng::new(7), |r, _| Some(r.next_u64())).collect();
+        let c: Vec<u64> = (0..5).scan(Rng::new(8), |r, _| Some(r.next_u64())).collect();
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+
+        let mut rng = Rng::new(1);
+        for _ in 0..1000 {
+            assert!(rng.below(3) < 3);
+            let x = rng.unit();
+            assert!((0.0..1.0).contains(&x));
+        }
+    }
+
+    #[test]
+    fn zipf_should_favour_low_ranks() {
+        let zipf = Zipf::new(10, 1.0);
+        let mut rng = Rng::new(0);
+        let mut counts = [0; 10];
+        for _ in 0..10_000 {
+            counts[zipf.sample(&mut rng)] += 1;
+        }
+        // Rank 0 is about ten times as likely as rank 9.
+        assert!(counts[0] > 5 * counts[9], "{:?}", counts);
+        assert!(counts[0] > counts[1] && counts[1] > counts[4]);
+
+        let flat = Zipf::new(10, 0.0);
+        let mut counts = [0; 10];
+        for _ in 0..10_000 {
+            counts[flat.sample(&mut rng)] += 1;
+        }
+        assert!(counts.iter().all(|&c| c > 800 && c < 1200), "{:?}", counts);
+    }
+}
@@ -0,0 +1,250 @@
+#![cfg(feature = "archive")]
+
+use flate2::write::GzEncoder;
+use rdb_fs::fromstr::FromStr;
+use rdb_fs::{
+    index_archive, read_entries, read_entry, ArchiveEntry, ArchiveError, ArchiveKind,
+    Compression, File, FileDB, FileQuery, Query, TagSet, TagTreeDBFS,
+};
+use std::collections::hash_set::HashSet;
+use std::io::{Cursor, Write};
+use zip::write::FileOptions;
+use zip::CompressionMethod;
+
+fn long_path() -> String {
+    format!("deep/{}/notes.txt", "nested".repeat(30))
+}
+
+// The contents every test archive holds, with a directory entry too.
+fn contents() -> Vec<(String, Vec<u8>)> {
+    vec![
+        ("./bin/app".to_string(), b"\x7fELF binary".to_vec()),
+        ("./lib/libapp.so".to_string(), vec![7; 3000]),
+        ("./README".to_string(), b"read me".to_vec()),
+        (long_path(), b"far down".to_vec()),
+    ]
+}
+
+fn tar_bytes() -> Vec<u8> {
+    let mut builder = tar::Builder::new(vec![]);
+    let mut dir = tar::Header::new_gnu();
+    dir.set_entry_type(tar::EntryType::Directory);
+    dir.set_size(0);
+    builder.append_data(&mut dir, "./bin/", &[][..]).unwrap();
+
+    for (path, data) in contents() {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        if path == "./README" {
+            // Named by a pax header rather than in its own.
+            builder
+                .append_pax_extensions([("path", path.as_bytes())])
+                .unwrap();
+            builder.append_data(&mut header, "placeholder", &data[..]).unwrap();
+        } else {
+            builder.append_data(&mut header, &path, &data[..]).unwrap();
+        }
+    }
+    builder.into_inner().unwrap()
+}
+
+fn tar_gz_bytes() -> Vec<u8> {
+    let mut gz = GzEncoder::new(vec![], flate2::Compression::default());
+    gz.write_all(&tar_bytes()).unwrap();
+    gz.finish().unwrap()
+}
+
+fn zip_bytes() -> Vec<u8> {
+    let mut zip = zip::ZipWriter::new(Cursor::new(vec![]));
+    zip.add_directory("bin/", FileOptions::default()).unwrap();
+    for (i, (path, data)) in contents().into_iter().enumerate() {
+        let method = if i % 2 == 0 {
+            CompressionMethod::Deflated
+        } else {
+            CompressionMethod::Stored
+        };
+        let options = FileOptions::default().compression_method(method);
+        zip.start_file(path.trim_start_matches("./"), options)
+            .unwrap();
+        zip.write_all(&data).unwrap();
+    }
+    zip.finish().unwrap().into_inner()
+}
+
+fn check_archive(bytes: Vec<u8>, kind: ArchiveKind, archive: &str) {
+    assert_eq!(kind, ArchiveKind::detect(&mut Cursor::new(&bytes)).unwrap());
+
+    let mut db = TagTreeDBFS::new();
+    assert_eq!(4, index_archive(&mut db, Cursor::new(&bytes), archive).unwrap());
+
+    // Every entry is tagged with the archive and its directories.
+    let in_archive: HashSet<File> = db.get_files(&TagSet::from_str(archive).unwrap()).collect();
+    assert_eq!(4, in_archive.len());
+    let app = db
+        .get_file(&Query::from_str(&format!("/{}/bin", archive)).unwrap().with_name("app"))
+        .unwrap();
+    assert!(app.tags().contains("size=11"));
+
+    // Sizes can be searched.
+    let big = Query::from_str("/size>1K").unwrap();
+    let big: Vec<File> = db.get_files(&big).collect();
+    assert_eq!(1, big.len());
+    assert_eq!(Some("libapp.so"), big[0].name());
+
+    // And every entry can be read back.
+    for (path, data) in contents() {
+        let name = path.rsplit('/').next().unwrap();
+        let file = db
+            .get_file(&Query::new(TagSet::new()).with_name(name))
+            .unwrap();
+        assert_eq!(data, read_entry(Cursor::new(&bytes), &file).unwrap(), "{}", path);
+    }
+}
+
+#[test]
+fn tar_entries_should_be_indexed() {
+    check_archive(tar_bytes(), ArchiveKind::Tar, "build.tar");
+}
+
+#[test]
+fn tar_gz_entries_should_be_indexed() {
+    check_archive(tar_gz_bytes(), ArchiveKind::TarGz, "build.tar.gz");
+}
+
+#[test]
+fn zip_entries_should_be_indexed() {
+    check_archive(zip_bytes(), ArchiveKind::Zip, "build.zip");
+
+    let entries = read_entries(Cursor::new(zip_bytes())).unwrap();
+    assert_eq!(Compression::Deflate, entries[0].compression);
+    assert_eq!(Compression::Stored, entries[1].compression);
+    assert!(entries[1].packed_size == entries[1].size);
+}
+
+#[test]
+fn long_tar_paths_should_be_kept() {
+    let entries = read_entries(Cursor::new(tar_bytes())).unwrap();
+    let paths: Vec<&str> = entries
+        .iter()
+        .map(|e| e.path.trim_start_matches("./"))
+        .collect();
+    assert_eq!(vec!["bin/app", "lib/libapp.so", "README", &long_path()], paths);
+}
+
+#[test]
+fn entries_should_become_files() {
+    let entry = ArchiveEntry {
+        path: "./a/b/c.txt".to_string(),
+        size: 10,
+        offset: 512,
+        packed_size: 4,
+        compression: Compression::Deflate,
+    };
+    let file = entry.to_file("x.zip").unwrap();
+    assert_eq!(
+        File::new_cloned(
+            "c.txt",
+            ["a", "b", "x.zip", "size=10", "offset=512", "packed=4", "compression=deflate"]
+        ),
+        file
+    );
+
+    let located = ArchiveEntry::locate(&file).unwrap();
+    assert_eq!(
+        ArchiveEntry {
+            path: "c.txt".to_string(),
+            ..entry
+        },
+        located
+    );
+}
+
+#[test]
+fn directories_named_like_metadata_should_not_be_mistaken_for_it() {
+    let mut builder = tar::Builder::new(vec![]);
+    for (path, data) in [("size=0/offset=0/notes.txt", &b"the notes"[..]), ("a/b.txt", b"b")] {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        builder.append_data(&mut header, path, data).unwrap();
+    }
+    let bytes = builder.into_inner().unwrap();
+
+    let mut db = TagTreeDBFS::new();
+    assert_eq!(2, index_archive(&mut db, Cursor::new(&bytes), "x.tar").unwrap());
+
+    let notes = db
+        .get_file(&Query::new(TagSet::new()).with_name("notes.txt"))
+        .unwrap();
+    assert!(notes.tags().contains("size%3D0"));
+    assert!(notes.tags().contains("offset%3D0"));
+    assert!(notes.tags().contains("size=9"));
+    assert_eq!(b"the notes".to_vec(), read_entry(Cursor::new(&bytes), &notes).unwrap());
+}
+
+#[test]
+fn bad_archives_should_be_rejected() {
+    match read_entries(Cursor::new(b"just some text".to_vec())) {
+        Err(ArchiveError::UnknownFormat) => (),
+        other => panic!("Expected an unknown format, got {:?}", other),
+    }
+
+    let mut tar = tar_bytes();
+    tar[600] ^= 0xff;
+    match read_entries(Cursor::new(tar)) {
+        Err(ArchiveError::Malformed(_)) => (),
+        other => panic!("Expected a malformed archive, got {:?}", other),
+    }
+
+    let file = File::new_cloned("plain.txt", ["docs"]);
+    match read_entry(Cursor::new(zip_bytes()), &file) {
+        Err(ArchiveError::NotAnEntry(f)) => assert_eq!(file, f),
+        other => panic!("Expected not an entry, got {:?}", other),
+    }
+}
+
+#[test]
+fn huge_sizes_should_be_rejected() {
+    let mut builder = tar::Builder::new(vec![]);
+    builder
+        .append_pax_extensions([("size", &b"18446744073709551615"[..])])
+        .unwrap();
+    let mut header = tar::Header::new_gnu();
+    header.set_size(4);
+    builder.append_data(&mut header, "huge", &b"huge"[..]).unwrap();
+    match read_entries(Cursor::new(builder.into_inner().unwrap())) {
+        Err(ArchiveError::Malformed(_)) => (),
+        other => panic!("Expected a malformed archive, got {:?}", other),
+    }
+
+    let readme = read_entries(Cursor::new(tar_bytes()))
+        .unwrap()
+        .into_iter()
+        .find(|entry| entry.path.ends_with("README"))
+        .unwrap();
+    let entry = ArchiveEntry {
+        size: u64::MAX,
+        packed_size: u64::MAX,
+        ..readme
+    };
+    let file = entry.to_file("x.tar").unwrap();
+    match read_entry(Cursor::new(tar_bytes()), &file) {
+        Err(ArchiveError::Malformed(_)) => (),
+        other => panic!("Expected a malformed entry, got {:?}", other),
+    }
+
+    // Inflating to more than the recorded size is as bad as less.
+    let deflated = read_entries(Cursor::new(zip_bytes()))
+        .unwrap()
+        .into_iter()
+        .find(|entry| entry.compression == Compression::Deflate)
+        .unwrap();
+    let entry = ArchiveEntry {
+        size: deflated.size - 1,
+        ..deflated
+    };
+    let file = entry.to_file("x.zip").unwrap();
+    match read_entry(Cursor::new(zip_bytes()), &file) {
+        Err(ArchiveError::Malformed(_)) => (),
+        other => panic!("Expected a malformed entry, got {:?}", other),
+    }
+}
@@ -0,0 +1,191 @@
+mod helpers;
+
+use crate::helpers::file_list_from_iter_str;
+use rdb_fs::fromstr::FromStr;
+use rdb_fs::{AddFileError, Batch, BatchError, BatchOpError, File, FileDB, GetFileError, Query};
+use rdb_fs::{HashTagsDBFS, NaiveDBFS, TagSet, TagTreeDBFS};
+use std::collections::hash_set::HashSet;
+
+fn all_files<DB: FileDB>(db: &DB) -> HashSet<File> {
+    db.get_files(&TagSet::from_str("/etc").unwrap()).collect()
+}
+
+#[test]
+fn tagtree_batch_should_add_all_files() {
+    let mut db = TagTreeDBFS::new();
+    let files = file_list_from_iter_str([
+        "/etc/fine/shoes/make.txt",
+        "/etc/fine/shoes/blue.png",
+        "/etc/partition/fourteen.one",
+    ]);
+
+    let batch: Batch = files.iter().cloned().collect();
+
+    assert_eq!(Ok(vec![]), db.apply_batch(&batch));
+    assert_eq!(files, all_files(&db));
+}
+
+#[test]
+fn tagtree_batch_should_add_nothing_if_any_file_fails() {
+    let mut db = TagTreeDBFS::new();
+    db.add_file(&File::from_str("/etc/fine/make.txt").unwrap())
+        .unwrap();
+
+    let mut batch = Batch::new();
+    batch
+        .add_file(File::from_str("/etc/new.txt").unwrap())
+        .add_file(File::from_str("/etc/fine/make.txt").unwrap());
+
+    assert_eq!(
+        Err(BatchError {
+            index: 1,
+            error: BatchOpError::Add(AddFileError::Duplicate)
+        }),
+        db.apply_batch(&batch)
+    );
+    assert_eq!(
+        file_list_from_iter_str(["/etc/fine/make.txt"]),
+        all_files(&db)
+    );
+}
+
+#[test]
+fn tagtree_batch_should_reject_duplicates_within_batch() {
+    let mut db = TagTreeDBFS::new();
+
+    let mut batch = Batch::new();
+    batch
+        .add_file(File::from_str("/etc/a.txt").unwrap())
+        .add_file(File::from_str("/etc/b.txt").unwrap())
+        .add_file(File::from_str("/etc/a.txt").unwrap());
+
+    assert_eq!(
+        Err(BatchError {
+            index: 2,
+            error: BatchOpError::Add(AddFileError::Duplicate)
+        }),
+        db.apply_batch(&batch)
+    );
+    assert!(all_files(&db).is_empty());
+}
+
+#[test]
+fn naive_batch_should_add_nothing_if_any_file_fails() {
+    let mut db = NaiveDBFS::new();
+
+    let mut batch = Batch::new();
+    batch
+        .add_file(File::from_str("/etc/a.txt").unwrap())
+        .add_file(File::new_cloned("b.txt", ["etc", ""]));
+
+    assert_eq!(
+        Err(BatchError {
+            index: 1,
+            error: BatchOpError::Add(AddFileError::InvalidTag("".to_string()))
+        }),
+        db.apply_batch(&batch)
+    );
+    assert!(all_files(&db).is_empty());
+}
+
+#[test]
+fn hashtags_batch_should_add_nothing_if_files_in_batch_conflict() {
+    let mut db = HashTagsDBFS::new();
+
+    let mut batch = Batch::new();
+    batch
+        .add_file(File::from_str("/etc/fine/make.txt").unwrap())
+        .add_file(File::from_str("/etc/make.txt").unwrap());
+
+    assert!(matches!(
+        db.apply_batch(&batch),
+        Err(BatchError {
+            index: 1,
+            error: BatchOpError::Add(AddFileError::Conflict(_))
+        })
+    ));
+    assert!(db.find_by_name("make.txt").is_empty());
+}
+
+#[test]
+fn tagtree_batch_should_remove_files_in_order() {
+    let mut db = TagTreeDBFS::new();
+    db.add_file(&File::from_str("/etc/old.txt").unwrap())
+        .unwrap();
+
+    let mut batch = Batch::new();
+    batch
+        .add_file(File::from_str("/etc/new.txt").unwrap())
+        .remove_file(Query::new(TagSet::new()).with_name("old.txt"))
+        .add_file(File::from_str("/etc/fine/old.txt").unwrap())
+        .remove_file(Query::new(TagSet::new()).with_name("new.txt"));
+
+    assert_eq!(
+        Ok(vec![
+            File::from_str("/etc/old.txt").unwrap(),
+            File::from_str("/etc/new.txt").unwrap()
+        ]),
+        db.apply_batch(&batch)
+    );
+    assert_eq!(
+        file_list_from_iter_str(["/etc/fine/old.txt"]),
+        all_files(&db)
+    );
+}
+
+#[test]
+fn tagtree_batch_should_remove_nothing_if_any_op_fails() {
+    let mut db = TagTreeDBFS::new();
+    let files = file_list_from_iter_str(["/etc/a.txt", "/etc/b.txt"]);
+    for f in &files {
+        db.add_file(f).unwrap();
+    }
+
+    let mut batch = Batch::new();
+    batch
+        .remove_file(Query::new(TagSet::new()).with_name("a.txt"))
+        .remove_file(Query::new(TagSet::new()).with_name("a.txt"));
+
+    assert_eq!(
+        Err(BatchError {
+            index: 1,
+            error: BatchOpError::Remove(GetFileError::NoSuchFile)
+        }),
+        db.apply_batch(&batch)
+    );
+    assert_eq!(files, all_files(&db));
+}
+
+#[test]
+fn tagtree_batch_should_match_adding_one_by_one() {
+    let files: Vec<File> = (0..200)
+        .map(|i| File::new_cloned(&format!("{}.txt", i), ["etc", &format!("t{}", i % 13)]))
+        .collect();
+
+    let mut one_by_one = TagTreeDBFS::new();
+    for f in &files {
+        one_by_one.add_file(f).unwrap();
+    }
+
+    let mut batched = TagTreeDBFS::new();
+    let batch: Batch = files.iter().cloned().collect();
+    assert_eq!(Ok(vec![]), batched.apply_batch(&batch));
+
+    assert_eq!(all_files(&one_by_one), all_files(&batched));
+    assert!(batched.check_invariants().is_ok());
+}
+
+#[test]
+fn naive_batch_should_remove_files() {
+    let mut db = NaiveDBFS::new();
+    db.add_file(&File::from_str("/etc/a.txt").unwrap()).unwrap();
+
+    let mut batch = Batch::new();
+    batch.remove_file(Query::new(TagSet::from_str("/etc").unwrap()).with_name("a.txt"));
+
+    assert_eq!(
+        Ok(vec![File::from_str("/etc/a.txt").unwrap()]),
+        db.apply_batch(&batch)
+    );
+    assert!(all_files(&db).is_empty());
+}
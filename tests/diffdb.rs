@@ -0,0 +1,133 @@
+mod helpers;
+
+use crate::helpers::hprops::arb_tagged_file;
+use crate::helpers::{file_list_from_iter_str, with_photos};
+use proptest::collection;
+use proptest::proptest;
+use rdb_fs::fromstr::FromStr;
+use rdb_fs::{
+    DiffDBFS, File, FileDB, FileQuery, HashTagsDBFS, NaiveDBFS, OnDivergence, Operation, Query,
+    TagSet, TagTreeDBFS,
+};
+use std::collections::hash_set::HashSet;
+
+// A hash backend that already holds a file the naive one doesn't.
+fn diverging_secondary() -> HashTagsDBFS {
+    let mut db = HashTagsDBFS::new();
+    db.add_file(&File::from_str("/photos/2021/extra.jpg").unwrap())
+        .unwrap();
+    db
+}
+
+#[test]
+fn diffdb_should_pass_through_when_backends_agree() {
+    let mut db = with_photos(DiffDBFS::new(NaiveDBFS::new(), TagTreeDBFS::new()));
+
+    let query = TagSet::from_str("/photos/2021").unwrap();
+    assert_eq!(
+        file_list_from_iter_str(["/photos/2021/beach.jpg"]),
+        db.get_files(&query).collect::<HashSet<File>>()
+    );
+
+    let snow = Query::new(TagSet::new()).with_name("snow.jpg");
+    db.retag_file(&snow, TagSet::from_str("/photos/winter").unwrap())
+        .unwrap();
+    assert_eq!(1, db.find_by_name("snow.jpg").len());
+    db.remove_file(&snow).unwrap();
+    assert!(db.get_file(&snow).is_err());
+
+    assert_eq!(None, db.divergence());
+    assert_eq!(2, db.primary().get_files(&TagSet::new()).count());
+    assert_eq!(2, db.secondary().get_files(&TagSet::new()).count());
+}
+
+#[test]
+fn diffdb_should_record_the_first_divergence() {
+    let db = with_photos(
+        DiffDBFS::new(NaiveDBFS::new(), diverging_secondary())
+            .with_on_divergence(OnDivergence::Record),
+    );
+
+    let query = TagSet::from_str("/photos/2021").unwrap();
+    let found: HashSet<File> = db.get_files(&query).collect();
+    // Answered by the primary.
+    assert_eq!(file_list_from_iter_str(["/photos/2021/beach.jpg"]), found);
+    db.get_files(&TagSet::from_str("/docs/2021").unwrap())
+        .for_each(drop);
+
+    let divergence = db.divergence().unwrap();
+    assert_eq!(
+        Operation::GetFiles(Query::new(query.clone())),
+        divergence.operation
+    );
+    assert_eq!(3, divergence.history.len());
+    assert!(divergence
+        .history
+        .iter()
+        .all(|op| matches!(op, Operation::Add(_))));
+    assert!(!divergence.truncated);
+    assert!(divergence
+        .primary
+        .starts_with("1 files, 0 not in the other"));
+    assert!(divergence
+        .secondary
+        .starts_with("2 files, 1 not in the other"));
+    assert!(divergence
+        .to_string()
+        .starts_with("backends diverged on GetFiles("));
+}
+
+#[test]
+#[should_panic(expected = "backends diverged on GetFiles")]
+fn diffdb_should_panic_by_default() {
+    let db = with_photos(DiffDBFS::new(NaiveDBFS::new(), diverging_secondary()));
+    db.get_files(&TagSet::from_str("/photos/2021").unwrap())
+        .for_each(drop);
+}
+
+#[test]
+fn diffdb_should_compare_errors_by_kind() {
+    let mut db = with_photos(DiffDBFS::new(NaiveDBFS::new(), TagTreeDBFS::new()));
+
+    assert!(db
+        .add_file(&File::from_str("/docs/2021/taxes.pdf").unwrap())
+        .is_err());
+    assert!(db.get_file(&TagSet::from_str("/2021").unwrap()).is_err());
+    assert!(db.get_file(&TagSet::from_str("/music").unwrap()).is_err());
+    assert_eq!(None, db.divergence());
+}
+
+#[test]
+fn diffdb_should_limit_the_history() {
+    let db = with_photos(
+        DiffDBFS::new(NaiveDBFS::new(), diverging_secondary())
+            .with_on_divergence(OnDivergence::Record)
+            .with_history_limit(2),
+    );
+    db.get_files(&TagSet::from_str("/photos/2021").unwrap())
+        .for_each(drop);
+
+    let divergence = db.divergence().unwrap();
+    assert_eq!(2, divergence.history.len());
+    assert!(divergence.truncated);
+    assert!(divergence.to_string().contains("(older changes dropped)"));
+}
+
+proptest! {
+    #[test]
+    fn naive_and_tagtree_should_agree(files in collection::vec(arb_tagged_file(), 0..50)) {
+        let mut db = DiffDBFS::new(NaiveDBFS::new(), TagTreeDBFS::new());
+
+        for f in &files {
+            let _ = db.add_file(f);
+        }
+        for f in &files {
+            db.get_files(f.tags()).for_each(drop);
+            let _ = db.get_file(f);
+        }
+        for f in files.iter().step_by(2) {
+            let _ = db.remove_file(f);
+        }
+        db.get_files(&TagSet::new()).for_each(drop);
+    }
+}
@@ -0,0 +1,80 @@
+mod helpers;
+
+use crate::helpers::{add_files_to_db, file_list_from_iter_str, with_photos};
+use rdb_fs::{DotOptions, File, FileDB, TagTreeDBFS};
+
+fn photo_tree() -> TagTreeDBFS {
+    let mut db = with_photos(TagTreeDBFS::new());
+    add_files_to_db(&mut db, file_list_from_iter_str(["/photos/2021/hills.jpg"])).unwrap();
+    db
+}
+
+// Enough files with different tags to make the root split.
+fn big_tree() -> TagTreeDBFS {
+    let mut db = TagTreeDBFS::new();
+    for i in 0..100 {
+        let file = File::new_cloned(&format!("{}.txt", i), [format!("tag{}", i % 50)]);
+        db.add_file(&file).unwrap();
+    }
+    db
+}
+
+#[test]
+fn dot_should_draw_branches_and_end_nodes() {
+    let dot = photo_tree().to_dot(&DotOptions::new());
+
+    assert!(dot.starts_with("digraph tagtree {\n"));
+    assert!(dot.ends_with("}\n"));
+    assert!(dot.contains("[shape=box, label=\"branch\\n"));
+    assert!(dot.contains("label=\"2021, photos\\n2 files\""));
+    assert!(dot.contains("label=\"2020, photos\\n1 files\""));
+    assert!(dot.contains("label=\"2021, docs\\n1 files\""));
+    assert!(dot.contains(" -> "));
+    assert!(!dot.contains("dashed"));
+}
+
+#[test]
+fn dot_should_escape_labels() {
+    let mut db = TagTreeDBFS::new();
+    db.add_file(&File::new_cloned("a", ["say \"hi\"", "back\\slash"]))
+        .unwrap();
+    let dot = db.to_dot(&DotOptions::new());
+
+    assert!(dot.contains("back\\\\slash, say \\\"hi\\\"\\n1 files"));
+}
+
+#[test]
+fn dot_should_stop_at_the_depth_limit() {
+    let db = big_tree();
+    let full = db.to_dot(&DotOptions::new());
+    let shallow = db.to_dot(&DotOptions::new().with_max_depth(0));
+
+    // The root alone, summarising everything.
+    assert!(shallow.contains("n0 [shape=box, style=dashed, label=\""));
+    assert!(shallow.contains("\\n100 files\"];"));
+    assert!(!shallow.contains(" -> "));
+    assert!(shallow.len() < full.len());
+}
+
+#[test]
+fn dot_should_collapse_large_subtrees() {
+    let db = big_tree();
+    let collapsed = db.to_dot(&DotOptions::new().collapse_above(10));
+
+    assert!(collapsed.contains("dashed"));
+    // Never the root, however big.
+    assert!(collapsed.contains("n0 [shape=box, label=\"branch"));
+
+    let dot = db.to_dot(&DotOptions::new().collapse_above(1000));
+    assert!(!dot.contains("dashed"));
+}
+
+#[test]
+fn dot_should_shorten_long_tag_lists() {
+    let mut db = TagTreeDBFS::new();
+    db.add_file(&File::new_cloned("a", ["1", "2", "3", "4", "5"]))
+        .unwrap();
+    let dot = db.to_dot(&DotOptions::new().with_max_label_tags(2));
+
+    assert!(dot.contains("label=\"1, 2, +3 more\\n1 files\""));
+}
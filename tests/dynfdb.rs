@@ -0,0 +1,175 @@
+mod helpers;
+
+use crate::helpers::{file_list_from_iter_str, with_photos};
+use rdb_fs::fromstr::FromStr;
+use rdb_fs::{
+    AddFileError, BackendConfig, Batch, BatchError, BatchOpError, DynFileDB, File, FileDB,
+    FileQuery, GetFileError, Query, QueryOptions, RuleSet, TagHierarchy, TagSet, UnknownBackend,
+    BACKENDS,
+};
+use std::collections::hash_set::HashSet;
+
+// Code that only knows it has some backend.
+fn find(db: &dyn DynFileDB, query: &dyn FileQuery) -> HashSet<File> {
+    db.dyn_get_files(query).collect()
+}
+
+#[test]
+fn every_backend_should_be_built_by_name() {
+    for name in BACKENDS {
+        let db = with_photos(BackendConfig::new(name).build().unwrap());
+
+        let query = TagSet::from_str("/photos").unwrap();
+        assert_eq!(
+            file_list_from_iter_str(["/photos/2021/beach.jpg", "/photos/2020/snow.jpg"]),
+            find(db.as_ref(), &query),
+            "{}",
+            name
+        );
+
+        let taxes = Query::new(TagSet::new()).with_name("taxes.pdf");
+        assert_eq!(
+            File::from_str("/docs/2021/taxes.pdf").unwrap(),
+            db.get_file(&taxes).unwrap(),
+            "{}",
+            name
+        );
+        assert_eq!(1, db.find_by_name("snow.jpg").len(), "{}", name);
+        assert_eq!(
+            2,
+            db.get_page(&query, &QueryOptions::new().with_limit(5))
+                .files
+                .len(),
+            "{}",
+            name
+        );
+        assert_eq!(2, db.explain(&query).files_matched, "{}", name);
+    }
+}
+
+#[test]
+fn dyn_backends_should_remove_and_retag() {
+    for name in BACKENDS {
+        let mut db = with_photos(BackendConfig::new(name).build().unwrap());
+
+        let snow = Query::new(TagSet::new()).with_name("snow.jpg");
+        db.retag_file(&snow, TagSet::from_str("/photos/winter").unwrap())
+            .unwrap();
+        let winter = TagSet::from_str("/winter").unwrap();
+        assert_eq!(
+            file_list_from_iter_str(["/photos/winter/snow.jpg"]),
+            find(db.as_ref(), &winter),
+            "{}",
+            name
+        );
+
+        db.remove_file(&snow).unwrap();
+        assert_eq!(
+            Err(GetFileError::NoSuchFile),
+            db.get_file(&snow),
+            "{}",
+            name
+        );
+    }
+}
+
+#[test]
+fn dyn_backends_should_apply_batches() {
+    let mut rules = RuleSet::new();
+    rules.add_alias("pic", "photos").unwrap();
+    for name in BACKENDS {
+        let mut db = with_photos(
+            BackendConfig::new(name)
+                .with_rules(rules.clone())
+                .build()
+                .unwrap(),
+        );
+
+        let mut batch = Batch::new();
+        batch
+            .add_file(File::from_str("/pic/2022/dunes.jpg").unwrap())
+            .remove_file(Query::new(TagSet::new()).with_name("taxes.pdf"));
+        assert_eq!(
+            Ok(vec![File::from_str("/docs/2021/taxes.pdf").unwrap()]),
+            db.apply_batch(&batch),
+            "{}",
+            name
+        );
+
+        let mut batch = Batch::new();
+        batch
+            .add_file(File::from_str("/photos/2022/cliffs.jpg").unwrap())
+            .add_file(File::from_str("/photos/2020/snow.jpg").unwrap());
+        assert_eq!(
+            Err(BatchError {
+                index: 1,
+                error: BatchOpError::Add(AddFileError::Duplicate)
+            }),
+            db.apply_batch(&batch),
+            "{}",
+            name
+        );
+
+        let query = TagSet::from_str("/photos").unwrap();
+        assert_eq!(
+            file_list_from_iter_str([
+                "/photos/2021/beach.jpg",
+                "/photos/2020/snow.jpg",
+                "/photos/2022/dunes.jpg"
+            ]),
+            find(db.as_ref(), &query),
+            "{}",
+            name
+        );
+    }
+}
+
+#[test]
+fn backend_config_should_reject_unknown_names() {
+    match BackendConfig::new("btree").build() {
+        Err(error) => assert_eq!(UnknownBackend("btree".to_string()), error),
+        Ok(_) => panic!("Expected an unknown backend"),
+    }
+}
+
+#[test]
+fn backend_config_should_apply_hierarchy_and_rules() {
+    let mut rules = RuleSet::new();
+    rules.add_alias("pic", "photo").unwrap();
+    let config = BackendConfig::new("tagtree")
+        .with_hierarchy(TagHierarchy::with_separator('.'))
+        .with_rules(rules);
+    assert_eq!("tagtree", config.name());
+
+    let mut db = config.build().unwrap();
+    db.add_file(&File::new_cloned("cat.png", ["pic", "animals.cats"]))
+        .unwrap();
+
+    let expected = vec![File::new_cloned(
+        "cat.png",
+        ["photo", "animals", "animals.cats"],
+    )];
+    for query in ["/pic", "/animals"] {
+        let query = TagSet::from_str(query).unwrap();
+        assert_eq!(expected, db.get_files(&query).collect::<Vec<_>>());
+    }
+}
+
+// With both traits in scope, as after a glob import, calls on a plain
+// backend still have only one method to go to.
+mod glob_import {
+    use rdb_fs::fromstr::FromStr;
+    use rdb_fs::*;
+
+    #[test]
+    fn plain_backends_should_not_be_ambiguous() {
+        let mut db = NaiveDBFS::new();
+        let file = File::from_str("/photos/beach.jpg").unwrap();
+        db.add_file(&file).unwrap();
+
+        let query = TagSet::from_str("/photos").unwrap();
+        assert_eq!(vec![file.clone()], db.get_files(&query).collect::<Vec<_>>());
+        assert_eq!(Ok(file.clone()), db.get_file(&query));
+        assert_eq!(Ok(file), db.dyn_get_file(&query));
+    }
+}
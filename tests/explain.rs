@@ -0,0 +1,130 @@
+mod helpers;
+
+use crate::helpers::add_files_to_db;
+use rdb_fs::fromstr::FromStr;
+use rdb_fs::{File, FileDB, Query, QueryStats, RuleSet, RulesDBFS, TagSet};
+use rdb_fs::{HashTags2DBFS, HashTagsDBFS, NaiveDBFS, TagTreeDBFS};
+
+// A hundred files in ten disjoint groups of tags, enough to make the tag
+// tree branch.
+fn workload() -> Vec<File> {
+    (0..100)
+        .map(|i| {
+            File::new_cloned(
+                &format!("file{:03}", i),
+                [format!("group{}", i % 10), format!("year={}", 2010 + (i / 10) % 5)],
+            )
+        })
+        .collect()
+}
+
+fn loaded<DB: FileDB>(mut db: DB) -> DB {
+    add_files_to_db(&mut db, workload()).unwrap();
+    db
+}
+
+fn check_matches_get_files<DB: FileDB>(db: &DB, query: &Query) -> QueryStats {
+    let stats = db.explain(query);
+    assert_eq!(db.get_files(query).count(), stats.files_matched);
+    assert_eq!(
+        stats.files_scanned,
+        stats.files_matched + stats.files_rejected
+    );
+    stats
+}
+
+#[test]
+fn explain_should_count_the_files_each_backend_returns() {
+    let queries = [
+        Query::from_str("/group3").unwrap(),
+        Query::from_str("/").unwrap(),
+        Query::from_str("/missing").unwrap(),
+    ];
+    for query in &queries {
+        check_matches_get_files(&loaded(NaiveDBFS::new()), query);
+        check_matches_get_files(&loaded(HashTagsDBFS::new()), query);
+        check_matches_get_files(&loaded(HashTags2DBFS::new()), query);
+        check_matches_get_files(&loaded(TagTreeDBFS::new()), query);
+    }
+}
+
+#[test]
+fn naive_explain_should_scan_every_file() {
+    let db = loaded(NaiveDBFS::new());
+    let stats = check_matches_get_files(&db, &Query::from_str("/group3").unwrap());
+
+    assert_eq!(100, stats.files_scanned);
+    assert_eq!(90, stats.files_rejected);
+    assert_eq!(0, stats.branch_nodes_visited);
+}
+
+#[test]
+fn hashtags_explain_should_count_index_lookups() {
+    let db = loaded(HashTagsDBFS::new());
+    let stats = check_matches_get_files(&db, &Query::from_str("/group3").unwrap());
+
+    assert_eq!(1, stats.index_lookups);
+    assert_eq!(10, stats.index_entries_read);
+    assert_eq!(10, stats.files_scanned);
+
+    let missing = db.explain(&Query::from_str("/group3/missing").unwrap());
+    assert_eq!(2, missing.index_lookups);
+    assert_eq!(0, missing.files_matched);
+}
+
+#[test]
+fn tagtree_explain_should_show_pruning() {
+    let db = loaded(TagTreeDBFS::new());
+    let query = Query::from_str("/group3/year>=2012").unwrap();
+    let stats = check_matches_get_files(&db, &query);
+
+    assert_eq!(6, stats.files_matched);
+    assert!(stats.branch_nodes_visited >= 1);
+    assert!(stats.children_pruned > 0);
+    assert!(stats.pruned_by["group3"] > 0);
+    assert!(stats.pruned_by.contains_key("year"));
+    // Only the leaves holding group3 are looked at.
+    assert!(stats.files_scanned < 100);
+
+    // An empty query prunes nothing, and looks at every file.
+    let all = check_matches_get_files(&db, &Query::new(TagSet::new()));
+    assert_eq!(0, all.children_pruned);
+    assert_eq!(100, all.files_scanned);
+}
+
+#[test]
+fn tagtree_snapshot_should_explain_like_the_db() {
+    let db = loaded(TagTreeDBFS::new());
+    let query = Query::from_str("/group7").unwrap();
+
+    assert_eq!(db.explain(&query), db.snapshot().explain(&query));
+}
+
+#[test]
+fn rulesdb_should_explain_the_rewritten_query() {
+    let mut rules = RuleSet::new();
+    rules.add_alias("g3", "group3").unwrap();
+    let db = loaded(RulesDBFS::new(TagTreeDBFS::new(), rules));
+
+    let stats = db.explain(&Query::from_str("/g3").unwrap());
+    assert_eq!(10, stats.files_matched);
+    assert!(stats.pruned_by.contains_key("group3"));
+}
+
+#[test]
+fn explain_should_only_count_files_checked_against_the_query() {
+    let query = Query::from_str("/group3").unwrap().with_name("file013");
+
+    let naive = check_matches_get_files(&loaded(NaiveDBFS::new()), &query);
+    assert_eq!((100, 1), (naive.files_scanned, naive.files_matched));
+
+    // The hash backends check every file the index finds.
+    let hashtags = check_matches_get_files(&loaded(HashTagsDBFS::new()), &query);
+    assert_eq!((10, 9), (hashtags.files_scanned, hashtags.files_rejected));
+    let hashtags2 = check_matches_get_files(&loaded(HashTags2DBFS::new()), &query);
+    assert_eq!((10, 9), (hashtags2.files_scanned, hashtags2.files_rejected));
+
+    // The tag tree only looks up the name, and only in leaves with group3.
+    let tagtree = check_matches_get_files(&loaded(TagTreeDBFS::new()), &query);
+    assert_eq!((1, 0), (tagtree.files_scanned, tagtree.files_rejected));
+}
@@ -0,0 +1,21 @@
+use proptest::collection;
+use proptest::proptest;
+
+use rdb_fs::fromstr::FromStr;
+use rdb_fs::File;
+
+proptest! {
+    #[test]
+    fn test_file_from_str(ref name in "[^/\\\\\"\n]+", ref tags in collection::btree_set("[^/\\\\\"\n]+", 0..20)) {
+        let path = tags.iter().fold(String::new(), |acc, t| acc + "/" + t) + "/" + name;
+
+        assert_eq!(Ok(File::new_cloned(name, tags)), File::from_str(&path));
+    }
+
+    #[test]
+    fn test_file_display_round_trips(ref name in ".*", ref tags in collection::btree_set(".*", 0..20)) {
+        let file = File::new_cloned(name, tags);
+
+        assert_eq!(Ok(file.clone()), File::from_str(&file.to_string()));
+    }
+}
@@ -0,0 +1,27 @@
+use proptest::collection;
+use proptest::prelude::*;
+
+use rdb_fs::File;
+
+prop_compose! {
+    pub fn arb_file()(ref name in "[^/\n]+", ref tags in collection::btree_set("[^/\n]+", 1..20)) -> File {
+        File::new_cloned(name, tags)
+    }
+}
+
+// Up to four tags from a small alphabet, so files share some of their
+// tags with many others.
+prop_compose! {
+    pub fn arb_tagged_file()(ref name in "[a-d]", ref tags in collection::btree_set("[a-h]", 1..5)) -> File {
+        File::new_cloned(name, tags)
+    }
+}
+
+// Small alphabet with a single tag, so sets of these collide on names and
+// tags often, while never having one file's tags be a subset of another
+// file with the same name.
+prop_compose! {
+    pub fn arb_simple_file()(ref name in "[a-zA-Z0-9_.-]", ref tag in "[a-zA-Z0-9_.-]") -> File {
+        File::new_cloned(name, [tag])
+    }
+}
@@ -2,6 +2,7 @@ mod file_proptest;
 pub(crate) mod hprops;
 
 use rdb_fs::fromstr::FromStr;
+use rdb_fs::AddFileError;
 use rdb_fs::File;
 use rdb_fs::FileDB;
 use std::collections::hash_set::HashSet;
@@ -16,7 +17,7 @@ where
         .collect()
 }
 
-pub(crate) fn add_files_to_db<'a, DB, I>(db: &mut DB, files: I) -> Option<()>
+pub(crate) fn add_files_to_db<DB, I>(db: &mut DB, files: I) -> Result<(), AddFileError>
 where
     DB: FileDB,
     I: IntoIterator<Item = File>,
@@ -24,14 +25,30 @@ where
     for f in files.into_iter() {
         db.add_file(&f)?;
     }
-    Some(())
+    Ok(())
+}
+
+// The small collection many tests start from: two photos and a document,
+// sharing a year.
+pub(crate) fn photos() -> HashSet<File> {
+    file_list_from_iter_str([
+        "/photos/2021/beach.jpg",
+        "/photos/2020/snow.jpg",
+        "/docs/2021/taxes.pdf",
+    ])
+}
+
+// The DB with the photos added.
+pub(crate) fn with_photos<DB: FileDB>(mut db: DB) -> DB {
+    add_files_to_db(&mut db, photos()).unwrap();
+    db
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rdb_fs::{FileQuery, GetFileError};
     use std::collections::hash_set::IntoIter;
-    use rdb_fs::{GetFileError, FileQuery};
 
     pub struct DummyDBFS {
         files: HashSet<File>,
@@ -48,9 +65,13 @@ mod tests {
     impl FileDB for DummyDBFS {
         type FileIterator = IntoIter<File>;
 
-        fn add_file(&mut self, new_file: &File) -> Option<()> {
+        fn add_file(&mut self, new_file: &File) -> Result<(), AddFileError> {
             self.files.insert(new_file.clone());
-            Some(())
+            Ok(())
+        }
+
+        fn remove_file<F: FileQuery>(&mut self, _query: &F) -> Result<File, GetFileError> {
+            Err(GetFileError::NoSuchFile)
         }
 
         fn get_files<F: FileQuery>(&self, _query: &F) -> Self::FileIterator {
@@ -90,7 +111,7 @@ mod tests {
         ];
 
         let mut actual = DummyDBFS::new();
-        add_files_to_db(&mut actual, file_list_from_iter_str(input));
+        add_files_to_db(&mut actual, file_list_from_iter_str(input)).unwrap();
 
         let expected: HashSet<File> = HashSet::from([
             File::new_cloned("make.txt", ["etc", "fine", "shoes"]),
@@ -100,4 +121,9 @@ mod tests {
 
         assert_eq!(expected, actual.files);
     }
+
+    #[test]
+    fn should_add_the_photos() {
+        assert_eq!(photos(), with_photos(DummyDBFS::new()).files);
+    }
 }
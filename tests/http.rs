@@ -0,0 +1,144 @@
+#![cfg(feature = "http")]
+
+mod helpers;
+
+use crate::helpers::{add_files_to_db, file_list_from_iter_str, with_photos};
+use rdb_fs::fromstr::FromStr;
+use rdb_fs::Facet;
+use rdb_fs::File;
+use rdb_fs::HttpClient;
+use rdb_fs::HttpError;
+use rdb_fs::HttpService;
+use rdb_fs::TagTreeDBFS;
+use std::collections::hash_set::HashSet;
+use std::net::TcpListener;
+use std::thread;
+
+// Serve a small photo collection on a thread, and return a client for it.
+fn photo_client() -> HttpClient {
+    let mut db = with_photos(TagTreeDBFS::new());
+    let extra = file_list_from_iter_str(["/photos/2021/hills.jpg", "/photos/2020/beach.jpg"]);
+    add_files_to_db(&mut db, extra).unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let service = HttpService::new(db);
+    thread::spawn(move || service.serve(listener));
+    HttpClient::new(address)
+}
+
+fn file(path: &str) -> File {
+    File::from_str(path).unwrap()
+}
+
+fn status(error: HttpError) -> u16 {
+    match error {
+        HttpError::Status(status, _) => status,
+        e => panic!("expected an error status, got {:?}", e),
+    }
+}
+
+#[test]
+fn http_should_page_through_sorted_files() {
+    let client = photo_client();
+
+    let page = client.get_files("/photos", None, 3).unwrap();
+    assert!(page.next.is_some());
+    let names: Vec<String> = page.files.iter().map(|f| f.name.clone()).collect();
+    assert_eq!(vec!["beach.jpg", "beach.jpg", "hills.jpg"], names);
+
+    let last = client
+        .get_files("/photos", page.next.as_deref(), 3)
+        .unwrap();
+    assert_eq!(None, last.next);
+    assert_eq!(
+        vec![File::from(last.files[0].clone())],
+        vec![file("/photos/2020/snow.jpg")]
+    );
+
+    let all: HashSet<File> = client
+        .get_all_files("/photos")
+        .unwrap()
+        .into_iter()
+        .collect();
+    assert_eq!(
+        file_list_from_iter_str([
+            "/photos/2021/beach.jpg",
+            "/photos/2021/hills.jpg",
+            "/photos/2020/snow.jpg",
+            "/photos/2020/beach.jpg",
+        ]),
+        all
+    );
+}
+
+#[test]
+fn http_should_get_add_and_remove_files() {
+    let client = photo_client();
+
+    assert_eq!(
+        file("/photos/2020/snow.jpg"),
+        client.get_file("/snow.jpg").unwrap()
+    );
+    assert_eq!(404, status(client.get_file("/nothing.jpg").unwrap_err()));
+    match client.get_file("/beach.jpg").unwrap_err() {
+        HttpError::Status(409, body) => assert_eq!(2, body.candidates.len()),
+        e => panic!("expected an ambiguity, got {:?}", e),
+    }
+
+    client
+        .add_file(&file("/photos/2022/city night.jpg"))
+        .unwrap();
+    assert_eq!(
+        409,
+        status(
+            client
+                .add_file(&file("/photos/2022/city night.jpg"))
+                .unwrap_err()
+        )
+    );
+    assert_eq!(
+        file("/photos/2022/city night.jpg"),
+        client.get_file("/2022/city night.jpg").unwrap()
+    );
+
+    assert_eq!(
+        file("/photos/2022/city night.jpg"),
+        client.remove_file("/city night.jpg").unwrap()
+    );
+    assert_eq!(404, status(client.get_file("/city night.jpg").unwrap_err()));
+}
+
+#[test]
+fn http_should_count_and_facet() {
+    let client = photo_client();
+
+    assert_eq!(5, client.count("/").unwrap());
+    assert_eq!(3, client.count("/2021").unwrap());
+    assert_eq!(400, status(client.count("/year>").unwrap_err()));
+
+    let facets = client.facets("/photos", 10).unwrap();
+    assert_eq!(
+        vec![
+            Facet {
+                tag: "2020".to_string(),
+                count: 2
+            },
+            Facet {
+                tag: "2021".to_string(),
+                count: 2
+            },
+        ],
+        facets
+    );
+    assert_eq!(1, client.facets("/", 1).unwrap().len());
+}
+
+#[test]
+fn http_should_refuse_huge_bodies() {
+    let client = photo_client();
+
+    let huge = File::new_cloned(&"x".repeat(2 << 20), ["photos"]);
+    assert_eq!(413, status(client.add_file(&huge).unwrap_err()));
+    assert_eq!(5, client.count("/").unwrap());
+}
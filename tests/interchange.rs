@@ -0,0 +1,148 @@
+mod helpers;
+
+use crate::helpers::{add_files_to_db, file_list_from_iter_str, photos};
+use rdb_fs::{
+    export, import, AddFileError, File, FileDB, FileReader, FileWriter, Format,
+    ImportError, NaiveDBFS, TagSet, TagTreeDBFS,
+};
+use std::collections::hash_set::HashSet;
+use std::fs::File as OSFile;
+use std::io::BufReader;
+
+const FORMATS: [Format; 3] = [Format::Yaml, Format::JsonLines, Format::Csv];
+
+// The photos, plus a file with no tags at all.
+fn photos_and_readme() -> HashSet<File> {
+    let mut files = photos();
+    files.extend(file_list_from_iter_str(["/README"]));
+    files
+}
+
+fn all_files<DB: FileDB>(db: &DB) -> HashSet<File> {
+    db.get_files(&TagSet::new()).collect()
+}
+
+#[test]
+fn every_format_should_round_trip_a_db() {
+    let mut db = TagTreeDBFS::new();
+    add_files_to_db(&mut db, photos_and_readme()).unwrap();
+
+    for format in FORMATS {
+        let mut out = vec![];
+        assert_eq!(4, export(&db, &mut out, format).unwrap());
+
+        let mut copy = NaiveDBFS::new();
+        assert_eq!(4, import(&mut copy, &out[..], format).unwrap(), "{:?}", format);
+        assert_eq!(photos_and_readme(), all_files(&copy), "{:?}", format);
+    }
+}
+
+#[test]
+fn every_format_should_round_trip_an_empty_db() {
+    let expected = ["[]\n", "", "name,tags\r\n"];
+    for (format, expected) in FORMATS.into_iter().zip(expected) {
+        let mut out = vec![];
+        assert_eq!(0, export(&NaiveDBFS::new(), &mut out, format).unwrap());
+        assert_eq!(expected.as_bytes(), &out[..]);
+        assert_eq!(0, import(&mut NaiveDBFS::new(), &out[..], format).unwrap());
+    }
+}
+
+#[test]
+fn every_format_should_keep_awkward_names() {
+    let awkward: HashSet<File> = [
+        File::new_cloned("AC/DC - Back in Black.mp3", ["music", "rock/metal"]),
+        File::new_cloned("notes, \"draft\".txt", ["line\nbreak"]),
+        File::new_cloned(" spaced ", ["a"]),
+        File::new_cloned("\"quoted\"", ["\"", "back\\slash\\"]),
+    ]
+    .into_iter()
+    .collect();
+    let mut db = NaiveDBFS::new();
+    add_files_to_db(&mut db, awkward.clone()).unwrap();
+
+    for format in FORMATS {
+        let mut out = vec![];
+        export(&db, &mut out, format).unwrap();
+        let files: HashSet<File> = FileReader::new(&out[..], format)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(awkward, files, "{:?}", format);
+    }
+}
+
+#[test]
+fn writers_should_produce_the_documented_formats() {
+    let file = File::new_cloned("beach.jpg", ["photos", "2021"]);
+    let write = |format| {
+        let mut writer = FileWriter::new(vec![], format);
+        writer.write(&file).unwrap();
+        String::from_utf8(writer.finish().unwrap()).unwrap()
+    };
+
+    assert_eq!("- /2021/photos/beach.jpg\n", write(Format::Yaml));
+    assert_eq!(
+        "{\"name\":\"beach.jpg\",\"tags\":[\"2021\",\"photos\"]}\n",
+        write(Format::JsonLines)
+    );
+    assert_eq!("name,tags\r\nbeach.jpg,2021,photos\r\n", write(Format::Csv));
+}
+
+#[test]
+fn import_should_report_the_line_of_a_bad_record() {
+    let yaml = "- /a/x\n\n# comment\n- /b\n- /c/\n";
+    match import(&mut NaiveDBFS::new(), yaml.as_bytes(), Format::Yaml) {
+        Err(ImportError::Parse { line, message }) => {
+            assert_eq!(5, line);
+            assert_eq!("\"/c/\" isn't a path to a file: no file name at byte 3", message);
+        }
+        other => panic!("Expected a parse error, got {:?}", other),
+    }
+
+    let yaml = "- /a/x\n- /a//y\n";
+    match import(&mut NaiveDBFS::new(), yaml.as_bytes(), Format::Yaml) {
+        Err(ImportError::Parse { line, .. }) => assert_eq!(2, line),
+        other => panic!("Expected a parse error, got {:?}", other),
+    }
+
+    let jsonl = "{\"name\":\"x\",\"tags\":[\"a\"]}\n{\"name\":\"x\",\"tags\":[\"a\"]}\n";
+    match import(&mut NaiveDBFS::new(), jsonl.as_bytes(), Format::JsonLines) {
+        Err(ImportError::Add { line, file, error }) => {
+            assert_eq!(2, line);
+            assert_eq!(File::new_cloned("x", ["a"]), file);
+            assert_eq!(AddFileError::Duplicate, error);
+        }
+        other => panic!("Expected an add error, got {:?}", other),
+    }
+
+    let csv = "name,tags\r\n\"multi\nline\",a\r\nbad\"quote,b\r\n";
+    match import(&mut NaiveDBFS::new(), csv.as_bytes(), Format::Csv) {
+        Err(ImportError::Parse { line, .. }) => assert_eq!(4, line),
+        other => panic!("Expected a parse error, got {:?}", other),
+    }
+
+    match import(&mut NaiveDBFS::new(), "x,a\r\n".as_bytes(), Format::Csv) {
+        Err(ImportError::Parse { line, message }) => {
+            assert_eq!(1, line);
+            assert!(message.contains("header"));
+        }
+        other => panic!("Expected a parse error, got {:?}", other),
+    }
+}
+
+#[test]
+fn formats_should_be_guessed_from_extensions() {
+    assert_eq!(Some(Format::Yaml), Format::from_path("data/test_files.yml"));
+    assert_eq!(Some(Format::JsonLines), Format::from_path("dump.jsonl"));
+    assert_eq!(Some(Format::Csv), Format::from_path("dump.csv"));
+    assert_eq!(None, Format::from_path("dump"));
+}
+
+#[test]
+fn the_test_data_should_import() {
+    let input = BufReader::new(OSFile::open("data/test_files.yml").unwrap());
+    let files: Vec<File> = FileReader::new(input, Format::Yaml)
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(56143, files.len());
+}
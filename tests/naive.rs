@@ -4,8 +4,13 @@ use crate::helpers::{add_files_to_db, file_list_from_iter_str};
 use rdb_fs::File;
 use rdb_fs::FileDB;
 use rdb_fs::FileQuery;
+use rdb_fs::AddFileError;
+use rdb_fs::Ambiguity;
 use rdb_fs::GetFileError;
 use rdb_fs::NaiveDBFS;
+use rdb_fs::NamePattern;
+use rdb_fs::Query;
+use rdb_fs::TagHierarchy;
 use rdb_fs::TagSet;
 use rdb_fs::fromstr::FromStr;
 use std::collections::hash_set::HashSet;
@@ -20,7 +25,7 @@ fn dbfs_should_find_files() {
         "/mnt/partition/fourteen.one",
     ]);
 
-    add_files_to_db(&mut db, files);
+    add_files_to_db(&mut db, files).unwrap();
 
     let query = TagSet::from_str("/etc/fine").unwrap();
 
@@ -41,7 +46,7 @@ fn dbfs_should_find_files_with_different_query_order() {
         "/mnt/partition/fourteen.one",
     ]);
 
-    add_files_to_db(&mut db, files);
+    add_files_to_db(&mut db, files).unwrap();
 
     let expected_files = HashSet::from_iter([
         File::new_cloned("make.txt", ["etc", "fine", "shoes"]),
@@ -64,7 +69,7 @@ fn dbfs_should_get_empty_list_for_file_that_doesnt_exist() {
         "/mnt/partition/fourteen.one",
     ]);
 
-    add_files_to_db(&mut db, files);
+    add_files_to_db(&mut db, files).unwrap();
 
     let query = TagSet::from_str("/fine/etc/luke.txt").unwrap();
 
@@ -80,7 +85,7 @@ fn dbfs_should_get_file_for_file_that_exists() {
     let mut db = NaiveDBFS::new();
     let files = file_list_from_iter_str(["/etc/fine/shoes/make.txt"]);
 
-    add_files_to_db(&mut db, files);
+    add_files_to_db(&mut db, files).unwrap();
 
     let query = File::from_str("/etc/fine/shoes/make.txt").unwrap();
 
@@ -96,7 +101,7 @@ fn dbfs_should_get_file_with_more_tags_for_file_that_exists() {
     let mut db = NaiveDBFS::new();
     let files = file_list_from_iter_str(["/etc/fine/shoes/make.txt"]);
 
-    add_files_to_db(&mut db, files);
+    add_files_to_db(&mut db, files).unwrap();
 
     let query = File::from_str("/etc/fine/make.txt").unwrap();
 
@@ -117,7 +122,7 @@ fn dbfs_should_get_no_such_file_for_file_that_doesnt_exist() {
         "/mnt/partition/fourteen.one",
     ]);
 
-    add_files_to_db(&mut db, files);
+    add_files_to_db(&mut db, files).unwrap();
 
     let query = File::from_str("/fine/etc/luke.txt").unwrap();
 
@@ -131,13 +136,22 @@ fn dbfs_should_get_too_many_files_for_query_with_multiple_matches() {
     let mut db = NaiveDBFS::new();
     let files = file_list_from_iter_str(["/etc/fine/shoes/make.txt", "/etc/fine/extra/make.txt"]);
 
-    add_files_to_db(&mut db, files);
+    add_files_to_db(&mut db, files).unwrap();
 
     let query = File::from_str("/fine/etc/make.txt").unwrap();
 
     let actual = db.get_file(&query);
 
-    assert_eq!(Err(GetFileError::TooManyFiles), actual);
+    let expected = Ambiguity {
+        candidates: vec![
+            File::from_str("/etc/fine/extra/make.txt").unwrap(),
+            File::from_str("/etc/fine/shoes/make.txt").unwrap(),
+        ],
+        truncated: false,
+        disambiguating_tags: TagSet::from_str("/extra/shoes").unwrap(),
+    };
+
+    assert_eq!(Err(GetFileError::TooManyFiles(expected)), actual);
 }
 
 #[test]
@@ -149,7 +163,7 @@ fn found_files_should_have_all_tags_in_query() {
         "/mnt/partition/fourteen.one",
     ]);
 
-    add_files_to_db(&mut db, files);
+    add_files_to_db(&mut db, files).unwrap();
 
     let query = TagSet::from_str("/fine/etc").unwrap();
 
@@ -161,3 +175,205 @@ fn found_files_should_have_all_tags_in_query() {
         assert!(query.could_match(&i));
     }
 }
+
+#[test]
+fn dbfs_should_find_files_by_structured_tag_range() {
+    let mut db = NaiveDBFS::new();
+    let files = file_list_from_iter_str([
+        "/photos/year=2019/size=2M/old.jpg",
+        "/photos/year=2021/size=12M/big.jpg",
+        "/photos/year=2021/size=1M/small.jpg",
+        "/photos/year=recent/unknown.jpg",
+    ]);
+
+    add_files_to_db(&mut db, files).unwrap();
+
+    let query = Query::from_str("/photos/year>=2020/size<10M").unwrap();
+
+    let expected: HashSet<File> = file_list_from_iter_str(["/photos/year=2021/size=1M/small.jpg"]);
+
+    let actual: HashSet<File> = db.get_files(&query).collect();
+
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn dbfs_should_get_file_by_structured_tag_prefix() {
+    let mut db = NaiveDBFS::new();
+    let files = file_list_from_iter_str([
+        "/photos/camera=canon-eos/a.jpg",
+        "/photos/camera=nikon-d750/b.jpg",
+    ]);
+
+    add_files_to_db(&mut db, files).unwrap();
+
+    let query = Query::from_str("/camera^=canon").unwrap();
+
+    let expected = Ok(File::from_str("/photos/camera=canon-eos/a.jpg").unwrap());
+
+    assert_eq!(expected, db.get_file(&query));
+}
+
+#[test]
+fn dbfs_should_find_files_by_ancestor_tag() {
+    let mut db = NaiveDBFS::with_hierarchy(TagHierarchy::with_separator(':'));
+    let files = file_list_from_iter_str([
+        "/media:photo:raw/holiday.cr2",
+        "/media:video/holiday.mp4",
+        "/docs/notes.txt",
+    ]);
+
+    add_files_to_db(&mut db, files).unwrap();
+
+    let query = TagSet::from_str("/media").unwrap();
+
+    let expected: HashSet<File> = file_list_from_iter_str([
+        "/media/media:photo/media:photo:raw/holiday.cr2",
+        "/media/media:video/holiday.mp4",
+    ]);
+
+    let actual: HashSet<File> = db.get_files(&query).collect();
+
+    assert_eq!(expected, actual);
+
+    let query = File::from_str("/media:photo/holiday.cr2").unwrap();
+
+    assert_eq!(
+        Ok(File::from_str("/media/media:photo/media:photo:raw/holiday.cr2").unwrap()),
+        db.get_file(&query)
+    );
+}
+
+#[test]
+fn dbfs_should_find_files_by_name_glob_and_tags() {
+    let mut db = NaiveDBFS::new();
+    let files = file_list_from_iter_str([
+        "/photos/2021/holiday.jpg",
+        "/photos/2021/holiday.png",
+        "/photos/2020/beach.jpg",
+        "/docs/2021/scan.jpg",
+    ]);
+
+    add_files_to_db(&mut db, files).unwrap();
+
+    let query = Query::new(TagSet::from_str("/photos").unwrap())
+        .with_name_pattern(NamePattern::glob("*.jpg").unwrap());
+
+    let expected: HashSet<File> =
+        file_list_from_iter_str(["/photos/2021/holiday.jpg", "/photos/2020/beach.jpg"]);
+
+    let actual: HashSet<File> = db.get_files(&query).collect();
+
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn dbfs_should_get_file_by_name_regex() {
+    let mut db = NaiveDBFS::new();
+    let files = file_list_from_iter_str([
+        "/photos/img_0001.jpg",
+        "/photos/img_0002.jpg",
+        "/photos/img_final.jpg",
+    ]);
+
+    add_files_to_db(&mut db, files).unwrap();
+
+    let query = Query::new(TagSet::from_str("/photos").unwrap())
+        .with_name_pattern(NamePattern::regex("^img_[a-z]+").unwrap());
+
+    assert_eq!(
+        Ok(File::from_str("/photos/img_final.jpg").unwrap()),
+        db.get_file(&query)
+    );
+
+    let query = Query::new(TagSet::from_str("/photos").unwrap())
+        .with_name_pattern(NamePattern::regex("^img_[0-9]+").unwrap());
+
+    assert!(matches!(
+        db.get_file(&query),
+        Err(GetFileError::TooManyFiles(_))
+    ));
+}
+
+#[test]
+fn dbfs_should_find_files_by_name_whatever_their_tags() {
+    let mut db = NaiveDBFS::new();
+    let files = file_list_from_iter_str([
+        "/home/projects/one/Cargo.toml",
+        "/home/projects/two/Cargo.toml",
+        "/home/projects/two/main.rs",
+    ]);
+
+    add_files_to_db(&mut db, files).unwrap();
+
+    let expected: HashSet<File> = file_list_from_iter_str([
+        "/home/projects/one/Cargo.toml",
+        "/home/projects/two/Cargo.toml",
+    ]);
+
+    let actual: HashSet<File> = db.find_by_name("Cargo.toml").into_iter().collect();
+
+    assert_eq!(expected, actual);
+    assert!(db.find_by_name("lib.rs").is_empty());
+
+    let query = File::from_str("/two/Cargo.toml").unwrap();
+
+    assert_eq!(
+        Ok(File::from_str("/home/projects/two/Cargo.toml").unwrap()),
+        db.get_file(&query)
+    );
+}
+
+#[test]
+fn dbfs_should_reject_duplicate_and_invalid_files() {
+    let mut db = NaiveDBFS::new();
+    let file = File::from_str("/etc/fine/make.txt").unwrap();
+
+    assert_eq!(Ok(()), db.add_file(&file));
+    assert_eq!(Err(AddFileError::Duplicate), db.add_file(&file));
+    assert_eq!(
+        Err(AddFileError::InvalidTag("".to_string())),
+        db.add_file(&File::new_cloned("bad.txt", ["etc", ""]))
+    );
+}
+
+#[test]
+fn dbfs_should_remove_and_retag_files() {
+    let mut db = NaiveDBFS::new();
+    let files = file_list_from_iter_str([
+        "/etc/fine/shoes/make.txt",
+        "/etc/fine/shoes/blue.png",
+        "/mnt/partition/fourteen.one",
+    ]);
+
+    add_files_to_db(&mut db, files).unwrap();
+
+    assert_eq!(
+        Ok(File::from_str("/etc/fine/shoes/make.txt").unwrap()),
+        db.remove_file(&File::from_str("/fine/make.txt").unwrap())
+    );
+    assert_eq!(
+        Err(GetFileError::NoSuchFile),
+        db.remove_file(&File::from_str("/fine/make.txt").unwrap())
+    );
+
+    assert_eq!(
+        Ok(File::from_str("/mnt/partition/fourteen.one").unwrap()),
+        db.retag_file(
+            &File::from_str("/mnt/fourteen.one").unwrap(),
+            TagSet::from_str("/etc/fine/numbers").unwrap()
+        )
+    );
+
+    let query = TagSet::from_str("/etc/fine").unwrap();
+
+    let expected: HashSet<File> = file_list_from_iter_str([
+        "/etc/fine/shoes/blue.png",
+        "/etc/fine/numbers/fourteen.one",
+    ]);
+
+    let actual: HashSet<File> = db.get_files(&query).collect();
+
+    assert_eq!(expected, actual);
+    assert!(db.find_by_name("make.txt").is_empty());
+}
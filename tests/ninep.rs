@@ -0,0 +1,187 @@
+#![cfg(unix)]
+
+mod helpers;
+
+use crate::helpers::with_photos;
+use rdb_fs::fromstr::FromStr;
+use rdb_fs::File;
+use rdb_fs::FileDB;
+use rdb_fs::NinePClient;
+use rdb_fs::NinePError;
+use rdb_fs::NinePServer;
+use rdb_fs::Stat;
+use rdb_fs::TagTreeDBFS;
+use rdb_fs::Vfs;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::os::unix::net::UnixStream;
+use std::thread;
+use std::time::Duration;
+
+fn photo_server() -> NinePServer<TagTreeDBFS> {
+    NinePServer::new(Vfs::new(with_photos(TagTreeDBFS::new())))
+}
+
+// Serve one connection on a thread, and connect a client to it.
+fn connect(server: &NinePServer<TagTreeDBFS>) -> NinePClient<UnixStream> {
+    let (client, served) = UnixStream::pair().unwrap();
+    let server = server.clone();
+    thread::spawn(move || server.serve(served));
+    NinePClient::new(client).unwrap()
+}
+
+fn names(stats: &[Stat]) -> Vec<&str> {
+    stats.iter().map(|s| s.name.as_str()).collect()
+}
+
+fn list<S: std::io::Read + std::io::Write>(
+    client: &mut NinePClient<S>,
+    root: u32,
+    path: &[&str],
+) -> Result<Vec<Stat>, NinePError> {
+    let fid = client.walk(root, path)?;
+    client.open(fid)?;
+    let stats = client.read_dir(fid);
+    client.clunk(fid)?;
+    stats
+}
+
+#[test]
+fn ninep_walk_should_add_tags_to_the_query() {
+    let server = photo_server();
+    let mut client = connect(&server);
+    let root = client.attach().unwrap();
+
+    assert_eq!(
+        vec![
+            "2020",
+            "2021",
+            "beach.jpg",
+            "docs",
+            "photos",
+            "snow.jpg",
+            "taxes.pdf"
+        ],
+        names(&list(&mut client, root, &[]).unwrap())
+    );
+    assert_eq!(
+        vec!["2021", "taxes.pdf"],
+        names(&list(&mut client, root, &["docs", "2021", ".."]).unwrap())
+    );
+    assert_eq!(
+        vec!["beach.jpg"],
+        names(&list(&mut client, root, &["2021", "photos"]).unwrap())
+    );
+
+    let fid = client.walk(root, &["photos", "beach.jpg"]).unwrap();
+    let stat = client.stat(fid).unwrap();
+
+    assert_eq!("beach.jpg", stat.name);
+    assert!(!stat.qid.is_dir());
+
+    assert!(matches!(
+        client.walk(root, &["photos", "music"]),
+        Err(NinePError::Remote(_))
+    ));
+    assert!(matches!(
+        client.walk(root, &["music"]),
+        Err(NinePError::Remote(e)) if e == "file not found"
+    ));
+}
+
+#[test]
+fn ninep_should_read_large_directories_in_pieces() {
+    let mut db = TagTreeDBFS::new();
+    for i in 0..2000 {
+        db.add_file(&File::new_cloned(&format!("file_{:04}.txt", i), ["big"]))
+            .unwrap();
+    }
+    let server = NinePServer::new(Vfs::new(db));
+    let mut client = connect(&server);
+    let root = client.attach().unwrap();
+
+    let stats = list(&mut client, root, &["big"]).unwrap();
+
+    assert_eq!(2000, stats.len());
+    assert_eq!("file_0000.txt", stats[0].name);
+    assert_eq!("file_1999.txt", stats[1999].name);
+}
+
+#[test]
+fn ninep_should_create_rename_and_remove_entries() {
+    let server = photo_server();
+    let mut client = connect(&server);
+    let root = client.attach().unwrap();
+
+    let raw = client.walk(root, &["photos"]).unwrap();
+    assert!(client.create(raw, "raw", true).unwrap().is_dir());
+    let file = client.walk(root, &["photos", "raw"]).unwrap();
+    assert!(!client.create(file, "img.cr2", false).unwrap().is_dir());
+
+    client.rename(file, "img_001.cr2").unwrap();
+    assert_eq!("img_001.cr2", client.stat(file).unwrap().name);
+
+    {
+        let vfs = server.vfs().lock().unwrap();
+        assert_eq!(
+            Ok(File::from_str("/photos/raw/img_001.cr2").unwrap()),
+            vfs.inner()
+                .get_file(&File::from_str("/img_001.cr2").unwrap())
+        );
+    }
+
+    client.remove(file).unwrap();
+    assert!(list(&mut client, root, &["photos", "raw"])
+        .unwrap()
+        .is_empty());
+
+    let dir = client.walk(root, &["photos"]).unwrap();
+    assert!(matches!(
+        client.remove(dir),
+        Err(NinePError::Remote(e)) if e == "is a directory"
+    ));
+}
+
+#[test]
+fn ninep_should_serve_over_tcp() {
+    let server = photo_server();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let serving = server.clone();
+    thread::spawn(move || serving.serve_tcp(listener));
+
+    let mut first = NinePClient::new(TcpStream::connect(address).unwrap()).unwrap();
+    let mut second = NinePClient::new(TcpStream::connect(address).unwrap()).unwrap();
+
+    let root = first.attach().unwrap();
+    let docs = first.walk(root, &["docs"]).unwrap();
+    first.create(docs, "letter.txt", false).unwrap();
+
+    // Both connections share the same database.
+    let root = second.attach().unwrap();
+    assert_eq!(
+        vec!["2021", "letter.txt", "taxes.pdf"],
+        names(&list(&mut second, root, &["docs"]).unwrap())
+    );
+}
+
+#[test]
+fn ninep_should_refuse_a_tiny_message_size() {
+    let server = photo_server();
+    let (mut client, served) = UnixStream::pair().unwrap();
+    thread::spawn(move || server.serve(served));
+    client
+        .set_read_timeout(Some(Duration::from_secs(10)))
+        .unwrap();
+
+    // Tversion, tag NOTAG, msize 16, "9P2000".
+    let mut request = vec![19, 0, 0, 0, 100, 0xff, 0xff, 16, 0, 0, 0, 6, 0];
+    request.extend_from_slice(b"9P2000");
+    client.write_all(&request).unwrap();
+
+    let mut reply = [0; 20];
+    client.read_exact(&mut reply).unwrap();
+    assert_eq!(101, reply[4]);
+    assert_eq!(&[7, 0], &reply[11..13]);
+    assert_eq!(b"unknown", &reply[13..20]);
+}
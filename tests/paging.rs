@@ -0,0 +1,120 @@
+mod helpers;
+
+use crate::helpers::add_files_to_db;
+use rdb_fs::fromstr::FromStr;
+use rdb_fs::{Cursor, File, FileDB, FileQuery, QueryOptions, SortOrder, TagSet};
+use rdb_fs::{HashTags2DBFS, HashTagsDBFS, NaiveDBFS, TagTreeDBFS};
+
+fn many_files() -> Vec<File> {
+    (0..50)
+        .map(|i| {
+            let tags = (0..i % 4).map(|t| format!("t{}", t));
+            File::new_cloned(
+                &format!("file{:02}", i),
+                ["docs".to_string()].into_iter().chain(tags),
+            )
+        })
+        .collect()
+}
+
+// Page through every file under /docs, a page at a time, passing the
+// cursor through its string form as a client would.
+fn paged<DB: FileDB>(db: &DB, options: QueryOptions, limit: usize) -> Vec<File> {
+    let query = TagSet::from_str("/docs").unwrap();
+    let mut files = vec![];
+    let mut options = options.with_limit(limit);
+    loop {
+        let page = db.get_page(&query, &options);
+        assert!(page.files.len() <= limit);
+        files.extend(page.files);
+        match page.next {
+            Some(cursor) => {
+                let cursor = Cursor::from_str(&cursor.to_string()).unwrap();
+                options = options.after(cursor);
+            }
+            None => return files,
+        }
+    }
+}
+
+fn check_paging<DB: FileDB>(mut db: DB) {
+    add_files_to_db(&mut db, many_files()).unwrap();
+
+    let mut by_name = many_files();
+    by_name.sort();
+    assert_eq!(by_name, paged(&db, QueryOptions::new(), 7));
+
+    let by_count = paged(
+        &db,
+        QueryOptions::new()
+            .order_by(SortOrder::TagCount)
+            .descending(),
+        10,
+    );
+    assert_eq!(50, by_count.len());
+    assert_eq!(
+        File::new_cloned("file47", ["docs", "t0", "t1", "t2"]),
+        by_count[0]
+    );
+    assert!(by_count
+        .windows(2)
+        .all(|w| w[0].tags().len() >= w[1].tags().len()));
+
+    let query = TagSet::from_str("/docs").unwrap();
+    let first = db.get_page(&query, &QueryOptions::new().with_limit(3));
+    assert_eq!(by_name[..3].to_vec(), first.files);
+    let all = db.get_page(&query, &QueryOptions::new());
+    assert_eq!(by_name, all.files);
+    assert_eq!(None, all.next);
+}
+
+#[test]
+fn naive_should_page_through_results() {
+    check_paging(NaiveDBFS::new());
+}
+
+#[test]
+fn hashtags_should_page_through_results() {
+    check_paging(HashTagsDBFS::new());
+}
+
+#[test]
+fn hashtags2_should_page_through_results() {
+    check_paging(HashTags2DBFS::new());
+}
+
+#[test]
+fn tagtree_should_page_through_results() {
+    check_paging(TagTreeDBFS::new());
+}
+
+#[test]
+fn paging_should_carry_on_past_removed_files() {
+    let mut db = TagTreeDBFS::new();
+    add_files_to_db(&mut db, many_files()).unwrap();
+    let query = TagSet::from_str("/docs").unwrap();
+
+    let first = db.get_page(&query, &QueryOptions::new().with_limit(5));
+    let cursor = first.next.unwrap();
+    // The last file of the page is gone before the next page is fetched.
+    db.remove_file(first.files.last().unwrap()).unwrap();
+
+    let second = db.get_page(&query, &QueryOptions::new().with_limit(5).after(cursor));
+    assert_eq!(File::new_cloned("file05", ["docs", "t0"]), second.files[0]);
+}
+
+#[test]
+fn tagtree_snapshot_should_page_like_the_db() {
+    let mut db = TagTreeDBFS::new();
+    add_files_to_db(&mut db, many_files()).unwrap();
+    let snapshot = db.snapshot();
+    let query = TagSet::from_str("/docs").unwrap();
+    let options = QueryOptions::new()
+        .order_by(SortOrder::TagCount)
+        .with_limit(4);
+
+    assert_eq!(
+        db.get_page(&query, &options),
+        snapshot.get_page(&query, &options)
+    );
+}
@@ -0,0 +1,186 @@
+mod helpers;
+
+use crate::helpers::{add_files_to_db, file_list_from_iter_str};
+use rdb_fs::fromstr::FromStr;
+use rdb_fs::AddFileError;
+use rdb_fs::File;
+use rdb_fs::FileDB;
+use rdb_fs::FileQuery;
+use rdb_fs::GetFileError;
+use rdb_fs::NaiveDBFS;
+use rdb_fs::RuleSet;
+use rdb_fs::RulesDBFS;
+use rdb_fs::SetRulesError;
+use rdb_fs::TagHierarchy;
+use rdb_fs::TagSet;
+use rdb_fs::TagTreeDBFS;
+use std::collections::hash_set::HashSet;
+
+fn photo_rules() -> RuleSet {
+    let mut rules = RuleSet::new();
+    rules.add_implication("jpg", "image").unwrap();
+    rules.add_alias("pic", "photo").unwrap();
+    rules
+}
+
+#[test]
+fn rulesdb_should_find_files_by_implied_tag() {
+    let mut db = RulesDBFS::new(TagTreeDBFS::new(), photo_rules());
+    let files = file_list_from_iter_str(["/jpg/holiday.jpg", "/png/logo.png"]);
+
+    add_files_to_db(&mut db, files).unwrap();
+
+    let query = TagSet::from_str("/image").unwrap();
+
+    let expected: HashSet<File> = file_list_from_iter_str(["/jpg/image/holiday.jpg"]);
+
+    let actual: HashSet<File> = db.get_files(&query).collect();
+
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn rulesdb_should_normalize_aliases_on_add_and_query() {
+    let mut db = RulesDBFS::new(TagTreeDBFS::new(), photo_rules());
+    let files = file_list_from_iter_str(["/pic/cat.png"]);
+
+    add_files_to_db(&mut db, files).unwrap();
+
+    let expected = Ok(File::from_str("/photo/cat.png").unwrap());
+
+    assert_eq!(expected, db.get_file(&File::from_str("/pic/cat.png").unwrap()));
+    assert_eq!(expected, db.get_file(&File::from_str("/photo/cat.png").unwrap()));
+}
+
+#[test]
+fn rulesdb_should_reapply_changed_rules_to_stored_files() {
+    let mut db = RulesDBFS::new(TagTreeDBFS::new(), photo_rules());
+    let files = file_list_from_iter_str(["/png/logo.png", "/jpg/holiday.jpg"]);
+
+    add_files_to_db(&mut db, files).unwrap();
+
+    let mut rules = photo_rules();
+    rules.add_implication("png", "image").unwrap();
+    assert_eq!(Ok(()), db.set_rules(rules));
+
+    let query = TagSet::from_str("/image").unwrap();
+
+    let expected: HashSet<File> =
+        file_list_from_iter_str(["/jpg/image/holiday.jpg", "/png/image/logo.png"]);
+
+    let actual: HashSet<File> = db.get_files(&query).collect();
+
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn rulesdb_should_forget_removed_files_when_rules_change() {
+    let mut db = RulesDBFS::new(TagTreeDBFS::new(), photo_rules());
+    let files = file_list_from_iter_str(["/jpg/holiday.jpg", "/pic/cat.jpg"]);
+
+    add_files_to_db(&mut db, files).unwrap();
+
+    assert_eq!(
+        Ok(File::from_str("/jpg/image/holiday.jpg").unwrap()),
+        db.remove_file(&File::from_str("/image/holiday.jpg").unwrap())
+    );
+
+    assert_eq!(Ok(()), db.set_rules(photo_rules()));
+    assert!(db.find_by_name("holiday.jpg").is_empty());
+    assert_eq!(1, db.find_by_name("cat.jpg").len());
+}
+
+#[test]
+fn rulesdb_should_keep_the_inner_hierarchy_when_rules_change() {
+    let hierarchy = TagHierarchy::with_separator(':');
+    let mut db = RulesDBFS::new(TagTreeDBFS::with_hierarchy(hierarchy), photo_rules());
+    let files = file_list_from_iter_str(["/place:uk/jpg/holiday.jpg", "/place/jpg/map.jpg"]);
+
+    add_files_to_db(&mut db, files).unwrap();
+
+    assert_eq!(Ok(()), db.set_rules(photo_rules()));
+
+    let query = TagSet::from_str("/place").unwrap();
+
+    let expected: HashSet<File> = file_list_from_iter_str([
+        "/place:uk/place/jpg/image/holiday.jpg",
+        "/place/jpg/image/map.jpg",
+    ]);
+
+    let actual: HashSet<File> = db.get_files(&query).collect();
+
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn rulesdb_should_keep_files_the_new_rules_reject_until_told_to_drop_them() {
+    let mut db = RulesDBFS::new(TagTreeDBFS::new(), RuleSet::new());
+    let cat = |tags: &str| File::from_str(&format!("{}/cat.png", tags)).unwrap();
+
+    // Added in this order, so the pic file is the one rejected.
+    db.add_file(&cat("/photo")).unwrap();
+    db.add_file(&cat("/pic")).unwrap();
+
+    // Both files are "/photo/cat.png" once pic is an alias.
+    let rejected = match db.set_rules(photo_rules()) {
+        Err(SetRulesError::Rejected(rejected)) => rejected,
+        other => panic!("Expected rejected files, got {:?}", other),
+    };
+    assert_eq!(vec![cat("/pic")], rejected);
+    assert_eq!("pic", db.rules().canonical("pic"));
+
+    let actual: HashSet<File> = db.get_files(&TagSet::new()).collect();
+
+    assert_eq!(HashSet::from([cat("/photo"), cat("/pic")]), actual);
+
+    assert_eq!(Ok(rejected), db.set_rules_dropping_rejected(photo_rules()));
+    assert_eq!(vec![cat("/photo")], db.find_by_name("cat.png"));
+
+    // The dropped file is gone for good.
+    assert_eq!(Ok(()), db.set_rules(RuleSet::new()));
+    assert_eq!(vec![cat("/photo")], db.find_by_name("cat.png"));
+}
+
+// A DB that won't give up its files.
+#[derive(Clone)]
+struct Stuck(NaiveDBFS);
+
+impl FileDB for Stuck {
+    type FileIterator = <NaiveDBFS as FileDB>::FileIterator;
+
+    fn add_file(&mut self, new_file: &File) -> Result<(), AddFileError> {
+        self.0.add_file(new_file)
+    }
+
+    fn get_files<F: FileQuery>(&self, query: &F) -> Self::FileIterator {
+        self.0.get_files(query)
+    }
+
+    fn get_file<F: FileQuery>(&self, query: &F) -> Result<File, GetFileError> {
+        self.0.get_file(query)
+    }
+
+    fn remove_file<F: FileQuery>(&mut self, _query: &F) -> Result<File, GetFileError> {
+        Err(GetFileError::NoSuchFile)
+    }
+}
+
+#[test]
+fn rulesdb_should_leave_the_files_alone_if_the_inner_db_cant_be_cleared() {
+    let mut db = RulesDBFS::new(Stuck(NaiveDBFS::new()), photo_rules());
+    let files = file_list_from_iter_str(["/jpg/holiday.jpg"]);
+
+    add_files_to_db(&mut db, files).unwrap();
+
+    assert_eq!(
+        Err(SetRulesError::Clear(
+            File::from_str("/jpg/image/holiday.jpg").unwrap(),
+            GetFileError::NoSuchFile
+        )),
+        db.set_rules(RuleSet::new())
+    );
+    assert_eq!(
+        vec![File::from_str("/jpg/image/holiday.jpg").unwrap()],
+        db.find_by_name("holiday.jpg")
+    );
+}
@@ -0,0 +1,107 @@
+mod helpers;
+
+use crate::helpers::add_files_to_db;
+use rdb_fs::{File, FileDB, FileQuery, HashTags2DBFS, HashTagsDBFS, NaiveDBFS, TagTreeDBFS};
+use std::mem::size_of;
+
+// Two hundred files over forty tag sets.
+fn workload() -> Vec<File> {
+    (0..200)
+        .map(|i| {
+            File::new_cloned(
+                &format!("file{:03}.txt", i),
+                [format!("group{}", i % 8), format!("kind{}", i % 5)],
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn tagtree_stats_should_describe_an_empty_tree() {
+    let stats = TagTreeDBFS::new().stats();
+
+    assert_eq!(1, stats.branches.len());
+    assert_eq!(0, stats.branches[0].fanout);
+    assert_eq!(0, stats.end_nodes);
+    assert_eq!(0, stats.files);
+    assert_eq!(0, stats.distinct_tags);
+    assert_eq!(0, stats.max_depth());
+    assert!(stats.heap_bytes > 0);
+}
+
+#[test]
+fn tagtree_stats_should_add_up() {
+    let mut db = TagTreeDBFS::new();
+    add_files_to_db(&mut db, workload()).unwrap();
+    let stats = db.stats();
+
+    assert_eq!(200, stats.files);
+    assert_eq!(13, stats.distinct_tags);
+    // Files with the same tags usually share an EndNode, but each
+    // EndNode holds a single tag set.
+    assert!(stats.end_nodes >= 40);
+    assert_eq!(
+        200,
+        stats
+            .files_per_end_node
+            .iter()
+            .map(|(files, nodes)| files * nodes)
+            .sum::<usize>()
+    );
+    assert_eq!(
+        stats.end_nodes,
+        stats.end_node_depths.values().sum::<usize>()
+    );
+    assert!(stats.max_depth() >= 2);
+
+    assert_eq!(0, stats.branches[0].depth);
+    for b in &stats.branches {
+        assert!(b.fanout > 0);
+        assert!(b.occupancy() > 0.0 && b.occupancy() <= 1.0);
+    }
+    assert_eq!(stats.heap_bytes, db.memory_usage());
+}
+
+#[test]
+fn tagtree_stats_should_display_a_summary() {
+    let mut db = TagTreeDBFS::new();
+    add_files_to_db(&mut db, workload()).unwrap();
+    let summary = db.stats().to_string();
+
+    assert!(summary.starts_with("branch nodes: "));
+    assert!(summary.contains("distinct tags: 13\n"));
+    assert!(summary.contains("files: 200\n"));
+}
+
+fn check_memory_grows<DB: FileDB>(mut db: DB, memory_usage: fn(&DB) -> usize) -> usize {
+    let empty = memory_usage(&db);
+    add_files_to_db(&mut db, workload()).unwrap();
+    let full = memory_usage(&db);
+
+    // At the very least, every name is stored somewhere, in a String of
+    // its own.
+    let names: usize = workload()
+        .iter()
+        .map(|f| f.name().unwrap().len() + size_of::<String>())
+        .sum();
+    assert!(full >= empty + names, "{} < {} + {}", full, empty, names);
+    full
+}
+
+#[test]
+fn every_backend_should_estimate_its_memory_usage() {
+    let naive = check_memory_grows(NaiveDBFS::new(), NaiveDBFS::memory_usage);
+    let hashtags = check_memory_grows(HashTagsDBFS::new(), HashTagsDBFS::memory_usage);
+    check_memory_grows(HashTags2DBFS::new(), HashTags2DBFS::memory_usage);
+    let tagtree = check_memory_grows(TagTreeDBFS::new(), TagTreeDBFS::memory_usage);
+
+    // HashTagsDBFS keeps a copy of each file for every tag it has.
+    assert!(hashtags > naive);
+
+    // Every branch holds a pointer to each of its 32 children, and each
+    // child, even an empty one, is an allocation with its own Arc counts.
+    let mut db = TagTreeDBFS::new();
+    add_files_to_db(&mut db, workload()).unwrap();
+    let branches = db.stats().branches.len();
+    assert!(tagtree >= branches * 32 * 3 * size_of::<usize>());
+}
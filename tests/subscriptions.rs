@@ -0,0 +1,142 @@
+mod helpers;
+
+use crate::helpers::{add_files_to_db, file_list_from_iter_str};
+use rdb_fs::fromstr::FromStr;
+use rdb_fs::Batch;
+use rdb_fs::File;
+use rdb_fs::FileDB;
+use rdb_fs::FileEvent;
+use rdb_fs::NaiveDBFS;
+use rdb_fs::Query;
+use rdb_fs::SubscribedDBFS;
+use rdb_fs::TagHierarchy;
+use rdb_fs::TagSet;
+use rdb_fs::TagTreeDBFS;
+
+#[test]
+fn subscribers_should_only_hear_about_matching_files() {
+    let mut db = SubscribedDBFS::new(TagTreeDBFS::new());
+    let photos = db.subscribe(TagSet::from_str("/photos").unwrap());
+    let docs = db.subscribe(TagSet::from_str("/docs").unwrap());
+
+    add_files_to_db(
+        &mut db,
+        file_list_from_iter_str(["/photos/2021/beach.jpg", "/docs/notes.txt"]),
+    )
+    .unwrap();
+
+    db.remove_file(&File::from_str("/beach.jpg").unwrap())
+        .unwrap();
+
+    assert_eq!(
+        vec![
+            FileEvent::Added(File::from_str("/photos/2021/beach.jpg").unwrap()),
+            FileEvent::Removed(File::from_str("/photos/2021/beach.jpg").unwrap()),
+        ],
+        photos.try_iter().collect::<Vec<_>>()
+    );
+    assert_eq!(
+        vec![FileEvent::Added(File::from_str("/docs/notes.txt").unwrap())],
+        docs.try_iter().collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn subscribers_should_see_retagged_files_enter_and_leave() {
+    let mut db = SubscribedDBFS::new(NaiveDBFS::new());
+    db.add_file(&File::from_str("/inbox/2021/scan.pdf").unwrap())
+        .unwrap();
+
+    let inbox = db.subscribe(TagSet::from_str("/inbox").unwrap());
+    let archive = db.subscribe(TagSet::from_str("/archive").unwrap());
+    let year = db.subscribe(TagSet::from_str("/2021").unwrap());
+
+    db.retag_file(
+        &File::from_str("/scan.pdf").unwrap(),
+        TagSet::from_str("/archive/2021").unwrap(),
+    )
+    .unwrap();
+
+    let old = File::from_str("/inbox/2021/scan.pdf").unwrap();
+    let new = File::from_str("/archive/2021/scan.pdf").unwrap();
+
+    assert_eq!(Ok(FileEvent::Removed(old.clone())), inbox.try_recv());
+    assert_eq!(Ok(FileEvent::Added(new.clone())), archive.try_recv());
+    assert_eq!(Ok(FileEvent::Retagged { old, new }), year.try_recv());
+}
+
+#[test]
+fn subscribers_should_see_files_as_stored() {
+    let mut db = SubscribedDBFS::new(TagTreeDBFS::with_hierarchy(TagHierarchy::with_separator(
+        ':',
+    )));
+    let media = db.subscribe(TagSet::from_str("/media").unwrap());
+
+    let mut batch = Batch::new();
+    batch
+        .add_file(File::from_str("/media:photo/holiday.jpg").unwrap())
+        .add_file(File::from_str("/docs/notes.txt").unwrap());
+    db.apply_batch(&batch).unwrap();
+
+    assert_eq!(
+        vec![FileEvent::Added(
+            File::from_str("/media/media:photo/holiday.jpg").unwrap()
+        )],
+        media.try_iter().collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn subscribers_should_hear_about_batch_removals() {
+    let mut db = SubscribedDBFS::new(TagTreeDBFS::new());
+    let old = File::from_str("/docs/old.txt").unwrap();
+    db.add_file(&old).unwrap();
+    let docs = db.subscribe(TagSet::from_str("/docs").unwrap());
+
+    let new = File::from_str("/docs/new.txt").unwrap();
+    let mut batch = Batch::new();
+    batch
+        .remove_file(Query::new(TagSet::new()).with_name("old.txt"))
+        .add_file(new.clone());
+    db.apply_batch(&batch).unwrap();
+
+    assert_eq!(
+        vec![FileEvent::Removed(old), FileEvent::Added(new)],
+        docs.try_iter().collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn subscribers_should_be_dropped_with_their_receiver() {
+    let mut db = SubscribedDBFS::new(NaiveDBFS::new());
+    let kept = db.subscribe(TagSet::from_str("/a").unwrap());
+    drop(db.subscribe(TagSet::from_str("/a").unwrap()));
+
+    assert_eq!(2, db.subscriber_count());
+
+    db.add_file(&File::from_str("/a/one.txt").unwrap()).unwrap();
+
+    assert_eq!(1, db.subscriber_count());
+    assert_eq!(
+        Ok(FileEvent::Added(File::from_str("/a/one.txt").unwrap())),
+        kept.try_recv()
+    );
+}
+
+#[test]
+fn failed_changes_should_send_no_events() {
+    let mut db = SubscribedDBFS::new(NaiveDBFS::new());
+    let all = db.subscribe(TagSet::from_str("/a").unwrap());
+
+    let file = File::from_str("/a/one.txt").unwrap();
+    db.add_file(&file).unwrap();
+    assert!(db.add_file(&file).is_err());
+    assert!(db
+        .remove_file(&File::from_str("/a/two.txt").unwrap())
+        .is_err());
+
+    assert_eq!(
+        vec![FileEvent::Added(file)],
+        all.try_iter().collect::<Vec<_>>()
+    );
+}
@@ -3,7 +3,7 @@ use proptest::prelude::*;
 use proptest::proptest;
 
 use rdb_fs::fromstr::FromStr;
-use rdb_fs::TagSet;
+use rdb_fs::{TagPath, TagSet};
 
 prop_compose! {
     pub fn arb_tagset()(ref tags in collection::btree_set("[^/\n]+", 1..100) ) -> TagSet {
@@ -15,16 +15,21 @@ proptest! {
     #[test]
     // We use a set here because we don't support repeated tags, by intention.
     // This is different to traditional filesystems.
-    fn test_from_str(ref first in "/{0,1}", ref raw_tags in collection::btree_set("[^/\n]+", 1..100)) {
+    fn test_from_str(ref first in "/{0,1}", ref raw_tags in collection::btree_set("[^/\\\\\"\n]+", 1..100)) {
         let a = first.clone() + &raw_tags.iter().cloned().collect::<Vec<String>>().join("/");
         let dut = TagSet::from_str(&a);
 
-        assert!(dut.is_some());
+        assert!(dut.is_ok());
 
         let d = dut.unwrap();
 
-        let ntags = a.split('/').filter(|x| *x != "").count();
+        let ntags = a.split('/').filter(|x| !x.is_empty()).count();
 
         assert_eq!(ntags, d.len());
     }
+
+    #[test]
+    fn test_tag_path_round_trips(ref tags in collection::btree_set(".*", 0..20)) {
+        assert_eq!(Ok(tags.clone()), TagSet::from_str(&TagPath(tags).to_string()));
+    }
 }
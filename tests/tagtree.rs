@@ -2,7 +2,12 @@ mod helpers;
 
 use crate::helpers::{add_files_to_db, file_list_from_iter_str};
 use rdb_fs::fromstr::FromStr;
+use rdb_fs::AddFileError;
+use rdb_fs::Ambiguity;
 use rdb_fs::GetFileError;
+use rdb_fs::NamePattern;
+use rdb_fs::Query;
+use rdb_fs::TagHierarchy;
 use rdb_fs::TagSet;
 use rdb_fs::TagTreeDBFS;
 use rdb_fs::{File, FileDB, FileQuery};
@@ -18,7 +23,7 @@ fn tagtree_should_find_files() {
         "/mnt/partition/fourteen.one",
     ]);
 
-    add_files_to_db(&mut db, files);
+    add_files_to_db(&mut db, files).unwrap();
 
     let query = TagSet::from_str("/etc/fine").unwrap();
 
@@ -39,7 +44,7 @@ fn tagtree_should_find_files_with_different_query_order() {
         "/mnt/partition/fourteen.one",
     ]);
 
-    add_files_to_db(&mut db, files);
+    add_files_to_db(&mut db, files).unwrap();
 
     let expected_files = HashSet::from_iter([
         File::new_cloned("make.txt", ["etc", "fine", "shoes"]),
@@ -62,7 +67,7 @@ fn tagtree_should_get_empty_list_for_file_that_doesnt_exist() {
         "/mnt/partition/fourteen.one",
     ]);
 
-    add_files_to_db(&mut db, files);
+    add_files_to_db(&mut db, files).unwrap();
 
     let query = TagSet::from_str("/fine/etc/luke.txt").unwrap();
 
@@ -78,7 +83,7 @@ fn tagtree_should_get_file_for_file_that_exists() {
     let mut db = TagTreeDBFS::new();
     let files = file_list_from_iter_str(["/etc/fine/shoes/make.txt"]);
 
-    add_files_to_db(&mut db, files);
+    add_files_to_db(&mut db, files).unwrap();
 
     let query = File::from_str("/etc/fine/shoes/make.txt").unwrap();
 
@@ -94,7 +99,7 @@ fn tagtree_should_get_file_with_more_tags_for_file_that_exists() {
     let mut db = TagTreeDBFS::new();
     let files = file_list_from_iter_str(["/etc/fine/shoes/make.txt"]);
 
-    add_files_to_db(&mut db, files);
+    add_files_to_db(&mut db, files).unwrap();
 
     let query = File::from_str("/etc/fine/make.txt").unwrap();
 
@@ -115,7 +120,7 @@ fn tagtree_should_get_no_such_file_for_file_that_doesnt_exist() {
         "/mnt/partition/fourteen.one",
     ]);
 
-    add_files_to_db(&mut db, files);
+    add_files_to_db(&mut db, files).unwrap();
 
     let query = File::from_str("/fine/etc/luke.txt").unwrap();
 
@@ -129,13 +134,22 @@ fn tagtree_should_get_too_many_files_for_query_with_multiple_matches() {
     let mut db = TagTreeDBFS::new();
     let files = file_list_from_iter_str(["/etc/fine/shoes/make.txt", "/etc/fine/extra/make.txt"]);
 
-    add_files_to_db(&mut db, files);
+    add_files_to_db(&mut db, files).unwrap();
 
     let query = File::from_str("/fine/etc/make.txt").unwrap();
 
     let actual = db.get_file(&query);
 
-    assert_eq!(Err(GetFileError::TooManyFiles), actual);
+    let expected = Ambiguity {
+        candidates: vec![
+            File::from_str("/etc/fine/extra/make.txt").unwrap(),
+            File::from_str("/etc/fine/shoes/make.txt").unwrap(),
+        ],
+        truncated: false,
+        disambiguating_tags: TagSet::from_str("/extra/shoes").unwrap(),
+    };
+
+    assert_eq!(Err(GetFileError::TooManyFiles(expected)), actual);
 }
 
 #[test]
@@ -147,7 +161,7 @@ fn found_files_should_have_all_tags_in_query() {
         "/mnt/partition/fourteen.one",
     ]);
 
-    add_files_to_db(&mut db, files);
+    add_files_to_db(&mut db, files).unwrap();
 
     let query = TagSet::from_str("/fine/etc").unwrap();
 
@@ -159,3 +173,413 @@ fn found_files_should_have_all_tags_in_query() {
         assert!(query.could_match(&i));
     }
 }
+
+#[test]
+fn tagtree_should_find_files_by_structured_tag_range() {
+    let mut db = TagTreeDBFS::new();
+    let files = file_list_from_iter_str([
+        "/photos/year=2019/size=2M/old.jpg",
+        "/photos/year=2021/size=12M/big.jpg",
+        "/photos/year=2021/size=1M/small.jpg",
+        "/photos/year=recent/unknown.jpg",
+    ]);
+
+    add_files_to_db(&mut db, files).unwrap();
+
+    let query = Query::from_str("/photos/year>=2020/size<10M").unwrap();
+
+    let expected: HashSet<File> = file_list_from_iter_str(["/photos/year=2021/size=1M/small.jpg"]);
+
+    let actual: HashSet<File> = db.get_files(&query).collect();
+
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn tagtree_should_get_file_by_structured_tag_prefix() {
+    let mut db = TagTreeDBFS::new();
+    let files = file_list_from_iter_str([
+        "/photos/camera=canon-eos/a.jpg",
+        "/photos/camera=nikon-d750/b.jpg",
+    ]);
+
+    add_files_to_db(&mut db, files).unwrap();
+
+    let query = Query::from_str("/camera^=canon").unwrap();
+
+    let expected = Ok(File::from_str("/photos/camera=canon-eos/a.jpg").unwrap());
+
+    assert_eq!(expected, db.get_file(&query));
+}
+
+#[test]
+fn tagtree_should_find_files_by_ancestor_tag() {
+    let mut db = TagTreeDBFS::with_hierarchy(TagHierarchy::with_separator(':'));
+    let files = file_list_from_iter_str([
+        "/media:photo:raw/holiday.cr2",
+        "/media:video/holiday.mp4",
+        "/docs/notes.txt",
+    ]);
+
+    add_files_to_db(&mut db, files).unwrap();
+
+    let query = TagSet::from_str("/media").unwrap();
+
+    let expected: HashSet<File> = file_list_from_iter_str([
+        "/media/media:photo/media:photo:raw/holiday.cr2",
+        "/media/media:video/holiday.mp4",
+    ]);
+
+    let actual: HashSet<File> = db.get_files(&query).collect();
+
+    assert_eq!(expected, actual);
+
+    let query = File::from_str("/media:photo/holiday.cr2").unwrap();
+
+    assert_eq!(
+        Ok(File::from_str("/media/media:photo/media:photo:raw/holiday.cr2").unwrap()),
+        db.get_file(&query)
+    );
+}
+
+#[test]
+fn tagtree_should_find_files_by_name_glob_and_tags() {
+    let mut db = TagTreeDBFS::new();
+    let files = file_list_from_iter_str([
+        "/photos/2021/holiday.jpg",
+        "/photos/2021/holiday.png",
+        "/photos/2020/beach.jpg",
+        "/docs/2021/scan.jpg",
+    ]);
+
+    add_files_to_db(&mut db, files).unwrap();
+
+    let query = Query::new(TagSet::from_str("/photos").unwrap())
+        .with_name_pattern(NamePattern::glob("*.jpg").unwrap());
+
+    let expected: HashSet<File> =
+        file_list_from_iter_str(["/photos/2021/holiday.jpg", "/photos/2020/beach.jpg"]);
+
+    let actual: HashSet<File> = db.get_files(&query).collect();
+
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn tagtree_should_get_file_by_name_regex() {
+    let mut db = TagTreeDBFS::new();
+    let files = file_list_from_iter_str([
+        "/photos/img_0001.jpg",
+        "/photos/img_0002.jpg",
+        "/photos/img_final.jpg",
+    ]);
+
+    add_files_to_db(&mut db, files).unwrap();
+
+    let query = Query::new(TagSet::from_str("/photos").unwrap())
+        .with_name_pattern(NamePattern::regex("^img_[a-z]+").unwrap());
+
+    assert_eq!(
+        Ok(File::from_str("/photos/img_final.jpg").unwrap()),
+        db.get_file(&query)
+    );
+
+    let query = Query::new(TagSet::from_str("/photos").unwrap())
+        .with_name_pattern(NamePattern::regex("^img_[0-9]+").unwrap());
+
+    assert!(matches!(
+        db.get_file(&query),
+        Err(GetFileError::TooManyFiles(_))
+    ));
+}
+
+#[test]
+fn tagtree_should_find_files_by_name_whatever_their_tags() {
+    let mut db = TagTreeDBFS::new();
+    let files = file_list_from_iter_str([
+        "/home/projects/one/Cargo.toml",
+        "/home/projects/two/Cargo.toml",
+        "/home/projects/two/main.rs",
+    ]);
+
+    add_files_to_db(&mut db, files).unwrap();
+
+    let expected: HashSet<File> = file_list_from_iter_str([
+        "/home/projects/one/Cargo.toml",
+        "/home/projects/two/Cargo.toml",
+    ]);
+
+    let actual: HashSet<File> = db.find_by_name("Cargo.toml").into_iter().collect();
+
+    assert_eq!(expected, actual);
+    assert!(db.find_by_name("lib.rs").is_empty());
+
+    let query = File::from_str("/two/Cargo.toml").unwrap();
+
+    assert_eq!(
+        Ok(File::from_str("/home/projects/two/Cargo.toml").unwrap()),
+        db.get_file(&query)
+    );
+}
+
+#[test]
+fn tagtree_should_reject_duplicate_and_invalid_files() {
+    let mut db = TagTreeDBFS::new();
+    let file = File::from_str("/etc/fine/make.txt").unwrap();
+
+    assert_eq!(Ok(()), db.add_file(&file));
+    assert_eq!(Err(AddFileError::Duplicate), db.add_file(&file));
+    assert_eq!(
+        Err(AddFileError::InvalidTag("".to_string())),
+        db.add_file(&File::new_cloned("bad.txt", ["etc", ""]))
+    );
+}
+
+#[test]
+fn tagtree_should_remove_and_retag_files() {
+    let mut db = TagTreeDBFS::new();
+    let files = file_list_from_iter_str([
+        "/etc/fine/shoes/make.txt",
+        "/etc/fine/shoes/blue.png",
+        "/mnt/partition/fourteen.one",
+    ]);
+
+    add_files_to_db(&mut db, files).unwrap();
+
+    assert_eq!(
+        Ok(File::from_str("/etc/fine/shoes/make.txt").unwrap()),
+        db.remove_file(&File::from_str("/fine/make.txt").unwrap())
+    );
+    assert_eq!(
+        Err(GetFileError::NoSuchFile),
+        db.remove_file(&File::from_str("/fine/make.txt").unwrap())
+    );
+
+    assert_eq!(
+        Ok(File::from_str("/mnt/partition/fourteen.one").unwrap()),
+        db.retag_file(
+            &File::from_str("/mnt/fourteen.one").unwrap(),
+            TagSet::from_str("/etc/fine/numbers").unwrap()
+        )
+    );
+
+    let query = TagSet::from_str("/etc/fine").unwrap();
+
+    let expected: HashSet<File> = file_list_from_iter_str([
+        "/etc/fine/shoes/blue.png",
+        "/etc/fine/numbers/fourteen.one",
+    ]);
+
+    let actual: HashSet<File> = db.get_files(&query).collect();
+
+    assert_eq!(expected, actual);
+    assert!(db.find_by_name("make.txt").is_empty());
+}
+
+#[test]
+fn tagtree_snapshot_should_not_see_later_changes() {
+    let mut db = TagTreeDBFS::new();
+    let files = file_list_from_iter_str([
+        "/etc/fine/shoes/make.txt",
+        "/etc/fine/shoes/blue.png",
+        "/mnt/partition/fourteen.one",
+    ]);
+
+    add_files_to_db(&mut db, files.clone()).unwrap();
+
+    let snapshot = db.snapshot();
+    assert_eq!(3, snapshot.version());
+
+    db.add_file(&File::from_str("/etc/fine/red.png").unwrap())
+        .unwrap();
+    db.remove_file(&File::from_str("/mnt/fourteen.one").unwrap())
+        .unwrap();
+
+    assert_eq!(5, db.version());
+    assert_eq!(3, snapshot.version());
+
+    let query = TagSet::from_str("/etc/fine").unwrap();
+
+    let expected: HashSet<File> =
+        file_list_from_iter_str(["/etc/fine/shoes/make.txt", "/etc/fine/shoes/blue.png"]);
+
+    let actual: HashSet<File> = snapshot.get_files(&query).collect();
+
+    assert_eq!(expected, actual);
+    assert_eq!(
+        Ok(File::from_str("/mnt/partition/fourteen.one").unwrap()),
+        snapshot.get_file(&File::from_str("/mnt/fourteen.one").unwrap())
+    );
+    assert!(snapshot.find_by_name("red.png").is_empty());
+    assert_eq!(1, db.find_by_name("red.png").len());
+}
+
+#[test]
+fn tagtree_snapshot_should_be_readable_from_another_thread() {
+    let mut db = TagTreeDBFS::new();
+    db.add_file(&File::from_str("/reports/q1.csv").unwrap())
+        .unwrap();
+
+    let snapshot = db.snapshot();
+    let reader = std::thread::spawn(move || {
+        snapshot
+            .get_files(&TagSet::from_str("/reports").unwrap())
+            .count()
+    });
+
+    for i in 0..100 {
+        db.add_file(&File::new_cloned(&format!("{}.csv", i), ["reports", "q2"]))
+            .unwrap();
+    }
+
+    assert_eq!(1, reader.join().unwrap());
+    assert_eq!(
+        101,
+        db.get_files(&TagSet::from_str("/reports").unwrap()).count()
+    );
+}
+
+#[test]
+fn tagtree_should_find_files_whatever_order_they_were_added_in() {
+    let paths = [
+        "/photos/2021/beach.jpg",
+        "/photos/2020/snow.jpg",
+        "/docs/2021/taxes.pdf",
+    ];
+    let orders = [
+        [0, 1, 2],
+        [0, 2, 1],
+        [1, 0, 2],
+        [1, 2, 0],
+        [2, 0, 1],
+        [2, 1, 0],
+    ];
+
+    for order in orders {
+        let mut db = TagTreeDBFS::new();
+        for i in order {
+            db.add_file(&File::from_str(paths[i]).unwrap()).unwrap();
+        }
+
+        let query = TagSet::from_str("/photos/2021").unwrap();
+
+        let expected: HashSet<File> = file_list_from_iter_str(["/photos/2021/beach.jpg"]);
+
+        let actual: HashSet<File> = db.get_files(&query).collect();
+
+        assert_eq!(expected, actual, "added in order {:?}", order);
+    }
+}
+
+#[test]
+fn tagtree_remove_should_keep_other_files_whatever_order_they_were_added_in() {
+    let paths = [
+        "/photos/2021/beach.jpg",
+        "/photos/2020/snow.jpg",
+        "/docs/2021/taxes.pdf",
+    ];
+    let orders = [
+        [0, 1, 2],
+        [0, 2, 1],
+        [1, 0, 2],
+        [1, 2, 0],
+        [2, 0, 1],
+        [2, 1, 0],
+    ];
+
+    for order in orders {
+        let mut db = TagTreeDBFS::new();
+        for i in order {
+            db.add_file(&File::from_str(paths[i]).unwrap()).unwrap();
+        }
+
+        db.remove_file(&File::from_str("/photos/2021/beach.jpg").unwrap())
+            .unwrap();
+
+        let expected: HashSet<File> =
+            file_list_from_iter_str(["/photos/2020/snow.jpg", "/docs/2021/taxes.pdf"]);
+
+        let actual: HashSet<File> = db.get_files(&TagSet::new()).collect();
+
+        assert_eq!(expected, actual, "added in order {:?}", order);
+    }
+}
+
+#[test]
+fn tagtree_shortest_path_should_use_the_fewest_tags() {
+    let mut db = TagTreeDBFS::new();
+    let files = file_list_from_iter_str([
+        "/home/alice/projects/rdbfs/Cargo.toml",
+        "/home/alice/projects/web/Cargo.toml",
+        "/home/bob/projects/rdbfs/Cargo.toml",
+        "/home/alice/projects/rdbfs/README.md",
+        "/etc/hosts",
+    ]);
+    add_files_to_db(&mut db, files).unwrap();
+
+    let shortest = |path: &str| db.shortest_path(&File::from_str(path).unwrap());
+
+    // A unique name needs no tags at all.
+    assert_eq!(
+        Ok(File::from_str("/hosts").unwrap()),
+        shortest("/etc/hosts")
+    );
+    assert_eq!(
+        Ok(File::from_str("/README.md").unwrap()),
+        shortest("/alice/home/projects/rdbfs/README.md")
+    );
+    assert_eq!(
+        Ok(File::from_str("/web/Cargo.toml").unwrap()),
+        shortest("/alice/home/projects/web/Cargo.toml")
+    );
+    assert_eq!(
+        Ok(File::from_str("/bob/Cargo.toml").unwrap()),
+        shortest("/bob/home/projects/rdbfs/Cargo.toml")
+    );
+    // Neither "alice" nor "rdbfs" alone is enough.
+    let alice = File::from_str("/alice/home/projects/rdbfs/Cargo.toml").unwrap();
+    let path = db.shortest_path(&alice).unwrap();
+    assert_eq!(File::from_str("/alice/rdbfs/Cargo.toml").unwrap(), path);
+    assert_eq!(Ok(alice), db.get_file(&path));
+}
+
+#[test]
+fn tagtree_shortest_path_should_fail_as_get_file_does() {
+    let mut db = TagTreeDBFS::new();
+    let files = file_list_from_iter_str(["/a/x", "/a/b/x"]);
+    add_files_to_db(&mut db, files).unwrap();
+
+    assert_eq!(
+        Ok(File::from_str("/b/x").unwrap()),
+        db.shortest_path(&File::from_str("/a/b/x").unwrap())
+    );
+    // Every tag of /a/x is also on /a/b/x.
+    let expected = Ambiguity {
+        candidates: vec![
+            File::from_str("/a/x").unwrap(),
+            File::from_str("/a/b/x").unwrap(),
+        ],
+        truncated: false,
+        disambiguating_tags: TagSet::from_str("/b").unwrap(),
+    };
+    assert_eq!(
+        Err(GetFileError::TooManyFiles(expected)),
+        db.shortest_path(&File::from_str("/a/x").unwrap())
+    );
+    assert_eq!(
+        Err(GetFileError::NoSuchFile),
+        db.shortest_path(&File::from_str("/c/x").unwrap())
+    );
+}
+
+#[test]
+fn tagtree_shortest_path_should_expand_the_hierarchy() {
+    let mut db = TagTreeDBFS::with_hierarchy(TagHierarchy::with_separator(':'));
+    let files = file_list_from_iter_str(["/media:photo:raw/holiday.jpg", "/media:video/holiday.jpg"]);
+    add_files_to_db(&mut db, files).unwrap();
+
+    let raw = File::from_str("/media:photo:raw/holiday.jpg").unwrap();
+    let path = db.shortest_path(&raw).unwrap();
+    assert_eq!(1, path.tags().len());
+    assert_eq!(Ok(db.get_file(&raw).unwrap()), db.get_file(&path));
+}
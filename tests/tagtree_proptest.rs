@@ -1,11 +1,16 @@
 mod helpers;
 
-use crate::helpers::hprops::{arb_file, arb_simple_file};
+use crate::helpers::hprops::{arb_file, arb_simple_file, arb_tagged_file};
 //use crate::helpers::hprops::arb_file;
 use proptest::collection;
 use proptest::proptest;
 
-use rdb_fs::{FileDB, TagTreeDBFS};
+use rdb_fs::{FileDB, FileQuery, GetFileError, TagSet, TagTreeDBFS};
+
+fn assert_invariants(db: &TagTreeDBFS) {
+    let report = db.check_invariants();
+    assert!(report.is_ok(), "{}", report);
+}
 
 proptest! {
     #[test]
@@ -14,9 +19,10 @@ proptest! {
 
         let added = db.add_file(&file);
 
-        assert!(added.is_some());
+        assert!(added.is_ok());
 
         assert_eq!(file, db.get_file(&file).unwrap());
+        assert_invariants(&db);
     }
 }
 
@@ -28,8 +34,44 @@ proptest! {
 
         for f in &file_set {
             let added = db.add_file(f);
-            assert!(added.is_some());
+            assert!(added.is_ok());
+        }
+        assert_invariants(&db);
+
+        for f in &file_set {
+            assert_eq!(f, &db.get_file(f).unwrap());
+        }
+    }
+}
+
+proptest! {
+    #[test]
+    fn test_remove_files(file_set in collection::btree_set(arb_simple_file(), 0..100)) {
+        let mut db = TagTreeDBFS::new();
+
+        for f in &file_set {
+            assert!(db.add_file(f).is_ok());
+        }
+
+        // Remove every other file, then check the rest are still found, and
+        // the removed ones can be added back.
+        let (removed, kept): (Vec<_>, Vec<_>) =
+            file_set.iter().enumerate().partition(|(i, _)| i % 2 == 0);
+
+        for (_, f) in &removed {
+            assert_eq!(f, &&db.remove_file(*f).unwrap());
+            assert!(db.get_file(*f).is_err());
+        }
+        assert_invariants(&db);
+
+        for (_, f) in &kept {
+            assert_eq!(f, &&db.get_file(*f).unwrap());
+        }
+
+        for (_, f) in &removed {
+            assert!(db.add_file(f).is_ok());
         }
+        assert_invariants(&db);
 
         for f in &file_set {
             assert_eq!(f, &db.get_file(f).unwrap());
@@ -37,6 +79,32 @@ proptest! {
     }
 }
 
+proptest! {
+    #[test]
+    // Files with several tags from a small alphabet overlap in every way,
+    // so they take every path through insert_file, and force splits.
+    fn test_invariants_hold(files in collection::vec(arb_tagged_file(), 0..200)) {
+        let mut db = TagTreeDBFS::new();
+
+        let mut added = vec![];
+        for f in &files {
+            if db.add_file(f).is_ok() {
+                added.push(f);
+            }
+        }
+        assert_invariants(&db);
+
+        // A file with more tags than another of the same name makes
+        // removing the other ambiguous; those are left alone.
+        for f in added.iter().step_by(3) {
+            if let Ok(removed) = db.remove_file(*f) {
+                assert_eq!(*f, &removed);
+            }
+        }
+        assert_invariants(&db);
+    }
+}
+
 use rdb_fs::File;
 use std::collections::btree_set::BTreeSet;
 
@@ -103,12 +171,13 @@ fn test_add_files_fail() {
 
     for f in &file_set {
         let added = db.add_file(f);
-        assert!(added.is_some());
+        assert!(added.is_ok());
     }
 
     for f in &file_set {
         assert_eq!(f, &db.get_file(f).unwrap());
     }
+    assert_invariants(&db);
 }
 
 #[test]
@@ -124,10 +193,48 @@ fn tagtree_add_file_with_single_tag_and_multiple_tags() {
 
     for f in &file_set {
         let added = db.add_file(f);
-        assert!(added.is_some());
+        assert!(added.is_ok());
     }
 
     for f in &file_set {
         assert_eq!(f, &db.get_file(f).unwrap());
     }
+    assert_invariants(&db);
+}
+
+// The smallest number of the file's tags that get_file needs to find it,
+// trying every subset.
+fn fewest_tags_by_brute_force(db: &TagTreeDBFS, file: &File) -> Option<usize> {
+    let tags: Vec<&String> = file.tags().iter().collect();
+    (0u32..1 << tags.len())
+        .filter(|bits| {
+            let subset: TagSet = (0..tags.len())
+                .filter(|i| bits & (1 << i) != 0)
+                .map(|i| tags[i].clone())
+                .collect();
+            db.get_file(&File::new(file.name().unwrap().to_string(), subset)).as_ref() == Ok(file)
+        })
+        .map(|bits| bits.count_ones() as usize)
+        .min()
+}
+
+proptest! {
+    #[test]
+    fn test_shortest_path(file_set in collection::btree_set(arb_tagged_file(), 1..40)) {
+        let mut db = TagTreeDBFS::new();
+        for f in &file_set {
+            db.add_file(f).unwrap();
+        }
+
+        for f in &file_set {
+            match (db.shortest_path(f), fewest_tags_by_brute_force(&db, f)) {
+                (Ok(path), Some(fewest)) => {
+                    assert_eq!(fewest, path.tags().len());
+                    assert_eq!(f, &db.get_file(&path).unwrap());
+                }
+                (Err(GetFileError::TooManyFiles(_)), None) => (),
+                (result, fewest) => panic!("{:?} for {:?}, expected {:?} tags", result, f, fewest),
+            }
+        }
+    }
 }
@@ -0,0 +1,311 @@
+mod helpers;
+
+use crate::helpers::{add_files_to_db, file_list_from_iter_str, with_photos};
+use rdb_fs::fromstr::FromStr;
+use rdb_fs::AddFileError;
+use rdb_fs::DirEntry;
+use rdb_fs::EntryKind;
+use rdb_fs::File;
+use rdb_fs::FileDB;
+use rdb_fs::FileQuery;
+use rdb_fs::GetFileError;
+use rdb_fs::HashTagsDBFS;
+use rdb_fs::NaiveDBFS;
+use rdb_fs::TagSet;
+use rdb_fs::TagTreeDBFS;
+use rdb_fs::{Vfs, VfsError, ROOT_INODE};
+use std::collections::hash_set::HashSet;
+
+fn photo_vfs() -> Vfs<TagTreeDBFS> {
+    Vfs::new(with_photos(TagTreeDBFS::new()))
+}
+
+// Refuses to add files with the tag once it has taken room more of them.
+struct Refusing {
+    db: NaiveDBFS,
+    tag: String,
+    room: usize,
+}
+
+impl Refusing {
+    fn photos(tag: &str, room: usize) -> Self {
+        Refusing {
+            db: with_photos(NaiveDBFS::new()),
+            tag: tag.to_string(),
+            room,
+        }
+    }
+}
+
+impl FileDB for Refusing {
+    type FileIterator = <NaiveDBFS as FileDB>::FileIterator;
+
+    fn add_file(&mut self, new_file: &File) -> Result<(), AddFileError> {
+        if new_file.tags().contains(&self.tag) {
+            if self.room == 0 {
+                return Err(AddFileError::Capacity);
+            }
+            self.room -= 1;
+        }
+        self.db.add_file(new_file)
+    }
+
+    fn get_files<F: FileQuery>(&self, query: &F) -> Self::FileIterator {
+        self.db.get_files(query)
+    }
+
+    fn get_file<F: FileQuery>(&self, query: &F) -> Result<File, GetFileError> {
+        self.db.get_file(query)
+    }
+
+    fn remove_file<F: FileQuery>(&mut self, query: &F) -> Result<File, GetFileError> {
+        self.db.remove_file(query)
+    }
+}
+
+fn names(entries: &[DirEntry]) -> Vec<&str> {
+    entries.iter().map(|e| e.name.as_str()).collect()
+}
+
+// Walk a path of names down from the root, returning the last inode.
+fn walk<DB: FileDB>(vfs: &mut Vfs<DB>, path: &[&str]) -> Result<u64, VfsError> {
+    let mut ino = ROOT_INODE;
+    for name in path {
+        ino = vfs.lookup(ino, name)?.ino;
+    }
+    Ok(ino)
+}
+
+#[test]
+fn vfs_should_list_files_and_refinement_tags() {
+    let mut vfs = photo_vfs();
+
+    let root = vfs.readdir(ROOT_INODE).unwrap();
+    assert_eq!(
+        vec![
+            "2020",
+            "2021",
+            "beach.jpg",
+            "docs",
+            "photos",
+            "snow.jpg",
+            "taxes.pdf"
+        ],
+        names(&root)
+    );
+
+    let photos = walk(&mut vfs, &["photos"]).unwrap();
+    let entries = vfs.readdir(photos).unwrap();
+
+    assert_eq!(
+        vec!["2020", "2021", "beach.jpg", "snow.jpg"],
+        names(&entries)
+    );
+    assert_eq!(EntryKind::Directory, entries[0].kind);
+    assert_eq!(EntryKind::File, entries[2].kind);
+}
+
+#[test]
+fn vfs_should_reach_the_same_directory_by_any_tag_order() {
+    let mut vfs = photo_vfs();
+
+    let a = walk(&mut vfs, &["photos", "2021"]).unwrap();
+    let b = walk(&mut vfs, &["2021", "photos"]).unwrap();
+
+    assert_eq!(a, b);
+    assert_eq!(vec!["beach.jpg"], names(&vfs.readdir(a).unwrap()));
+    assert_eq!(
+        walk(&mut vfs, &["beach.jpg"]).unwrap(),
+        walk(&mut vfs, &["2021", "beach.jpg"]).unwrap()
+    );
+}
+
+#[test]
+fn vfs_should_report_missing_and_mistyped_entries() {
+    let mut vfs = photo_vfs();
+
+    assert_eq!(Err(VfsError::NotFound), walk(&mut vfs, &["music"]));
+    assert_eq!(
+        Err(VfsError::NotFound),
+        walk(&mut vfs, &["photos", "photos"])
+    );
+    assert_eq!(Err(VfsError::NotFound), walk(&mut vfs, &["docs", "2020"]));
+
+    let file = walk(&mut vfs, &["beach.jpg"]).unwrap();
+    assert_eq!(EntryKind::File, vfs.getattr(file).unwrap().kind);
+    assert_eq!(Err(VfsError::NotADirectory), vfs.readdir(file));
+    assert_eq!(Err(VfsError::NotFound), vfs.getattr(9999));
+}
+
+#[test]
+fn vfs_should_hide_files_whose_names_clash() {
+    let mut db = NaiveDBFS::new();
+    let files = file_list_from_iter_str(["/a/one/x.txt", "/a/two/x.txt"]);
+    add_files_to_db(&mut db, files).unwrap();
+    let mut vfs = Vfs::new(db);
+
+    let a = walk(&mut vfs, &["a"]).unwrap();
+
+    assert_eq!(vec!["one", "two"], names(&vfs.readdir(a).unwrap()));
+    assert!(matches!(
+        vfs.lookup(a, "x.txt"),
+        Err(VfsError::Ambiguous(_))
+    ));
+    assert!(walk(&mut vfs, &["a", "one", "x.txt"]).is_ok());
+}
+
+#[test]
+fn vfs_should_create_tags_and_files() {
+    let mut vfs = photo_vfs();
+
+    let photos = walk(&mut vfs, &["photos"]).unwrap();
+    let raw = vfs.mkdir(photos, "raw").unwrap();
+
+    assert_eq!(EntryKind::Directory, raw.kind);
+    assert_eq!(raw, vfs.lookup(photos, "raw").unwrap());
+    assert!(vfs.readdir(raw.ino).unwrap().is_empty());
+    assert_eq!(Err(VfsError::Exists), vfs.mkdir(photos, "raw"));
+    assert_eq!(Err(VfsError::Exists), vfs.mkdir(photos, "beach.jpg"));
+    assert_eq!(Err(VfsError::InvalidName), vfs.mkdir(photos, "a/b"));
+
+    let file = vfs.create(raw.ino, "img_001.cr2").unwrap();
+
+    assert_eq!(EntryKind::File, file.kind);
+    assert_eq!(
+        Ok(File::from_str("/photos/raw/img_001.cr2").unwrap()),
+        vfs.inner()
+            .get_file(&File::from_str("/img_001.cr2").unwrap())
+    );
+    let raw = walk(&mut vfs, &["raw"]).unwrap();
+    assert_eq!(
+        vec!["img_001.cr2", "photos"],
+        names(&vfs.readdir(raw).unwrap())
+    );
+}
+
+#[test]
+fn vfs_should_unlink_files() {
+    let mut vfs = photo_vfs();
+
+    let photos = walk(&mut vfs, &["photos"]).unwrap();
+    let beach = vfs.lookup(photos, "beach.jpg").unwrap();
+
+    assert_eq!(Ok(()), vfs.unlink(photos, "beach.jpg"));
+    assert_eq!(Err(VfsError::NotFound), vfs.getattr(beach.ino));
+    assert_eq!(Err(VfsError::NotFound), vfs.unlink(photos, "beach.jpg"));
+    assert_eq!(Err(VfsError::IsADirectory), vfs.unlink(photos, "2020"));
+    assert_eq!(
+        vec!["2020", "snow.jpg"],
+        names(&vfs.readdir(photos).unwrap())
+    );
+}
+
+#[test]
+fn vfs_should_retag_moved_files() {
+    let mut vfs = photo_vfs();
+
+    let photos_2021 = walk(&mut vfs, &["photos", "2021"]).unwrap();
+    let docs = walk(&mut vfs, &["docs"]).unwrap();
+
+    // Moving out of /photos/2021 into /docs swaps those tags for docs.
+    vfs.rename(photos_2021, "beach.jpg", docs, "beach.jpg")
+        .unwrap();
+
+    assert_eq!(
+        Ok(File::from_str("/docs/beach.jpg").unwrap()),
+        vfs.inner().get_file(&File::from_str("/beach.jpg").unwrap())
+    );
+
+    // Moving within a directory renames, and keeps other tags.
+    vfs.rename(ROOT_INODE, "taxes.pdf", ROOT_INODE, "taxes-2021.pdf")
+        .unwrap();
+
+    assert_eq!(
+        Ok(File::from_str("/docs/2021/taxes-2021.pdf").unwrap()),
+        vfs.inner()
+            .get_file(&File::from_str("/taxes-2021.pdf").unwrap())
+    );
+    assert_eq!(
+        Err(VfsError::Exists),
+        vfs.rename(ROOT_INODE, "snow.jpg", ROOT_INODE, "beach.jpg")
+    );
+}
+
+#[test]
+fn vfs_should_retag_every_file_in_a_renamed_directory() {
+    let mut vfs = photo_vfs();
+
+    vfs.rename(ROOT_INODE, "2021", ROOT_INODE, "last-year")
+        .unwrap();
+
+    let query = TagSet::from_str("/last-year").unwrap();
+
+    let expected: HashSet<File> =
+        file_list_from_iter_str(["/photos/last-year/beach.jpg", "/docs/last-year/taxes.pdf"]);
+
+    let actual: HashSet<File> = vfs.inner().get_files(&query).collect();
+
+    assert_eq!(expected, actual);
+    assert_eq!(Err(VfsError::NotFound), walk(&mut vfs, &["2021"]));
+    assert_eq!(
+        Err(VfsError::Exists),
+        vfs.rename(ROOT_INODE, "photos", ROOT_INODE, "docs")
+    );
+}
+
+#[test]
+fn vfs_should_report_a_file_lost_in_a_move() {
+    // Neither the moved file nor the original can be added back.
+    let mut vfs = Vfs::new(Refusing::photos("2021", 0));
+
+    let photos = walk(&mut vfs, &["photos"]).unwrap();
+    let docs = walk(&mut vfs, &["docs"]).unwrap();
+
+    assert_eq!(
+        Err(VfsError::Lost {
+            file: File::from_str("/photos/2021/beach.jpg").unwrap(),
+            retag: AddFileError::Capacity,
+            restore: AddFileError::Capacity,
+        }),
+        vfs.rename(photos, "beach.jpg", docs, "beach.jpg")
+    );
+    assert!(vfs.inner().find_by_name("beach.jpg").is_empty());
+}
+
+#[test]
+fn vfs_should_move_nothing_if_a_directory_rename_fails() {
+    // Only one file can be moved into last-year.
+    let mut vfs = Vfs::new(Refusing::photos("last-year", 1));
+
+    assert_eq!(
+        Err(VfsError::Add(AddFileError::Capacity)),
+        vfs.rename(ROOT_INODE, "2021", ROOT_INODE, "last-year")
+    );
+
+    let query = TagSet::from_str("/2021").unwrap();
+
+    let expected: HashSet<File> =
+        file_list_from_iter_str(["/photos/2021/beach.jpg", "/docs/2021/taxes.pdf"]);
+
+    let actual: HashSet<File> = vfs.inner().get_files(&query).collect();
+
+    assert_eq!(expected, actual);
+    assert_eq!(Err(VfsError::NotFound), walk(&mut vfs, &["last-year"]));
+}
+
+#[test]
+fn vfs_should_list_the_root_of_a_hash_backend() {
+    let mut db = HashTagsDBFS::new();
+    add_files_to_db(&mut db, file_list_from_iter_str(["/a/one.txt"])).unwrap();
+    let mut vfs = Vfs::new(db);
+
+    assert_eq!(
+        vec!["a", "one.txt"],
+        names(&vfs.readdir(ROOT_INODE).unwrap())
+    );
+    assert!(vfs.create(ROOT_INODE, "two.txt").is_ok());
+    assert_eq!(
+        vec!["a", "one.txt", "two.txt"],
+        names(&vfs.readdir(ROOT_INODE).unwrap())
+    );
+}
@@ -0,0 +1,110 @@
+mod helpers;
+
+use crate::helpers::add_files_to_db;
+use rdb_fs::{
+    File, FileDB, FileQuery, HashTags2DBFS, HashTagsDBFS, NaiveDBFS, QueryMix, TagTreeDBFS,
+    TagsPerFile, Workload,
+};
+use std::collections::btree_map::BTreeMap;
+use std::collections::hash_set::HashSet;
+
+#[test]
+fn workload_should_be_reproducible() {
+    let workload = Workload::new(200).with_seed(42);
+    let files = workload.generate_files();
+
+    assert_eq!(200, files.len());
+    assert_eq!(files, workload.generate_files());
+    assert_ne!(files, workload.clone().with_seed(43).generate_files());
+
+    let mix = QueryMix::default();
+    assert_eq!(
+        workload.generate_queries(&files, 50, &mix),
+        workload.generate_queries(&files, 50, &mix)
+    );
+}
+
+#[test]
+fn workload_files_should_fit_in_every_backend() {
+    let files = Workload::new(500)
+        .with_vocabulary(20)
+        .with_name_collisions(0.3)
+        .generate_files();
+
+    let distinct: HashSet<&File> = files.iter().collect();
+    assert_eq!(files.len(), distinct.len());
+    let names: HashSet<Option<&str>> = files.iter().map(|f| f.name()).collect();
+    assert!(names.len() < files.len() * 9 / 10, "{} names", names.len());
+
+    add_files_to_db(&mut NaiveDBFS::new(), files.clone()).unwrap();
+    add_files_to_db(&mut HashTagsDBFS::new(), files.clone()).unwrap();
+    add_files_to_db(&mut HashTags2DBFS::new(), files.clone()).unwrap();
+    add_files_to_db(&mut TagTreeDBFS::new(), files).unwrap();
+}
+
+#[test]
+fn workload_should_follow_the_tag_distribution() {
+    let files = Workload::new(2000)
+        .with_vocabulary(50)
+        .with_tags_per_file(TagsPerFile::Fixed(3))
+        .generate_files();
+    assert!(files.iter().all(|f| f.tags().len() == 3));
+
+    let mut popularity: BTreeMap<&str, usize> = BTreeMap::new();
+    for f in &files {
+        for t in f.tags() {
+            *popularity.entry(t).or_default() += 1;
+        }
+    }
+    assert!(popularity["tag0"] > 3 * popularity["tag20"], "{:?}", popularity);
+
+    let flat = Workload::new(2000)
+        .with_zipf_exponent(0.0)
+        .with_tags_per_file(TagsPerFile::Uniform(2, 4))
+        .generate_files();
+    assert!(flat.iter().all(|f| (2..=4).contains(&f.tags().len())));
+    let mean = flat.iter().map(|f| f.tags().len()).sum::<usize>() as f64 / 2000.0;
+    assert!((2.8..3.2).contains(&mean), "{}", mean);
+
+    let tail = Workload::new(2000)
+        .with_tags_per_file(TagsPerFile::Geometric(2.0))
+        .generate_files();
+    assert!(tail.iter().all(|f| !f.tags().is_empty()));
+    assert!(tail.iter().any(|f| f.tags().len() >= 5));
+}
+
+#[test]
+fn workload_queries_should_follow_the_mix() {
+    let workload = Workload::new(300);
+    let files = workload.generate_files();
+    let mut db = NaiveDBFS::new();
+    add_files_to_db(&mut db, files.clone()).unwrap();
+
+    let hits = QueryMix {
+        popular_tag: 0,
+        file_tags: 1,
+        name: 1,
+        miss: 0,
+    };
+    let queries = workload.generate_queries(&files, 100, &hits);
+    assert_eq!(100, queries.len());
+    assert!(queries.iter().any(|q| q.name().is_some()));
+    assert!(queries.iter().any(|q| !q.tags().is_empty()));
+    for q in &queries {
+        assert!(db.get_files(q).count() > 0, "{:?}", q);
+    }
+
+    let misses = QueryMix {
+        popular_tag: 0,
+        file_tags: 0,
+        name: 0,
+        miss: 1,
+    };
+    for q in &workload.generate_queries(&files, 100, &misses) {
+        assert_eq!(0, db.get_files(q).count(), "{:?}", q);
+    }
+
+    assert!(workload
+        .generate_queries(&[], 10, &QueryMix::default())
+        .is_empty());
+}